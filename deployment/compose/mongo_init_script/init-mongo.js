@@ -215,6 +215,7 @@ db.openers.insert({
   updatedAt: now,
   version: "1.0.2",
   nonce: "jdfjksdhfjshfkjsdhkfhk",
+  secret: "5f1d7a3c9e2b4f60a8c1d3e5f7092b4d6e8fa1c3b5d7e9f0a2c4e6b8d0f1a3c5",
   commandStatus: "READY"
 });
 
@@ -278,3 +279,70 @@ db.barrierManufacturers.updateOne({name: "Came"}, {$set: {modelIds: [gard4040["_
 let vasya = db.users.findOne({login: "vasya"});
 
 db.openers.updateOne({serialNumber: "111"}, {$set: {userId: vasya["_id"]}});
+
+db.createCollection("commands");
+db.commands.createIndex(
+  { serialNumber: 1, _id: -1 },
+  { name: "serialNumber" }
+);
+
+db.createCollection("events");
+db.events.createIndex(
+  { serialNumber: 1, _id: -1 },
+  { name: "serialNumber" }
+);
+db.events.createIndex(
+  { createdAt: 1 },
+  { name: "createdAt", expireAfterSeconds: 2592000 }
+);
+
+db.createCollection("telemetry", {
+  timeseries: { timeField: "createdAt", metaField: "serialNumber", granularity: "minutes" },
+  expireAfterSeconds: 1209600
+});
+
+db.createCollection("authFailures");
+db.authFailures.createIndex(
+  { serialNumber: 1, createdAt: -1 },
+  { name: "serialNumber" }
+);
+db.authFailures.createIndex(
+  { createdAt: 1 },
+  { name: "createdAt", expireAfterSeconds: 86400 }
+);
+
+db.createCollection("clusterSessions");
+db.clusterSessions.createIndex(
+  { updatedAt: 1 },
+  { name: "updatedAt", expireAfterSeconds: 90 }
+);
+
+db.createCollection("clusterMessages");
+db.clusterMessages.createIndex(
+  { createdAt: 1 },
+  { name: "createdAt", expireAfterSeconds: 3600 }
+);
+db.clusterMessages.createIndex(
+  { sender: 1, _id: 1 },
+  { name: "sender" }
+);
+
+db.createCollection("schedules");
+db.schedules.createIndex(
+  { serialNumber: 1 },
+  { name: "serialNumber" }
+);
+db.schedules.createIndex(
+  { enabled: 1, nextRunAt: 1 },
+  { name: "nextRunAt" }
+);
+
+db.createCollection("scheduleRuns");
+db.scheduleRuns.createIndex(
+  { scheduleId: 1, _id: -1 },
+  { name: "scheduleId" }
+);
+db.scheduleRuns.createIndex(
+  { createdAt: 1 },
+  { name: "createdAt", expireAfterSeconds: 7776000 }
+);
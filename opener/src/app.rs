@@ -64,7 +64,7 @@ impl App {
             return Err(AppError::SignalsAlreadyRegistered);
         }
 
-        let signals = Signals::new(&[SIGINT, SIGTERM]).unwrap();
+        let signals = Signals::new([SIGINT, SIGTERM]).unwrap();
         let handle = signals.handle();
 
         let (twx, rwx) = watch::channel("work");
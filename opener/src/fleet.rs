@@ -0,0 +1,214 @@
+//! Fleet of simulated controllers for load testing and demo environments
+
+use anyhow::{anyhow, bail, Context, Result};
+use futures_util::future::join_all;
+use std::fs;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::time::{sleep, Duration};
+
+use crate::stats::Stats;
+use crate::ws_client::WSClient;
+use crate::Config;
+
+/// Number in serial number pattern, e.g. `SIM-{n}` or `SIM-{n:4}` padded with zeros
+const NUMBER_PLACEHOLDER: &str = "{n";
+
+/// Credentials of one controller of fleet
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Device {
+    pub serial_number: String,
+    pub model: String,
+    pub login: String,
+    pub password: String,
+    pub secret: String,
+}
+
+/// Controllers of fleet, they connect one by one with `stagger` delay
+pub struct FleetConfig {
+    pub devices: Vec<Device>,
+    pub stagger: Duration,
+}
+
+/// Makes `count` controllers from serial number pattern, they share other settings
+pub fn from_pattern(config: &Config, count: u32) -> Result<Vec<Device>> {
+    let pattern = &config.serial_number;
+
+    let start = pattern
+        .find(NUMBER_PLACEHOLDER)
+        .ok_or_else(|| anyhow!("Serial number pattern must contain {{n}}"))?;
+    let end = start
+        + pattern[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("Serial number pattern has unclosed {{n"))?;
+
+    let width = match &pattern[start + NUMBER_PLACEHOLDER.len()..end] {
+        "" => 0,
+        width => width
+            .strip_prefix(':')
+            .and_then(|w| w.parse::<usize>().ok())
+            .ok_or_else(|| anyhow!("Invalid width of number in {}", pattern))?,
+    };
+
+    Ok((1..=count)
+        .map(|n| Device {
+            serial_number: format!(
+                "{}{:0width$}{}",
+                &pattern[..start],
+                n,
+                &pattern[end + 1..],
+                width = width
+            ),
+            model: config.model.clone(),
+            login: config.login.clone(),
+            password: config.password.clone(),
+            secret: config.secret.clone(),
+        })
+        .collect())
+}
+
+/// Reads controllers from CSV lines `serial,model,login,password,secret`,
+/// header, empty lines and lines starting with `#` are skipped
+pub fn from_csv(content: &str) -> Result<Vec<Device>> {
+    let mut devices = Vec::new();
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') || (i == 0 && line.starts_with("serial")) {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+
+        match fields.as_slice() {
+            [serial_number, model, login, password, secret] if !serial_number.is_empty() => devices
+                .push(Device {
+                    serial_number: serial_number.to_string(),
+                    model: model.to_string(),
+                    login: login.to_string(),
+                    password: password.to_string(),
+                    secret: secret.to_string(),
+                }),
+            _ => bail!(
+                "Line {}: expected serial,model,login,password,secret",
+                i + 1
+            ),
+        }
+    }
+
+    if devices.is_empty() {
+        bail!("No controllers in CSV");
+    }
+
+    Ok(devices)
+}
+
+pub fn read_csv(path: &str) -> Result<Vec<Device>> {
+    let content = fs::read_to_string(path).with_context(|| format!("Can't read {}", path))?;
+
+    from_csv(&content).with_context(|| format!("Invalid CSV {}", path))
+}
+
+/// Runs every controller with its own session until stop signal and prints summary
+pub async fn run(
+    config: &Config,
+    fleet: FleetConfig,
+    stop: watch::Receiver<&'static str>,
+) -> Result<()> {
+    let mut clients = Vec::with_capacity(fleet.devices.len());
+
+    for device in fleet.devices {
+        let config = Config {
+            serial_number: device.serial_number,
+            model: device.model,
+            login: device.login,
+            password: device.password,
+            secret: device.secret,
+            ..config.clone()
+        };
+
+        clients.push(Arc::new(WSClient::new(&config)?));
+    }
+
+    log::info!(
+        "Starting fleet of {} controllers with {} ms between connects",
+        clients.len(),
+        fleet.stagger.as_millis()
+    );
+
+    let tasks = clients.iter().enumerate().map(|(i, client)| {
+        let client = client.clone();
+        let mut stop = stop.clone();
+        let delay = fleet.stagger * i as u32;
+
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = sleep(delay) => {},
+                _ = stop.changed() => return Ok(()),
+            }
+
+            client.run(stop).await
+        })
+    });
+
+    let results = join_all(tasks).await;
+
+    let mut failed = Vec::new();
+
+    for (client, result) in clients.iter().zip(results) {
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => failed.push(format!("{}: {}", client.serial_number(), e)),
+            Err(e) => failed.push(format!("{}: {}", client.serial_number(), e)),
+        }
+    }
+
+    print_summary(&clients, &failed);
+
+    Ok(())
+}
+
+fn print_summary(clients: &[Arc<WSClient>], failed: &[String]) {
+    let stats: Vec<Arc<Stats>> = clients.iter().map(|c| c.stats()).collect();
+
+    let total = |counter: fn(&Stats) -> &std::sync::atomic::AtomicU64| -> u64 {
+        stats.iter().map(|s| Stats::get(counter(s))).sum()
+    };
+
+    let never_accepted: Vec<&str> = clients
+        .iter()
+        .zip(&stats)
+        .filter(|(_, s)| Stats::get(&s.sessions) == 0)
+        .map(|(c, _)| c.serial_number())
+        .collect();
+
+    println!("Fleet summary");
+    println!("  controllers:          {}", clients.len());
+    println!("  connects:             {}", total(|s| &s.connects));
+    println!(
+        "  connection failures:  {}",
+        total(|s| &s.connection_failures)
+    );
+    println!("  sessions accepted:    {}", total(|s| &s.sessions));
+    println!("  disconnects:          {}", total(|s| &s.disconnects));
+    println!("  commands:             {}", total(|s| &s.commands));
+    println!("  failed commands:      {}", total(|s| &s.failed_commands));
+    println!(
+        "  rejected messages:    {}",
+        total(|s| &s.rejected_messages)
+    );
+    println!("  never accepted:       {}", never_accepted.len());
+
+    for serial_number in never_accepted.iter().take(10) {
+        println!("    {}", serial_number);
+    }
+
+    if never_accepted.len() > 10 {
+        println!("    ... and {} more", never_accepted.len() - 10);
+    }
+
+    for failure in failed {
+        println!("  stopped with error: {}", failure);
+    }
+}
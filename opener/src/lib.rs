@@ -1,34 +1,60 @@
 #[macro_use]
 extern crate lazy_static;
 
+use acs_protocol::Encoding;
 use anyhow::Result;
 
 mod app;
+pub mod fleet;
+mod stats;
 mod ws_client;
 
+/// Settings of simulated controller
+#[derive(Clone)]
+pub struct Config {
+    /// Address of service: <ip> | <name>
+    pub address: String,
+    pub port: u16,
+    pub serial_number: String,
+    /// Id of barrier model for controller
+    pub model: String,
+    /// Login for access to controller from service
+    pub login: String,
+    /// Password for access to controller from service
+    pub password: String,
+    /// Secret of controller issued by createOpener
+    pub secret: String,
+    /// Encoding of messages requested from service
+    pub encoding: Encoding,
+    /// Interval in seconds of sample events, they are not sent if it is absent
+    pub events: Option<u64>,
+    /// Connect to service with `wss://`
+    pub tls: bool,
+    /// CA certificate in PEM to verify service instead of public roots
+    pub ca: Option<String>,
+    /// Client certificate in PEM, its CN must be serial number of controller
+    pub cert: Option<String>,
+    /// Private key of client certificate in PEM
+    pub key: Option<String>,
+}
+
 /// Runs the application
-pub async fn run(
-    address: &str,
-    port: u16,
-    serial_number: &str,
-    model: &str,
-    login: &str,
-    password: &str,
-) {
-    let res = run_internal(address, port, serial_number, model, login, password).await;
+pub async fn run(config: Config) {
+    let res = run_internal(config, None).await;
+    if let Err(e) = res {
+        log::error!("{}", e.to_string());
+    }
+}
+
+/// Runs fleet of controllers, settings of `config` are shared by controllers
+pub async fn run_fleet(config: Config, fleet: fleet::FleetConfig) {
+    let res = run_internal(config, Some(fleet)).await;
     if let Err(e) = res {
         log::error!("{}", e.to_string());
     }
 }
 
-async fn run_internal(
-    address: &str,
-    port: u16,
-    serial_number: &str,
-    model: &str,
-    login: &str,
-    password: &str,
-) -> Result<()> {
+async fn run_internal(config: Config, fleet: Option<fleet::FleetConfig>) -> Result<()> {
     log::info!("Opener started");
 
     let a = app::App::instance();
@@ -39,8 +65,13 @@ async fn run_internal(
 
     log::info!("Starting ws client");
 
-    let client = ws_client::WSClient::new(address, port, serial_number, model, login, password);
-    client.run(signals_rx).await?;
+    match fleet {
+        Some(fleet) => fleet::run(&config, fleet, signals_rx).await?,
+        None => {
+            let client = ws_client::WSClient::new(&config)?;
+            client.run(signals_rx).await?;
+        }
+    }
 
     log::info!("WS client is stopped");
 
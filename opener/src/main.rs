@@ -1,5 +1,8 @@
+use acs_protocol::Encoding;
 use clap::Parser;
 use env_logger::Env;
+use opener::fleet;
+use std::time::Duration;
 
 /// Client program for opener controller
 #[derive(Parser, Debug)]
@@ -10,39 +13,122 @@ struct Args {
     address: String,
 
     /// Port of service
-    #[clap(short, long, default_value_t = 4000)]
+    #[clap(short, long, default_value_t = 8765)]
     port: u16,
 
-    /// Serial number of controller
-    #[clap(short, long, value_parser)]
-    serial: String,
+    /// Serial number of controller, with --count it is pattern where {n} or {n:<width>}
+    /// is replaced by number of controller, e.g. SIM-{n:4}
+    #[clap(short, long, value_parser, required_unless_present = "csv")]
+    serial: Option<String>,
 
     /// Id of barrier model for controller
-    #[clap(short, long, value_parser)]
-    model: String,
+    #[clap(short, long, value_parser, required_unless_present = "csv")]
+    model: Option<String>,
 
     /// Login for access to controller from service
-    #[clap(short, long, value_parser)]
-    login: String,
+    #[clap(short, long, value_parser, required_unless_present = "csv")]
+    login: Option<String>,
 
     /// Password for access to controller from service
+    #[clap(long, value_parser, required_unless_present = "csv")]
+    password: Option<String>,
+
+    /// Secret of controller issued by createOpener to authenticate in service
+    #[clap(long, value_parser, required_unless_present = "csv")]
+    secret: Option<String>,
+
+    /// Encoding of messages: json | cbor | msgpack
+    #[clap(long, default_value = "json", value_parser = parse_encoding)]
+    encoding: Encoding,
+
+    /// Send random sample event with this interval in seconds
+    #[clap(long, value_parser)]
+    events: Option<u64>,
+
+    /// Connect with TLS (wss://), it is implied by --ca and --cert
+    #[clap(long)]
+    tls: bool,
+
+    /// CA certificate in PEM to verify service, e.g. self-signed one
+    #[clap(long, value_parser)]
+    ca: Option<String>,
+
+    /// Client certificate in PEM for services requiring it, CN must be serial number
+    #[clap(long, value_parser, requires = "key")]
+    cert: Option<String>,
+
+    /// Private key of client certificate in PEM
+    #[clap(long, value_parser, requires = "cert")]
+    key: Option<String>,
+
+    /// Simulate fleet of controllers with serial numbers made by --serial pattern
+    #[clap(long, value_parser, conflicts_with = "csv")]
+    count: Option<u32>,
+
+    /// Simulate fleet of controllers from CSV file with lines serial,model,login,password,secret
     #[clap(long, value_parser)]
-    password: String,
+    csv: Option<String>,
+
+    /// Delay in milliseconds between connects of fleet controllers
+    #[clap(long, default_value_t = 100)]
+    stagger: u64,
+}
+
+fn parse_encoding(name: &str) -> Result<Encoding, String> {
+    Encoding::from_name(name).ok_or_else(|| format!("Unsupported encoding: {}", name))
 }
 
 #[tokio::main]
 async fn main() {
-    env_logger::Builder::from_env(Env::default().default_filter_or("debug")).init();
-
     let args = Args::parse();
 
-    opener::run(
-        &args.address,
-        args.port,
-        &args.serial,
-        &args.model,
-        &args.login,
-        &args.password,
+    let fleet_mode = args.count.is_some() || args.csv.is_some();
+
+    // Messages of fleet controllers are too many to log them by default
+    let filter = if fleet_mode { "warn" } else { "debug" };
+    env_logger::Builder::from_env(Env::default().default_filter_or(filter)).init();
+
+    let config = opener::Config {
+        address: args.address,
+        port: args.port,
+        serial_number: args.serial.unwrap_or_default(),
+        model: args.model.unwrap_or_default(),
+        login: args.login.unwrap_or_default(),
+        password: args.password.unwrap_or_default(),
+        secret: args.secret.unwrap_or_default(),
+        encoding: args.encoding,
+        events: args.events,
+        tls: args.tls || args.ca.is_some() || args.cert.is_some(),
+        ca: args.ca,
+        cert: args.cert,
+        key: args.key,
+    };
+
+    if !fleet_mode {
+        opener::run(config).await;
+        return;
+    }
+
+    let devices = match (&args.csv, args.count) {
+        (Some(path), _) => fleet::read_csv(path),
+        (None, Some(count)) => fleet::from_pattern(&config, count),
+        (None, None) => unreachable!(),
+    };
+
+    let devices = match devices {
+        Ok(devices) => devices,
+        Err(e) => {
+            log::error!("{:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    opener::run_fleet(
+        config,
+        fleet::FleetConfig {
+            devices,
+            stagger: Duration::from_millis(args.stagger),
+        },
     )
     .await;
 }
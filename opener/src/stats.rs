@@ -0,0 +1,29 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters of one simulated controller
+#[derive(Default, Debug)]
+pub struct Stats {
+    /// Websocket handshakes completed
+    pub connects: AtomicU64,
+    /// Connection attempts failed or timed out
+    pub connection_failures: AtomicU64,
+    /// Sessions accepted by service with WELCOME
+    pub sessions: AtomicU64,
+    pub disconnects: AtomicU64,
+    /// Commands received from service
+    pub commands: AtomicU64,
+    /// Commands answered with ERROR, including unauthorized ones
+    pub failed_commands: AtomicU64,
+    /// Messages of controller rejected by service
+    pub rejected_messages: AtomicU64,
+}
+
+impl Stats {
+    pub fn add(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(counter: &AtomicU64) -> u64 {
+        counter.load(Ordering::Relaxed)
+    }
+}
@@ -1,60 +1,132 @@
+use acs_protocol::{
+    hello_proof, AckData, BarrierCommandArgs, BarrierData, Command, CommandBody, DeviceMessage,
+    Encoding, ErrorCode, ErrorData, HelloData, InfoData, ServiceMessage, SetCommandArgs, Settings,
+    TagResult, TagsCommandArgs, UpdateCommandArgs, PROTOCOL_VERSION,
+};
 use anyhow::{bail, Result};
 use futures_util::{future, pin_mut, StreamExt};
-use thiserror::Error;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::net::TcpStream;
 use tokio::sync::watch;
 use tokio::time::{sleep, Duration};
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
-
-mod dsl;
-
-use crate::ws_client::dsl::{Set, SetData, ERROR_TYPE, SET_TYPE};
-use dsl::{Hello, HelloData};
-
-use self::dsl::HELLO_TYPE;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::Response;
+use tokio_tungstenite::tungstenite::http::{HeaderValue, Request};
+use tokio_tungstenite::{
+    client_async_tls_with_config, connect_async, tungstenite::protocol::Message, Connector,
+    MaybeTlsStream, WebSocketStream,
+};
+
+use crate::stats::Stats;
+use crate::Config;
+
+mod events;
+mod telemetry;
+mod tls;
+mod updater;
+
+use self::events::EventEmitter;
+use self::telemetry::TelemetrySender;
+use self::updater::Updater;
+
+const INITIAL_VERSION: &str = "1.0.2";
+const BARRIER_MOVING_TIME: u64 = 2;
+const TAGS_CAPACITY: usize = 16;
 
 const PING_TIMEOUT: u64 = 60;
 const PONG_WAIT_TIMEOUT: u64 = 5;
 const RECONNECTION_TIMEOUT: u64 = 5;
 const CONNECTION_TIMEOUT: u64 = 5;
 
-#[derive(Error, Debug)]
-#[error("Internal error")]
-struct InternalError;
-
 type SenderChannel = futures_channel::mpsc::UnboundedSender<Message>;
 type PongReceiver = tokio::sync::mpsc::UnboundedReceiver<String>;
 type PongSender = tokio::sync::mpsc::UnboundedSender<String>;
 type ResetSender = tokio::sync::oneshot::Sender<i32>;
 type StopPingSender = tokio::sync::oneshot::Sender<i32>;
 
+/// Challenge state of connection, it is reset on every HELLO
+#[derive(Default)]
+struct Session {
+    /// Random nonce sent in HELLO
+    nonce: String,
+    /// Nonce issued by service, it is taken from the first command of session
+    server_nonce: Option<String>,
+    /// Counter of the last accepted command
+    counter: u64,
+}
+
+/// Message received from service, commands have no `type` field unlike other messages
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Incoming {
+    Message(ServiceMessage),
+    Command(Command),
+}
+
 /// Describes ws client for opener
 pub struct WSClient {
     url: String,
+    /// TLS settings of `wss://` connection
+    tls: Option<Arc<rustls::ClientConfig>>,
+    encoding: Encoding,
+    /// Interval of sample events, they are not sent if it is absent
+    events: Option<Duration>,
     serial_number: String,
-    model: String,
     login: String,
     password: String,
-    nonce: String,
+    secret: String,
+    session: Mutex<Session>,
+    started: Instant,
+    version: Arc<Mutex<String>>,
+    settings: Mutex<Settings>,
+    doors: Mutex<[bool; 2]>,
+    tags: Mutex<HashSet<String>>,
+    stats: Arc<Stats>,
 }
 
 impl WSClient {
-    pub fn new(
-        address: &str,
-        port: u16,
-        serial_number: &str,
-        model: &str,
-        login: &str,
-        password: &str,
-    ) -> Self {
-        let url = format!("ws://{address}:{port}/ws");
-        WSClient {
+    pub fn new(config: &Config) -> Result<Self> {
+        let scheme = if config.tls { "wss" } else { "ws" };
+        let url = format!("{}://{}:{}/ws", scheme, config.address, config.port);
+
+        let tls = if config.tls {
+            Some(tls::client_config(config)?)
+        } else {
+            None
+        };
+
+        Ok(WSClient {
             url,
-            serial_number: serial_number.to_string(),
-            model: model.to_string(),
-            login: login.to_string(),
-            password: password.to_string(),
-            nonce: "jdfjksdhfjshfkjsdhkfhk".to_string(),
-        }
+            tls,
+            encoding: config.encoding,
+            events: config.events.map(Duration::from_secs),
+            serial_number: config.serial_number.clone(),
+            login: config.login.clone(),
+            password: config.password.clone(),
+            secret: config.secret.clone(),
+            session: Mutex::new(Session::default()),
+            started: Instant::now(),
+            version: Arc::new(Mutex::new(INITIAL_VERSION.to_string())),
+            settings: Mutex::new(Settings {
+                barrier_model: config.model.clone(),
+                barrier_algorithm: String::new(),
+            }),
+            doors: Mutex::new([false; 2]),
+            tags: Mutex::new(HashSet::new()),
+            stats: Arc::new(Stats::default()),
+        })
+    }
+
+    pub fn serial_number(&self) -> &str {
+        &self.serial_number
+    }
+
+    /// Counters of client, they are updated while it runs
+    pub fn stats(&self) -> Arc<Stats> {
+        self.stats.clone()
     }
 
     /// It is used for starting client
@@ -124,8 +196,43 @@ impl WSClient {
         Ok(())
     }
 
+    /// Builds handshake request with subprotocol of encoding
+    fn request(&self, url: &url::Url) -> Result<Request<()>> {
+        let mut request = url.clone().into_client_request()?;
+
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            HeaderValue::from_static(self.encoding.subprotocol()),
+        );
+
+        Ok(request)
+    }
+
+    /// Connects to service, TLS connection is verified by configured CA
+    async fn connect(
+        &self,
+        url: &url::Url,
+    ) -> Result<(WebSocketStream<MaybeTlsStream<TcpStream>>, Response)> {
+        let request = self.request(url)?;
+
+        let tls = match &self.tls {
+            Some(tls) => tls.clone(),
+            None => return Ok(connect_async(request).await?),
+        };
+
+        let host = url.host_str().unwrap_or_default();
+        let port = url.port_or_known_default().unwrap_or(443);
+
+        let socket = TcpStream::connect((host, port)).await?;
+
+        Ok(
+            client_async_tls_with_config(request, socket, None, Some(Connector::Rustls(tls)))
+                .await?,
+        )
+    }
+
     async fn execute(&self, mut stop: watch::Receiver<&'static str>) -> Result<()> {
-        log::info!("Connect to: {}", self.url);
+        log::info!("Connect to: {} ({})", self.url, self.encoding.name());
 
         let url = url::Url::parse(&self.url)?;
 
@@ -136,7 +243,7 @@ impl WSClient {
             let ws_stream;
 
             loop {
-                let connection = connect_async(&url);
+                let connection = self.connect(&url);
 
                 let connection_timeout = sleep(Duration::from_secs(CONNECTION_TIMEOUT));
                 tokio::pin!(connection_timeout);
@@ -144,17 +251,31 @@ impl WSClient {
                 tokio::select! {
                     res = connection => {
                         match res {
-                            Ok((s, _)) => {
+                            Ok((s, response)) => {
+                                let subprotocol = response.headers().get("Sec-WebSocket-Protocol");
+
+                                // Old service ignores subprotocol and speaks JSON only
+                                if self.encoding.is_binary()
+                                    && subprotocol.and_then(|p| p.to_str().ok())
+                                        != Some(self.encoding.subprotocol())
+                                {
+                                    bail!("Service does not support {} encoding", self.encoding.name());
+                                }
+
+                                Stats::add(&self.stats.connects);
                                 ws_stream = s;
                                 break;
                             },
                             Err(e) => {
+                                Stats::add(&self.stats.connection_failures);
                                 log::error!("{}", e.to_string());
                             }
                         }
                     },
 
-                    _ = &mut connection_timeout => {}
+                    _ = &mut connection_timeout => {
+                        Stats::add(&self.stats.connection_failures);
+                    }
                 }
 
                 // To exit in case of Linux signal to interrupt
@@ -199,6 +320,18 @@ impl WSClient {
 
             log::info!("Hello message sent");
 
+            if let Some(interval) = self.events {
+                tokio::spawn(
+                    EventEmitter {
+                        encoding: self.encoding,
+                        serial_number: self.serial_number.clone(),
+                        interval,
+                        sender: tx.clone(),
+                    }
+                    .run(),
+                );
+            }
+
             let from_ws = {
                 read.for_each(|message| async {
                     if let Err(e) = self.process_package(message, &tx, &tx_pong).await {
@@ -225,10 +358,13 @@ impl WSClient {
 
                 _ = stop.changed() => {
                     ping_sender_handle.await?;
+                    Stats::add(&self.stats.disconnects);
                     break 'main;
                 }
             }
 
+            Stats::add(&self.stats.disconnects);
+
             log::info!("WebSocket client restarting...");
         }
 
@@ -251,81 +387,409 @@ impl WSClient {
             return Ok(());
         }
 
-        let text = message.into_text()?;
+        let incoming: Incoming = match message {
+            Message::Text(text) => {
+                log::info!("Message received: ->{}<-", text);
 
-        log::info!("Message received: ->{}<-", text);
+                if text.is_empty() {
+                    return Ok(());
+                }
 
-        if text.is_empty() {
-            return Ok(());
+                Encoding::Json.decode(text.as_bytes())?
+            }
+            Message::Binary(data) => {
+                log::info!("Binary message received: {} bytes", data.len());
+
+                self.encoding.decode(&data)?
+            }
+            _ => return Ok(()),
+        };
+
+        let command = match incoming {
+            Incoming::Message(message) => return self.handle_service_message(message, tx),
+            Incoming::Command(command) => command,
+        };
+
+        Stats::add(&self.stats.commands);
+
+        if let Err(e) = self.check_authorization(&command) {
+            return self.send_error(tx, &command.id, ErrorCode::Unauthorized, Some(e), None);
         }
 
-        let command: dsl::Command = serde_json::from_str(&text)?;
+        let id = command.id.as_str();
 
-        let credentials = format!("{}{}{}", self.login, self.password, self.nonce);
-        let hash = sha256::digest(credentials.clone());
+        match command.body {
+            CommandBody::Set(args) => self.handle_set_command(id, args, tx).await?,
+            CommandBody::Info(_) => self.handle_info_command(id, tx).await?,
+            CommandBody::Open(args) => self.move_barrier(id, args, tx, true).await?,
+            CommandBody::Close(args) => self.move_barrier(id, args, tx, false).await?,
+            CommandBody::AddTags(args) => self.handle_add_tags_command(id, args, tx).await?,
+            CommandBody::RemoveTags(args) => self.handle_remove_tags_command(id, args, tx).await?,
+            CommandBody::Update(args) => self.handle_update_command(id, args, tx).await?,
+        };
 
-        if hash != command.authorization {
-            let error = dsl::Error {
-                message_type: ERROR_TYPE.to_string(),
-                data: dsl::ErrorData {
-                    serial_number: command.serial_number.clone(),
-                    code: 103,
-                    description: "Unauthorized".to_string(),
-                    details: None,
-                },
-            };
+        Ok(())
+    }
+
+    fn handle_service_message(&self, message: ServiceMessage, s: &SenderChannel) -> Result<()> {
+        match message {
+            ServiceMessage::Welcome(welcome) => {
+                Stats::add(&self.stats.sessions);
+
+                log::info!(
+                    "Session is accepted with protocol version {}, heartbeat interval {} s",
+                    welcome.protocol_version,
+                    welcome.heartbeat_interval
+                );
+
+                tokio::spawn(
+                    TelemetrySender {
+                        encoding: self.encoding,
+                        serial_number: self.serial_number.clone(),
+                        interval: Duration::from_secs(welcome.telemetry_interval.max(1)),
+                        started: self.started,
+                        sender: s.clone(),
+                    }
+                    .run(),
+                );
+            }
+            ServiceMessage::Error(error) => {
+                Stats::add(&self.stats.rejected_messages);
+
+                log::error!(
+                    "Service rejected {} message: {} {} {}",
+                    error.message_type.as_deref().unwrap_or("unknown"),
+                    error.code,
+                    error.description,
+                    error.details.unwrap_or_default()
+                )
+            }
+        }
 
-            let msg = serde_json::to_string(&error).unwrap();
+        Ok(())
+    }
 
-            log::info!("{}", msg.as_str());
+    /// Checks HMAC of command and rejects commands replayed from other sessions
+    /// or with already used counter
+    fn check_authorization(&self, command: &Command) -> std::result::Result<(), &'static str> {
+        let mut session = self.session.lock().unwrap();
 
-            tx.unbounded_send(Message::Text(msg)).unwrap();
+        if !command.verify(&self.login, &self.password, &session.nonce) {
+            return Err("Invalid signature");
+        }
 
-            return Ok(());
+        match session.server_nonce.as_ref() {
+            Some(nonce) if *nonce != command.nonce => return Err("Nonce mismatch"),
+            Some(_) => {}
+            None => session.server_nonce = Some(command.nonce.clone()),
         }
 
-        match command.command.as_str() {
-            "SET" => self.handle_set_command(&command, tx).await?,
-            c => bail!("Unsupported command: {}", c),
-        };
+        if command.counter <= session.counter {
+            return Err("Counter is already used");
+        }
+
+        session.counter = command.counter;
 
         Ok(())
     }
 
     async fn send_hello(&self, s: &SenderChannel) -> Result<()> {
-        let hello = Hello {
-            message_type: HELLO_TYPE.to_string(),
-            data: HelloData {
-                serial_number: self.serial_number.clone(),
-                version: "1.0.2".to_string(),
-                nonce: self.nonce.clone(),
-                barrier_model: self.model.clone(),
-            },
+        // Fresh nonce for every connection, so commands of previous sessions are rejected
+        let nonce = hex::encode(rand::random::<[u8; 16]>());
+
+        *self.session.lock().unwrap() = Session {
+            nonce: nonce.clone(),
+            ..Session::default()
         };
 
-        let msg = serde_json::to_string(&hello).unwrap();
+        let hello = DeviceMessage::Hello(HelloData {
+            serial_number: self.serial_number.clone(),
+            version: self.version.lock().unwrap().clone(),
+            proof: Some(hello_proof(&self.secret, &self.serial_number, &nonce)),
+            nonce,
+            barrier_model: self.settings.lock().unwrap().barrier_model.clone(),
+            protocol_version: Some(PROTOCOL_VERSION),
+            capabilities: Some(CommandBody::NAMES.iter().map(|c| c.to_string()).collect()),
+        });
 
-        log::info!("{}", msg.as_str());
+        self.send(s, &hello);
 
-        s.unbounded_send(Message::Text(msg)).unwrap();
+        Ok(())
+    }
+
+    async fn handle_set_command(
+        &self,
+        id: &str,
+        args: SetCommandArgs,
+        s: &SenderChannel,
+    ) -> Result<()> {
+        {
+            let mut settings = self.settings.lock().unwrap();
+            settings.barrier_model = args.barrier_model;
+            settings.barrier_algorithm = args.barrier_algorithm;
+        }
+
+        self.send(s, &DeviceMessage::Set(self.ack(id)));
 
         Ok(())
     }
 
-    async fn handle_set_command(&self, _req: &dsl::Command, s: &SenderChannel) -> Result<()> {
-        let set = Set {
-            message_type: SET_TYPE.to_string(),
-            data: SetData {
-                serial_number: self.serial_number.clone(),
-            },
+    async fn handle_info_command(&self, id: &str, s: &SenderChannel) -> Result<()> {
+        let info = DeviceMessage::Info(InfoData {
+            id: Some(id.to_string()),
+            serial_number: self.serial_number.clone(),
+            version: self.version.lock().unwrap().clone(),
+            uptime: self.started.elapsed().as_secs(),
+            settings: self.settings.lock().unwrap().clone(),
+        });
+
+        self.send(s, &info);
+
+        Ok(())
+    }
+
+    /// Simulates barrier moving according to barrier algorithm set by SET command
+    async fn move_barrier(
+        &self,
+        id: &str,
+        args: BarrierCommandArgs,
+        s: &SenderChannel,
+        open: bool,
+    ) -> Result<()> {
+        let algorithm = self.settings.lock().unwrap().barrier_algorithm.clone();
+
+        let door = match (algorithm.as_str(), args.door) {
+            ("OPEN", _) if !open => {
+                return self.send_error(
+                    s,
+                    id,
+                    ErrorCode::MethodNotAllowed,
+                    Some("Barrier supports only open command"),
+                    None,
+                );
+            }
+            ("TWO_DOORS", Some(door @ (1 | 2))) => door,
+            ("TWO_DOORS", _) => {
+                return self.send_error(
+                    s,
+                    id,
+                    ErrorCode::BadRequest,
+                    Some("Door must be 1 or 2"),
+                    None,
+                );
+            }
+            (_, None) => 1,
+            (_, Some(_)) => {
+                return self.send_error(
+                    s,
+                    id,
+                    ErrorCode::BadRequest,
+                    Some("Door is supported only by barriers with two doors"),
+                    None,
+                );
+            }
+        };
+
+        let action = if open { "opening" } else { "closing" };
+
+        log::info!("Door {} is {}", door, action);
+
+        sleep(Duration::from_secs(BARRIER_MOVING_TIME)).await;
+
+        self.doors.lock().unwrap()[door as usize - 1] = open;
+
+        log::info!(
+            "Door {} is {}",
+            door,
+            if open { "opened" } else { "closed" }
+        );
+
+        let data = BarrierData {
+            id: Some(id.to_string()),
+            serial_number: self.serial_number.clone(),
+            door: args.door,
+        };
+
+        let barrier = if open {
+            DeviceMessage::Open(data)
+        } else {
+            DeviceMessage::Close(data)
+        };
+
+        self.send(s, &barrier);
+
+        Ok(())
+    }
+
+    async fn handle_add_tags_command(
+        &self,
+        id: &str,
+        args: TagsCommandArgs,
+        s: &SenderChannel,
+    ) -> Result<()> {
+        let results = {
+            let mut tags = self.tags.lock().unwrap();
+
+            args.tags
+                .into_iter()
+                .map(|tag| {
+                    let tag = tag.to_uppercase();
+                    let code = if !is_valid_tag(&tag) {
+                        Some(ErrorCode::InvalidTag.code())
+                    } else if tags.contains(&tag) {
+                        None
+                    } else if tags.len() >= TAGS_CAPACITY {
+                        Some(ErrorCode::NoSpaceForNewTags.code())
+                    } else {
+                        tags.insert(tag.clone());
+                        None
+                    };
+                    TagResult { tag, code }
+                })
+                .collect()
+        };
+
+        self.send_tags_results(DeviceMessage::AddTags, id, results, s)
+    }
+
+    async fn handle_remove_tags_command(
+        &self,
+        id: &str,
+        args: TagsCommandArgs,
+        s: &SenderChannel,
+    ) -> Result<()> {
+        let results = {
+            let mut tags = self.tags.lock().unwrap();
+
+            args.tags
+                .into_iter()
+                .map(|tag| {
+                    let tag = tag.to_uppercase();
+                    let code = if !is_valid_tag(&tag) {
+                        Some(ErrorCode::InvalidTag.code())
+                    } else if !tags.remove(&tag) {
+                        Some(ErrorCode::NotFound.code())
+                    } else {
+                        None
+                    };
+                    TagResult { tag, code }
+                })
+                .collect()
         };
 
-        let msg = serde_json::to_string(&set).unwrap();
+        self.send_tags_results(DeviceMessage::RemoveTags, id, results, s)
+    }
+
+    /// Starts firmware update in background to keep websocket connection alive
+    async fn handle_update_command(
+        &self,
+        id: &str,
+        args: UpdateCommandArgs,
+        s: &SenderChannel,
+    ) -> Result<()> {
+        let updater = Updater {
+            encoding: self.encoding,
+            command_id: Some(id.to_string()),
+            serial_number: self.serial_number.clone(),
+            version: self.version.clone(),
+            sender: s.clone(),
+        };
+
+        tokio::spawn(updater.run(args));
+
+        Ok(())
+    }
+
+    /// Sends acknowledgement if all tags are processed, otherwise sends error with per tag results
+    fn send_tags_results(
+        &self,
+        ack: fn(AckData) -> DeviceMessage,
+        id: &str,
+        results: Vec<TagResult>,
+        s: &SenderChannel,
+    ) -> Result<()> {
+        log::info!(
+            "Tags table: {} of {} used",
+            self.tags.lock().unwrap().len(),
+            TAGS_CAPACITY
+        );
+
+        let first_error = results.iter().find_map(|r| r.code);
+
+        let first_error = match first_error {
+            Some(code) => code,
+            None => {
+                self.send(s, &ack(self.ack(id)));
+                return Ok(());
+            }
+        };
+
+        let code = if results.iter().any(|r| r.code.is_none()) {
+            ErrorCode::PartiallySuccessful
+        } else {
+            ErrorCode::try_from(first_error).unwrap_or(ErrorCode::NotFound)
+        };
+
+        self.send_error(s, id, code, None, Some(results))
+    }
+
+    fn send(&self, s: &SenderChannel, message: &DeviceMessage) {
+        send(s, self.encoding, message);
+    }
+
+    fn ack(&self, id: &str) -> AckData {
+        AckData {
+            id: Some(id.to_string()),
+            serial_number: self.serial_number.clone(),
+        }
+    }
+
+    fn send_error(
+        &self,
+        s: &SenderChannel,
+        id: &str,
+        code: ErrorCode,
+        details: Option<&str>,
+        tags: Option<Vec<TagResult>>,
+    ) -> Result<()> {
+        Stats::add(&self.stats.failed_commands);
 
-        log::info!("{}", msg.as_str());
+        let error = DeviceMessage::Error(ErrorData {
+            id: Some(id.to_string()),
+            serial_number: self.serial_number.clone(),
+            code: code.code(),
+            description: code.description().to_string(),
+            details: details.map(str::to_string),
+            tags,
+        });
 
-        s.unbounded_send(Message::Text(msg)).unwrap();
+        self.send(s, &error);
 
         Ok(())
     }
 }
+
+/// Serializes message and puts it to websocket output channel
+fn send(s: &SenderChannel, encoding: Encoding, message: &DeviceMessage) {
+    let message = match encoding {
+        Encoding::Json => Message::Text(serde_json::to_string(message).unwrap()),
+        _ => match encoding.encode(message) {
+            Ok(data) => Message::Binary(data),
+            Err(e) => {
+                log::error!("Failed to serialize message: {}", e);
+                return;
+            }
+        },
+    };
+
+    log::info!("{}", message);
+
+    if let Err(e) = s.unbounded_send(message) {
+        log::error!("Failed to send message: {}", e);
+    }
+}
+
+/// Tag is valid if it is 4, 7 or 10 bytes UID in hex
+fn is_valid_tag(tag: &str) -> bool {
+    matches!(tag.len(), 8 | 14 | 20) && tag.chars().all(|c| c.is_ascii_hexdigit())
+}
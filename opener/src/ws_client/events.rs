@@ -0,0 +1,70 @@
+//! Emits sample events as if they happened on controller
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde_json::json;
+use tokio::time::{sleep, Duration};
+
+use acs_protocol::{DeviceMessage, Encoding, EventData};
+
+use super::SenderChannel;
+
+/// Sends random event with given interval until connection is closed
+pub(super) struct EventEmitter {
+    pub encoding: Encoding,
+    pub serial_number: String,
+    pub interval: Duration,
+    pub sender: SenderChannel,
+}
+
+impl EventEmitter {
+    pub async fn run(self) {
+        loop {
+            sleep(self.interval).await;
+
+            if self.sender.is_closed() {
+                break;
+            }
+
+            let event = self.sample();
+
+            log::info!("Emit {} event", event.event_type);
+
+            super::send(&self.sender, self.encoding, &DeviceMessage::Event(event));
+        }
+
+        log::info!("Event emitter is stopped");
+    }
+
+    fn sample(&self) -> EventData {
+        let mut rng = rand::thread_rng();
+
+        let event_type = EventData::TYPES
+            .choose(&mut rng)
+            .unwrap_or(&"POWER_RESTORED");
+
+        let data = match *event_type {
+            "OPENED_BY_REMOTE" | "OBSTACLE_DETECTED" => {
+                Some(json!({ "door": rng.gen_range(1..=2) }))
+            }
+            "TAG_SCANNED" => Some(json!({
+                "tag": format!("{:08X}", rng.gen::<u32>()),
+                "granted": rng.gen::<bool>(),
+            })),
+            _ => None,
+        };
+
+        EventData {
+            serial_number: self.serial_number.clone(),
+            event_type: event_type.to_string(),
+            description: None,
+            data,
+            time: Some(
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|t| t.as_secs() as i64)
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Reports simulated state of controller with interval given by service
+
+use rand::Rng;
+use std::time::Instant;
+use tokio::time::{sleep, Duration};
+
+use acs_protocol::{DeviceMessage, Encoding, TelemetryData};
+
+use super::SenderChannel;
+
+/// Sends TELEMETRY with given interval until connection is closed
+pub(super) struct TelemetrySender {
+    pub encoding: Encoding,
+    pub serial_number: String,
+    pub interval: Duration,
+    pub started: Instant,
+    pub sender: SenderChannel,
+}
+
+impl TelemetrySender {
+    pub async fn run(self) {
+        loop {
+            if self.sender.is_closed() {
+                break;
+            }
+
+            super::send(
+                &self.sender,
+                self.encoding,
+                &DeviceMessage::Telemetry(self.sample()),
+            );
+
+            sleep(self.interval).await;
+        }
+
+        log::info!("Telemetry sender is stopped");
+    }
+
+    fn sample(&self) -> TelemetryData {
+        let mut rng = rand::thread_rng();
+
+        TelemetryData {
+            serial_number: self.serial_number.clone(),
+            signal_strength: Some(rng.gen_range(-90..-50)),
+            temperature: Some((rng.gen_range(20.0..45.0_f64) * 10.0).round() / 10.0),
+            voltage: Some((rng.gen_range(11.5..12.5_f64) * 100.0).round() / 100.0),
+            uptime: self.started.elapsed().as_secs(),
+        }
+    }
+}
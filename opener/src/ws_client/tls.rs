@@ -0,0 +1,62 @@
+use anyhow::{anyhow, bail, Context, Result};
+use rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use rustls::ClientConfig;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+use crate::Config;
+
+/// Builds TLS settings of `wss://` connection, service is verified by given CA
+/// or public roots, client certificate is presented if it is set
+pub fn client_config(config: &Config) -> Result<Arc<ClientConfig>> {
+    let mut tls = ClientConfig::new();
+
+    match &config.ca {
+        Some(ca) => {
+            let (added, _) = tls
+                .root_store
+                .add_pem_file(&mut open(ca)?)
+                .map_err(|_| anyhow!("Invalid CA certificate in {}", ca))?;
+
+            if added == 0 {
+                bail!("No CA certificates in {}", ca);
+            }
+        }
+        None => tls
+            .root_store
+            .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS),
+    }
+
+    match (&config.cert, &config.key) {
+        (Some(cert), Some(key)) => {
+            let chain =
+                certs(&mut open(cert)?).map_err(|_| anyhow!("Invalid certificate in {}", cert))?;
+
+            let mut keys = pkcs8_private_keys(&mut open(key)?)
+                .map_err(|_| anyhow!("Invalid private key in {}", key))?;
+
+            if keys.is_empty() {
+                keys = rsa_private_keys(&mut open(key)?)
+                    .map_err(|_| anyhow!("Invalid private key in {}", key))?;
+            }
+
+            let key = keys
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("No private key in {}", key))?;
+
+            tls.set_single_client_cert(chain, key)?;
+        }
+        (None, None) => {}
+        _ => bail!("Client certificate and key must be set together"),
+    }
+
+    Ok(Arc::new(tls))
+}
+
+fn open(path: &str) -> Result<BufReader<File>> {
+    Ok(BufReader::new(
+        File::open(path).with_context(|| format!("Can't open {}", path))?,
+    ))
+}
@@ -0,0 +1,158 @@
+//! Simulates over-the-air firmware update
+
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+use tokio::time::{sleep, Duration};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use acs_protocol::{
+    AckData, DeviceMessage, Encoding, ErrorCode, ErrorData, ProgressData, UpdateCommandArgs,
+};
+
+use super::SenderChannel;
+
+const INSTALLATION_TIME: u64 = 3;
+const RESTART_TIMEOUT: u64 = 1;
+const PROGRESS_STEP: u32 = 10;
+
+const DOWNLOADING_STAGE: &str = "DOWNLOADING";
+const VERIFYING_STAGE: &str = "VERIFYING";
+const INSTALLING_STAGE: &str = "INSTALLING";
+
+/// Describes firmware update errors
+#[derive(Error, Debug)]
+enum UpdateError {
+    #[error("Failed to download firmware: {0}")]
+    Download(String),
+
+    #[error("{0}")]
+    InvalidFile(&'static str),
+}
+
+impl UpdateError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            UpdateError::Download(_) => ErrorCode::ServiceUnavailable,
+            UpdateError::InvalidFile(_) => ErrorCode::InvalidFirmwareFile,
+        }
+    }
+}
+
+/// Downloads, verifies and "installs" firmware, then restarts connection
+/// to report new version in HELLO
+pub(super) struct Updater {
+    pub encoding: Encoding,
+    pub command_id: Option<String>,
+    pub serial_number: String,
+    pub version: Arc<Mutex<String>>,
+    pub sender: SenderChannel,
+}
+
+impl Updater {
+    pub async fn run(self, args: UpdateCommandArgs) {
+        if let Err(e) = self.update(&args).await {
+            log::error!("Firmware update failed: {}", e);
+
+            self.send(&DeviceMessage::Error(ErrorData {
+                id: self.command_id.clone(),
+                serial_number: self.serial_number.clone(),
+                code: e.code().code(),
+                description: e.code().description().to_string(),
+                details: Some(e.to_string()),
+                tags: None,
+            }));
+
+            return;
+        }
+
+        *self.version.lock().unwrap() = args.version.clone();
+
+        log::info!("Firmware is updated to version {}", args.version);
+
+        self.send(&DeviceMessage::Update(AckData {
+            id: self.command_id.clone(),
+            serial_number: self.serial_number.clone(),
+        }));
+
+        sleep(Duration::from_secs(RESTART_TIMEOUT)).await;
+
+        log::info!("Restarting with new firmware");
+
+        let _ = self.sender.unbounded_send(Message::Close(None));
+    }
+
+    async fn update(&self, args: &UpdateCommandArgs) -> Result<(), UpdateError> {
+        log::info!("Downloading firmware from {}", args.url);
+
+        self.progress(DOWNLOADING_STAGE, 0);
+
+        let mut response = reqwest::get(&args.url)
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| UpdateError::Download(e.to_string()))?;
+
+        if response.content_length().is_some_and(|l| l > args.size) {
+            return Err(UpdateError::InvalidFile(
+                "Firmware file is bigger than expected",
+            ));
+        }
+
+        // Buffer grows with received data, size in command is not trusted for allocation
+        let mut data: Vec<u8> = Vec::new();
+        let mut reported = 0;
+
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| UpdateError::Download(e.to_string()))?
+        {
+            if (data.len() + chunk.len()) as u64 > args.size {
+                return Err(UpdateError::InvalidFile(
+                    "Firmware file is bigger than expected",
+                ));
+            }
+
+            data.extend_from_slice(&chunk);
+
+            let percent = (data.len() as u64 * 100 / args.size.max(1)) as u32;
+
+            if percent >= reported + PROGRESS_STEP {
+                reported = percent - percent % PROGRESS_STEP;
+                self.progress(DOWNLOADING_STAGE, reported);
+            }
+        }
+
+        if data.len() as u64 != args.size {
+            return Err(UpdateError::InvalidFile("Firmware file size mismatch"));
+        }
+
+        self.progress(VERIFYING_STAGE, 0);
+
+        if sha256::digest_bytes(&data) != args.checksum.to_lowercase() {
+            return Err(UpdateError::InvalidFile("Firmware file checksum mismatch"));
+        }
+
+        self.progress(VERIFYING_STAGE, 100);
+
+        self.progress(INSTALLING_STAGE, 0);
+
+        sleep(Duration::from_secs(INSTALLATION_TIME)).await;
+
+        self.progress(INSTALLING_STAGE, 100);
+
+        Ok(())
+    }
+
+    fn progress(&self, stage: &str, percent: u32) {
+        self.send(&DeviceMessage::Progress(ProgressData {
+            id: self.command_id.clone(),
+            serial_number: self.serial_number.clone(),
+            stage: stage.to_string(),
+            percent,
+        }));
+    }
+
+    fn send(&self, message: &DeviceMessage) {
+        super::send(&self.sender, self.encoding, message);
+    }
+}
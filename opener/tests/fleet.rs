@@ -0,0 +1,60 @@
+//! Controllers of fleet are made from serial number pattern or CSV
+
+use acs_protocol::Encoding;
+
+use opener::fleet::{from_csv, from_pattern};
+use opener::Config;
+
+fn config(serial_number: &str) -> Config {
+    Config {
+        address: "localhost".to_string(),
+        port: 8765,
+        serial_number: serial_number.to_string(),
+        model: "model".to_string(),
+        login: "admin".to_string(),
+        password: "secret".to_string(),
+        secret: "key".to_string(),
+        encoding: Encoding::Json,
+        events: None,
+        tls: false,
+        ca: None,
+        cert: None,
+        key: None,
+    }
+}
+
+#[test]
+fn serial_numbers_are_made_from_pattern() {
+    let devices = from_pattern(&config("SIM-{n:3}-A"), 12).unwrap();
+
+    assert_eq!(devices.len(), 12);
+    assert_eq!(devices[0].serial_number, "SIM-001-A");
+    assert_eq!(devices[11].serial_number, "SIM-012-A");
+    assert_eq!(devices[11].secret, "key");
+
+    let devices = from_pattern(&config("{n}"), 2).unwrap();
+    assert_eq!(devices[1].serial_number, "2");
+
+    assert!(from_pattern(&config("SIM"), 2).is_err());
+    assert!(from_pattern(&config("SIM-{n:x}"), 2).is_err());
+}
+
+#[test]
+fn controllers_are_read_from_csv() {
+    let csv = "serial,model,login,password,secret\n\
+               # demo controllers\n\
+               111, m1, admin, admin, s1\n\
+               \n\
+               112,m2,user,pass,s2\n";
+
+    let devices = from_csv(csv).unwrap();
+
+    assert_eq!(devices.len(), 2);
+    assert_eq!(devices[0].serial_number, "111");
+    assert_eq!(devices[0].model, "m1");
+    assert_eq!(devices[1].login, "user");
+    assert_eq!(devices[1].secret, "s2");
+
+    assert!(from_csv("111,m1,admin\n").is_err());
+    assert!(from_csv("serial,model,login,password,secret\n").is_err());
+}
@@ -0,0 +1,33 @@
+//! HMAC-SHA256 signatures used to authenticate controllers and commands
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+fn mac(key: &str, message: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts any key");
+    mac.update(message.as_bytes());
+    mac
+}
+
+/// Returns hex encoded HMAC-SHA256 of message
+pub(crate) fn sign(key: &str, message: &str) -> String {
+    hex::encode(mac(key, message).finalize().into_bytes())
+}
+
+/// Checks hex encoded signature in constant time
+pub(crate) fn verify(key: &str, message: &str, signature: &str) -> bool {
+    match hex::decode(signature) {
+        Ok(signature) => mac(key, message).verify_slice(&signature).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Returns proof of controller's secret sent in HELLO
+pub fn hello_proof(secret: &str, serial_number: &str, nonce: &str) -> String {
+    sign(secret, &format!("{}:{}", serial_number, nonce))
+}
+
+/// Checks proof of controller's secret sent in HELLO
+pub fn verify_hello_proof(secret: &str, serial_number: &str, nonce: &str, proof: &str) -> bool {
+    verify(secret, &format!("{}:{}", serial_number, nonce), proof)
+}
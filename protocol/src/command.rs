@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::auth;
+
+/// Command sent by service to controller
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Command {
+    /// Unique id of command, controller sends it back in reply
+    pub id: String,
+    pub serial_number: String,
+    /// Nonce issued by service for session
+    pub nonce: String,
+    /// Number of command in session, it grows with every command
+    pub counter: u64,
+    /// Hex encoded HMAC-SHA256 of command, see [`Command::sign`]
+    pub authorization: String,
+    #[serde(flatten)]
+    pub body: CommandBody,
+}
+
+/// Name and arguments of command
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(
+    tag = "command",
+    content = "arguments",
+    rename_all = "SCREAMING_SNAKE_CASE"
+)]
+pub enum CommandBody {
+    Set(SetCommandArgs),
+    Info(InfoCommandArgs),
+    Open(BarrierCommandArgs),
+    Close(BarrierCommandArgs),
+    AddTags(TagsCommandArgs),
+    RemoveTags(TagsCommandArgs),
+    Update(UpdateCommandArgs),
+}
+
+impl CommandBody {
+    /// Names of all commands
+    pub const NAMES: [&'static str; 7] = [
+        "SET",
+        "INFO",
+        "OPEN",
+        "CLOSE",
+        "ADD_TAGS",
+        "REMOVE_TAGS",
+        "UPDATE",
+    ];
+
+    /// Returns name of command as it is sent in `command` field
+    pub fn name(&self) -> &'static str {
+        match self {
+            CommandBody::Set(_) => "SET",
+            CommandBody::Info(_) => "INFO",
+            CommandBody::Open(_) => "OPEN",
+            CommandBody::Close(_) => "CLOSE",
+            CommandBody::AddTags(_) => "ADD_TAGS",
+            CommandBody::RemoveTags(_) => "REMOVE_TAGS",
+            CommandBody::Update(_) => "UPDATE",
+        }
+    }
+
+    /// Returns arguments as compact JSON with sorted keys, they are signed with command
+    pub fn canonical_arguments(&self) -> String {
+        let value = serde_json::to_value(self).expect("Command is serializable to JSON");
+
+        canonical_json(&value["arguments"])
+    }
+}
+
+/// Serializes JSON value without spaces and with keys of objects in sorted order
+fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+
+            let fields: Vec<String> = keys
+                .into_iter()
+                .map(|key| {
+                    format!(
+                        "{}:{}",
+                        Value::from(key.as_str()),
+                        canonical_json(&map[key])
+                    )
+                })
+                .collect();
+
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+
+            format!("[{}]", items.join(","))
+        }
+        other => other.to_string(),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SetCommandArgs {
+    pub barrier_model: String,
+    pub barrier_algorithm: String,
+}
+
+/// INFO command has no arguments, but controllers expect `arguments` field anyway
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InfoCommandArgs {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BarrierCommandArgs {
+    /// Door index, it is given only for barriers with TWO_DOORS algorithm
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub door: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TagsCommandArgs {
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UpdateCommandArgs {
+    /// URL to download firmware file
+    pub url: String,
+    /// Size of firmware file in bytes
+    pub size: u64,
+    /// sha256 of firmware file in hex
+    pub checksum: String,
+    /// Version of firmware in file
+    pub version: String,
+}
+
+impl Command {
+    /// String signed by `authorization`, `device_nonce` is nonce sent by controller in HELLO.
+    /// Arguments are signed too, so they can't be changed on the way to controller
+    fn signed_message(&self, device_nonce: &str) -> String {
+        format!(
+            "{}:{}:{}:{}:{}:{}:{}",
+            self.serial_number,
+            self.id,
+            self.body.name(),
+            device_nonce,
+            self.nonce,
+            self.counter,
+            self.body.canonical_arguments()
+        )
+    }
+
+    /// Sets `authorization` to HMAC-SHA256 with login and password of controller as key
+    pub fn sign(&mut self, login: &str, password: &str, device_nonce: &str) {
+        let key = signing_key(login, password);
+        self.authorization = auth::sign(&key, &self.signed_message(device_nonce));
+    }
+
+    /// Checks `authorization` of received command
+    pub fn verify(&self, login: &str, password: &str, device_nonce: &str) -> bool {
+        let key = signing_key(login, password);
+        auth::verify(
+            &key,
+            &self.signed_message(device_nonce),
+            &self.authorization,
+        )
+    }
+}
+
+/// Login is prefixed with its length, so different pairs of login and password
+/// never give the same key
+fn signing_key(login: &str, password: &str) -> String {
+    format!("{}:{}{}", login.len(), login, password)
+}
@@ -0,0 +1,90 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+/// Encoding of messages in websocket frames, it is chosen by controller when it connects.
+/// JSON is sent in text frames, other encodings in binary frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Encoding {
+    #[default]
+    Json,
+    Cbor,
+    MessagePack,
+}
+
+#[derive(Error, Debug)]
+pub enum CodecError {
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Invalid CBOR: {0}")]
+    Cbor(String),
+
+    #[error("Invalid MessagePack: {0}")]
+    MessagePack(String),
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 3] = [Encoding::Json, Encoding::Cbor, Encoding::MessagePack];
+
+    /// Name used in `encoding` query parameter
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Json => "json",
+            Encoding::Cbor => "cbor",
+            Encoding::MessagePack => "msgpack",
+        }
+    }
+
+    /// Websocket subprotocol requested by controller in `Sec-WebSocket-Protocol` header
+    pub fn subprotocol(&self) -> &'static str {
+        match self {
+            Encoding::Json => "acs.json",
+            Encoding::Cbor => "acs.cbor",
+            Encoding::MessagePack => "acs.msgpack",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Encoding::ALL.iter().find(|e| e.name() == name).copied()
+    }
+
+    pub fn from_subprotocol(subprotocol: &str) -> Option<Self> {
+        Encoding::ALL
+            .iter()
+            .find(|e| e.subprotocol() == subprotocol)
+            .copied()
+    }
+
+    pub fn is_binary(&self) -> bool {
+        *self != Encoding::Json
+    }
+
+    pub fn encode<T: Serialize>(&self, message: &T) -> Result<Vec<u8>, CodecError> {
+        match self {
+            Encoding::Json => Ok(serde_json::to_vec(message)?),
+            Encoding::Cbor => {
+                let mut data = Vec::new();
+                ciborium::ser::into_writer(message, &mut data)
+                    .map_err(|e| CodecError::Cbor(e.to_string()))?;
+                Ok(data)
+            }
+            // Structs are encoded as maps to keep field names as in JSON
+            Encoding::MessagePack => {
+                rmp_serde::to_vec_named(message).map_err(|e| CodecError::MessagePack(e.to_string()))
+            }
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, CodecError> {
+        match self {
+            Encoding::Json => Ok(serde_json::from_slice(data)?),
+            Encoding::Cbor => {
+                ciborium::de::from_reader(data).map_err(|e| CodecError::Cbor(e.to_string()))
+            }
+            Encoding::MessagePack => {
+                rmp_serde::from_slice(data).map_err(|e| CodecError::MessagePack(e.to_string()))
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::convert::TryFrom;
+
+/// Error codes for controller's operations, they are sent as numbers in `code` fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    InternalServerError = 101,
+    BadRequest = 102,
+    Unauthorized = 103,
+    Forbidden = 104,
+    NotFound = 105,
+    MethodNotAllowed = 106,
+    NotImplemented = 107,
+    ServiceUnavailable = 108,
+    InvalidFirmwareFile = 109,
+    PartiallySuccessful = 110,
+    DataNotProvided = 111,
+    NoSpaceForNewTags = 112,
+    InvalidTag = 113,
+    /// Set by service if controller does not reply to command in time
+    Timeout = 114,
+    /// Set by service if controller disconnects before reply to command
+    Disconnected = 115,
+}
+
+impl ErrorCode {
+    const ALL: [ErrorCode; 15] = [
+        ErrorCode::InternalServerError,
+        ErrorCode::BadRequest,
+        ErrorCode::Unauthorized,
+        ErrorCode::Forbidden,
+        ErrorCode::NotFound,
+        ErrorCode::MethodNotAllowed,
+        ErrorCode::NotImplemented,
+        ErrorCode::ServiceUnavailable,
+        ErrorCode::InvalidFirmwareFile,
+        ErrorCode::PartiallySuccessful,
+        ErrorCode::DataNotProvided,
+        ErrorCode::NoSpaceForNewTags,
+        ErrorCode::InvalidTag,
+        ErrorCode::Timeout,
+        ErrorCode::Disconnected,
+    ];
+
+    pub fn code(&self) -> u32 {
+        *self as u32
+    }
+
+    /// Description sent in `description` field of ERROR message
+    pub fn description(&self) -> &'static str {
+        match self {
+            ErrorCode::InternalServerError => "Internal server error",
+            ErrorCode::BadRequest => "Bad request",
+            ErrorCode::Unauthorized => "Unauthorized",
+            ErrorCode::Forbidden => "Forbidden",
+            ErrorCode::NotFound => "Not found",
+            ErrorCode::MethodNotAllowed => "Method not allowed",
+            ErrorCode::NotImplemented => "Not implemented",
+            ErrorCode::ServiceUnavailable => "Service unavailable",
+            ErrorCode::InvalidFirmwareFile => "Invalid firmware file",
+            ErrorCode::PartiallySuccessful => "Partially successful",
+            ErrorCode::DataNotProvided => "Data not provided",
+            ErrorCode::NoSpaceForNewTags => "No space for new tags",
+            ErrorCode::InvalidTag => "Invalid tag",
+            ErrorCode::Timeout => "Timeout",
+            ErrorCode::Disconnected => "Controller disconnected",
+        }
+    }
+}
+
+impl From<ErrorCode> for u32 {
+    fn from(code: ErrorCode) -> Self {
+        code.code()
+    }
+}
+
+impl TryFrom<u32> for ErrorCode {
+    type Error = u32;
+
+    /// Returns unknown code back as error
+    fn try_from(code: u32) -> Result<Self, Self::Error> {
+        ErrorCode::ALL
+            .iter()
+            .find(|c| c.code() == code)
+            .copied()
+            .ok_or(code)
+    }
+}
@@ -0,0 +1,29 @@
+//! Messages of websocket protocol between service and controllers.
+//!
+//! Controllers send [`DeviceMessage`] to service, service sends [`Command`] and
+//! [`ServiceMessage`] to controllers.
+//! JSON examples of every message are in `fixtures` folder of this crate.
+//! The same messages can be sent in CBOR or MessagePack, see [`Encoding`].
+
+mod auth;
+mod command;
+mod encoding;
+mod error_code;
+mod message;
+mod version;
+
+pub use auth::{hello_proof, verify_hello_proof};
+pub use command::{
+    BarrierCommandArgs, Command, CommandBody, InfoCommandArgs, SetCommandArgs, TagsCommandArgs,
+    UpdateCommandArgs,
+};
+pub use encoding::{CodecError, Encoding};
+pub use error_code::ErrorCode;
+pub use message::{
+    AckData, BarrierData, DeviceMessage, ErrorData, EventData, HelloData, InfoData,
+    MessageErrorData, ProgressData, ServiceMessage, Settings, TagResult, TelemetryData,
+    WelcomeData,
+};
+pub use version::{
+    negotiate_version, LEGACY_CAPABILITIES, LEGACY_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
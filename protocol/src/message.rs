@@ -0,0 +1,263 @@
+use serde::{Deserialize, Serialize};
+
+use crate::auth;
+
+/// Message sent by controller to service
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", content = "data", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DeviceMessage {
+    /// The first message after connection
+    Hello(HelloData),
+    /// Reply to SET command
+    Set(AckData),
+    /// Reply to any command if it is failed
+    Error(ErrorData),
+    /// Reply to INFO command
+    Info(InfoData),
+    /// Reply to OPEN command
+    Open(BarrierData),
+    /// Reply to CLOSE command
+    Close(BarrierData),
+    /// Reply to ADD_TAGS command if all tags are added
+    AddTags(AckData),
+    /// Reply to REMOVE_TAGS command if all tags are removed
+    RemoveTags(AckData),
+    /// Reply to UPDATE command when new firmware is installed
+    Update(AckData),
+    /// Progress of firmware update
+    Progress(ProgressData),
+    /// Something happened on controller without command from service
+    Event(EventData),
+    /// State of controller sent with interval given in WELCOME
+    Telemetry(TelemetryData),
+}
+
+impl DeviceMessage {
+    /// All types of messages sent by controllers
+    pub const TYPES: [&'static str; 12] = [
+        "HELLO",
+        "SET",
+        "ERROR",
+        "INFO",
+        "OPEN",
+        "CLOSE",
+        "ADD_TAGS",
+        "REMOVE_TAGS",
+        "UPDATE",
+        "PROGRESS",
+        "EVENT",
+        "TELEMETRY",
+    ];
+
+    /// Returns type of message as it is sent in `type` field
+    pub fn message_type(&self) -> &'static str {
+        match self {
+            DeviceMessage::Hello(_) => "HELLO",
+            DeviceMessage::Set(_) => "SET",
+            DeviceMessage::Error(_) => "ERROR",
+            DeviceMessage::Info(_) => "INFO",
+            DeviceMessage::Open(_) => "OPEN",
+            DeviceMessage::Close(_) => "CLOSE",
+            DeviceMessage::AddTags(_) => "ADD_TAGS",
+            DeviceMessage::RemoveTags(_) => "REMOVE_TAGS",
+            DeviceMessage::Update(_) => "UPDATE",
+            DeviceMessage::Progress(_) => "PROGRESS",
+            DeviceMessage::Event(_) => "EVENT",
+            DeviceMessage::Telemetry(_) => "TELEMETRY",
+        }
+    }
+
+    pub fn serial_number(&self) -> &str {
+        match self {
+            DeviceMessage::Hello(d) => &d.serial_number,
+            DeviceMessage::Set(d)
+            | DeviceMessage::AddTags(d)
+            | DeviceMessage::RemoveTags(d)
+            | DeviceMessage::Update(d) => &d.serial_number,
+            DeviceMessage::Error(d) => &d.serial_number,
+            DeviceMessage::Info(d) => &d.serial_number,
+            DeviceMessage::Open(d) | DeviceMessage::Close(d) => &d.serial_number,
+            DeviceMessage::Progress(d) => &d.serial_number,
+            DeviceMessage::Event(d) => &d.serial_number,
+            DeviceMessage::Telemetry(d) => &d.serial_number,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EventData {
+    pub serial_number: String,
+    /// One of [`EventData::TYPES`]
+    pub event_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Additional data depending on event type, e.g. scanned tag
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+    /// Unix time of event in seconds, absent if controller has no clock
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<i64>,
+}
+
+impl EventData {
+    /// Types of events reported by controllers
+    pub const TYPES: [&'static str; 4] = [
+        "OPENED_BY_REMOTE",
+        "TAG_SCANNED",
+        "OBSTACLE_DETECTED",
+        "POWER_RESTORED",
+    ];
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TelemetryData {
+    pub serial_number: String,
+    /// Signal strength of network connection in dBm
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal_strength: Option<i32>,
+    /// Temperature inside controller in degrees Celsius
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    /// Supply voltage in volts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voltage: Option<f64>,
+    /// Seconds since controller start
+    pub uptime: u64,
+}
+
+/// Message sent by service to controller, commands are sent as [`crate::Command`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", content = "data", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ServiceMessage {
+    /// Reply to accepted HELLO
+    Welcome(WelcomeData),
+    /// Reply to message which service can not process
+    Error(MessageErrorData),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WelcomeData {
+    /// Version of protocol used in session
+    pub protocol_version: u32,
+    /// Unix time of service in seconds
+    pub server_time: i64,
+    /// Service sends ping with this interval in seconds
+    pub heartbeat_interval: u64,
+    /// Service closes connection if controller is silent for this number of seconds
+    pub client_timeout: u64,
+    /// Controller sends TELEMETRY with this interval in seconds
+    pub telemetry_interval: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MessageErrorData {
+    /// Type of rejected message, it is absent if message can not be parsed at all
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_type: Option<String>,
+    /// One of [`crate::ErrorCode`]
+    pub code: u32,
+    pub description: String,
+    pub details: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HelloData {
+    pub serial_number: String,
+    pub version: String,
+    /// Random string generated for every connection, at least 16 characters
+    pub nonce: String,
+    pub barrier_model: String,
+    /// Proof of controller's secret, see [`crate::hello_proof`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proof: Option<String>,
+    /// Version of protocol, old firmware does not send it and speaks
+    /// [`crate::LEGACY_PROTOCOL_VERSION`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol_version: Option<u32>,
+    /// Names of commands supported by controller, old firmware does not send them
+    /// and supports [`crate::LEGACY_CAPABILITIES`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<String>>,
+}
+
+impl HelloData {
+    /// Checks proof of controller's secret
+    pub fn verify_proof(&self, secret: &str) -> bool {
+        match self.proof.as_deref() {
+            Some(proof) => {
+                auth::verify_hello_proof(secret, &self.serial_number, &self.nonce, proof)
+            }
+            None => false,
+        }
+    }
+}
+
+/// Acknowledgement of commands without result data
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AckData {
+    /// Id of command, old firmware does not send it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub serial_number: String,
+}
+
+/// Result of processing of single tag in ADD_TAGS and REMOVE_TAGS commands
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TagResult {
+    pub tag: String,
+    /// Error code, it is absent if tag is processed successfully
+    pub code: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ErrorData {
+    /// Id of command, old firmware does not send it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub serial_number: String,
+    /// One of [`crate::ErrorCode`]
+    pub code: u32,
+    pub description: String,
+    pub details: Option<String>,
+    /// Per tag results for failed ADD_TAGS and REMOVE_TAGS commands
+    pub tags: Option<Vec<TagResult>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub barrier_model: String,
+    pub barrier_algorithm: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InfoData {
+    /// Id of command, old firmware does not send it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub serial_number: String,
+    pub version: String,
+    /// Seconds since controller start
+    pub uptime: u64,
+    pub settings: Settings,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BarrierData {
+    /// Id of command, old firmware does not send it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub serial_number: String,
+    /// Door given in command
+    pub door: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProgressData {
+    /// Id of command, old firmware does not send it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub serial_number: String,
+    /// DOWNLOADING, VERIFYING or INSTALLING
+    pub stage: String,
+    pub percent: u32,
+}
@@ -0,0 +1,18 @@
+/// The latest version of protocol supported by this crate
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Version of firmware which does not send `protocol_version` in HELLO
+pub const LEGACY_PROTOCOL_VERSION: u32 = 1;
+
+/// Commands supported by firmware which does not send `capabilities` in HELLO
+pub const LEGACY_CAPABILITIES: [&str; 1] = ["SET"];
+
+/// Returns version of protocol used in session with controller,
+/// `None` if controller's version is not supported
+pub fn negotiate_version(device_version: u32) -> Option<u32> {
+    if device_version < LEGACY_PROTOCOL_VERSION {
+        return None;
+    }
+
+    Some(device_version.min(PROTOCOL_VERSION))
+}
@@ -0,0 +1,598 @@
+//! Checks messages against golden JSON fixtures shared with firmware teams
+
+use acs_protocol::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::fs;
+use std::path::Path;
+
+const SERIAL_NUMBER: &str = "111";
+const COMMAND_ID: &str = "6355f1e2a1b2c3d4e5f60710";
+const BARRIER_MODEL: &str = "63285f2c43188baf21ddc2f7";
+const SERVICE_NONCE: &str = "4e07408562bedb8b60ce05c1decfe3ad";
+
+/// Credentials used to sign commands and HELLO in fixtures
+#[derive(Deserialize)]
+struct Auth {
+    login: String,
+    password: String,
+    secret: String,
+    device_nonce: String,
+}
+
+fn load(name: &str) -> Value {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures")
+        .join(name);
+
+    let text = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e));
+
+    serde_json::from_str(&text).unwrap()
+}
+
+fn auth() -> Auth {
+    serde_json::from_value(load("auth.json")).unwrap()
+}
+
+/// Checks that message is serialized exactly as fixture and fixture is parsed back to message
+fn check<T>(name: &str, expected: &T)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    let fixture = load(name);
+
+    assert_eq!(serde_json::to_value(expected).unwrap(), fixture, "{}", name);
+
+    let parsed: T = serde_json::from_value(fixture).unwrap();
+    assert_eq!(&parsed, expected, "{}", name);
+
+    let text = serde_json::to_string(&parsed).unwrap();
+    assert_eq!(
+        &serde_json::from_str::<T>(&text).unwrap(),
+        expected,
+        "{}",
+        name
+    );
+}
+
+fn ack() -> AckData {
+    AckData {
+        id: Some(COMMAND_ID.to_string()),
+        serial_number: SERIAL_NUMBER.to_string(),
+    }
+}
+
+#[test]
+fn hello() {
+    let auth = auth();
+
+    let hello = HelloData {
+        serial_number: SERIAL_NUMBER.to_string(),
+        version: "1.0.2".to_string(),
+        nonce: auth.device_nonce.clone(),
+        barrier_model: BARRIER_MODEL.to_string(),
+        proof: Some(hello_proof(&auth.secret, SERIAL_NUMBER, &auth.device_nonce)),
+        protocol_version: Some(PROTOCOL_VERSION),
+        capabilities: Some(vec![
+            "SET".to_string(),
+            "INFO".to_string(),
+            "OPEN".to_string(),
+            "CLOSE".to_string(),
+        ]),
+    };
+
+    assert!(hello.verify_proof(&auth.secret));
+    assert!(!hello.verify_proof("other secret"));
+
+    check("device/hello.json", &DeviceMessage::Hello(hello));
+}
+
+#[test]
+fn legacy_hello() {
+    let auth = auth();
+
+    let hello = HelloData {
+        serial_number: SERIAL_NUMBER.to_string(),
+        version: "1.0.2".to_string(),
+        nonce: auth.device_nonce.clone(),
+        barrier_model: BARRIER_MODEL.to_string(),
+        proof: Some(hello_proof(&auth.secret, SERIAL_NUMBER, &auth.device_nonce)),
+        protocol_version: None,
+        capabilities: None,
+    };
+
+    check("device/hello_legacy.json", &DeviceMessage::Hello(hello));
+}
+
+#[test]
+fn welcome() {
+    check(
+        "service/welcome.json",
+        &ServiceMessage::Welcome(WelcomeData {
+            protocol_version: PROTOCOL_VERSION,
+            server_time: 1666577890,
+            heartbeat_interval: 5,
+            client_timeout: 10,
+            telemetry_interval: 60,
+        }),
+    );
+}
+
+#[test]
+fn message_error() {
+    check(
+        "service/error.json",
+        &ServiceMessage::Error(MessageErrorData {
+            message_type: Some("OPEN".to_string()),
+            code: ErrorCode::BadRequest.into(),
+            description: ErrorCode::BadRequest.description().to_string(),
+            details: Some("missing field `serial_number`".to_string()),
+        }),
+    );
+}
+
+#[test]
+fn version_negotiation() {
+    assert_eq!(negotiate_version(0), None);
+    assert_eq!(
+        negotiate_version(LEGACY_PROTOCOL_VERSION),
+        Some(LEGACY_PROTOCOL_VERSION)
+    );
+    assert_eq!(negotiate_version(PROTOCOL_VERSION), Some(PROTOCOL_VERSION));
+    assert_eq!(
+        negotiate_version(PROTOCOL_VERSION + 1),
+        Some(PROTOCOL_VERSION)
+    );
+
+    let names: Vec<&str> = [
+        CommandBody::Set(SetCommandArgs {
+            barrier_model: BARRIER_MODEL.to_string(),
+            barrier_algorithm: "OPEN".to_string(),
+        }),
+        CommandBody::Info(InfoCommandArgs {}),
+        CommandBody::Open(BarrierCommandArgs { door: None }),
+        CommandBody::Close(BarrierCommandArgs { door: None }),
+        CommandBody::AddTags(TagsCommandArgs { tags: vec![] }),
+        CommandBody::RemoveTags(TagsCommandArgs { tags: vec![] }),
+        CommandBody::Update(UpdateCommandArgs {
+            url: String::new(),
+            size: 0,
+            checksum: String::new(),
+            version: String::new(),
+        }),
+    ]
+    .iter()
+    .map(CommandBody::name)
+    .collect();
+
+    assert_eq!(names, CommandBody::NAMES);
+
+    // Firmware before capabilities were declared handles only SET
+    assert_eq!(LEGACY_CAPABILITIES, ["SET"]);
+}
+
+#[test]
+fn hello_without_proof_is_rejected() {
+    let hello = HelloData {
+        serial_number: SERIAL_NUMBER.to_string(),
+        version: "1.0.2".to_string(),
+        nonce: auth().device_nonce,
+        barrier_model: BARRIER_MODEL.to_string(),
+        proof: None,
+        protocol_version: None,
+        capabilities: None,
+    };
+
+    assert!(!hello.verify_proof(&auth().secret));
+}
+
+#[test]
+fn acknowledgements() {
+    check("device/set.json", &DeviceMessage::Set(ack()));
+    check("device/add_tags.json", &DeviceMessage::AddTags(ack()));
+    check("device/remove_tags.json", &DeviceMessage::RemoveTags(ack()));
+    check("device/update.json", &DeviceMessage::Update(ack()));
+
+    check(
+        "device/set_without_id.json",
+        &DeviceMessage::Set(AckData {
+            id: None,
+            serial_number: SERIAL_NUMBER.to_string(),
+        }),
+    );
+}
+
+#[test]
+fn errors() {
+    check(
+        "device/error.json",
+        &DeviceMessage::Error(ErrorData {
+            id: Some(COMMAND_ID.to_string()),
+            serial_number: SERIAL_NUMBER.to_string(),
+            code: ErrorCode::Unauthorized.into(),
+            description: ErrorCode::Unauthorized.description().to_string(),
+            details: Some("Invalid signature".to_string()),
+            tags: None,
+        }),
+    );
+
+    check(
+        "device/error_tags.json",
+        &DeviceMessage::Error(ErrorData {
+            id: Some(COMMAND_ID.to_string()),
+            serial_number: SERIAL_NUMBER.to_string(),
+            code: ErrorCode::PartiallySuccessful.into(),
+            description: ErrorCode::PartiallySuccessful.description().to_string(),
+            details: None,
+            tags: Some(vec![
+                TagResult {
+                    tag: "04A1B2C3".to_string(),
+                    code: None,
+                },
+                TagResult {
+                    tag: "XYZ".to_string(),
+                    code: Some(ErrorCode::InvalidTag.into()),
+                },
+            ]),
+        }),
+    );
+}
+
+#[test]
+fn info() {
+    check(
+        "device/info.json",
+        &DeviceMessage::Info(InfoData {
+            id: Some(COMMAND_ID.to_string()),
+            serial_number: SERIAL_NUMBER.to_string(),
+            version: "1.0.2".to_string(),
+            uptime: 3600,
+            settings: Settings {
+                barrier_model: BARRIER_MODEL.to_string(),
+                barrier_algorithm: "OPEN".to_string(),
+            },
+        }),
+    );
+}
+
+#[test]
+fn barrier_replies() {
+    check(
+        "device/open.json",
+        &DeviceMessage::Open(BarrierData {
+            id: Some(COMMAND_ID.to_string()),
+            serial_number: SERIAL_NUMBER.to_string(),
+            door: Some(2),
+        }),
+    );
+
+    check(
+        "device/close.json",
+        &DeviceMessage::Close(BarrierData {
+            id: Some(COMMAND_ID.to_string()),
+            serial_number: SERIAL_NUMBER.to_string(),
+            door: None,
+        }),
+    );
+}
+
+#[test]
+fn progress() {
+    check(
+        "device/progress.json",
+        &DeviceMessage::Progress(ProgressData {
+            id: Some(COMMAND_ID.to_string()),
+            serial_number: SERIAL_NUMBER.to_string(),
+            stage: "DOWNLOADING".to_string(),
+            percent: 40,
+        }),
+    );
+}
+
+#[test]
+fn event() {
+    check(
+        "device/event.json",
+        &DeviceMessage::Event(EventData {
+            serial_number: SERIAL_NUMBER.to_string(),
+            event_type: "TAG_SCANNED".to_string(),
+            description: Some("Tag is scanned".to_string()),
+            data: Some(serde_json::json!({ "tag": "04A2B3C4D5", "granted": true })),
+            time: Some(1666577890),
+        }),
+    );
+
+    check(
+        "device/event_minimal.json",
+        &DeviceMessage::Event(EventData {
+            serial_number: SERIAL_NUMBER.to_string(),
+            event_type: "POWER_RESTORED".to_string(),
+            description: None,
+            data: None,
+            time: None,
+        }),
+    );
+}
+
+#[test]
+fn telemetry() {
+    check(
+        "device/telemetry.json",
+        &DeviceMessage::Telemetry(TelemetryData {
+            serial_number: SERIAL_NUMBER.to_string(),
+            signal_strength: Some(-67),
+            temperature: Some(36.5),
+            voltage: Some(12.1),
+            uptime: 3600,
+        }),
+    );
+}
+
+#[test]
+fn unknown_message_type_is_rejected() {
+    let text = r#"{"type":"REBOOT","data":{"serial_number":"111"}}"#;
+
+    assert!(serde_json::from_str::<DeviceMessage>(text).is_err());
+}
+
+#[test]
+fn message_types() {
+    for name in [
+        "hello",
+        "set",
+        "error",
+        "info",
+        "open",
+        "close",
+        "add_tags",
+        "remove_tags",
+        "update",
+        "progress",
+        "event",
+        "telemetry",
+    ] {
+        let message: DeviceMessage =
+            serde_json::from_value(load(&format!("device/{}.json", name))).unwrap();
+
+        assert!(DeviceMessage::TYPES.contains(&message.message_type()));
+        assert_eq!(message.message_type(), name.to_uppercase());
+    }
+}
+
+/// Builds command as fixture `index` of service folder, it is signed with credentials from auth.json
+fn command(index: u64, body: CommandBody) -> Command {
+    let auth = auth();
+
+    let mut command = Command {
+        id: format!("6355f1e2a1b2c3d4e5f607{}", 10 + index),
+        serial_number: SERIAL_NUMBER.to_string(),
+        nonce: SERVICE_NONCE.to_string(),
+        counter: index + 1,
+        authorization: String::new(),
+        body,
+    };
+
+    command.sign(&auth.login, &auth.password, &auth.device_nonce);
+
+    command
+}
+
+#[test]
+fn commands() {
+    let fixtures = [
+        (
+            "service/set.json",
+            CommandBody::Set(SetCommandArgs {
+                barrier_model: BARRIER_MODEL.to_string(),
+                barrier_algorithm: "OPEN".to_string(),
+            }),
+        ),
+        ("service/info.json", CommandBody::Info(InfoCommandArgs {})),
+        (
+            "service/open.json",
+            CommandBody::Open(BarrierCommandArgs { door: None }),
+        ),
+        (
+            "service/open_door.json",
+            CommandBody::Open(BarrierCommandArgs { door: Some(2) }),
+        ),
+        (
+            "service/close.json",
+            CommandBody::Close(BarrierCommandArgs { door: None }),
+        ),
+        (
+            "service/add_tags.json",
+            CommandBody::AddTags(TagsCommandArgs {
+                tags: vec!["04A1B2C3".to_string(), "04A1B2C3D4E5F6".to_string()],
+            }),
+        ),
+        (
+            "service/remove_tags.json",
+            CommandBody::RemoveTags(TagsCommandArgs {
+                tags: vec!["04A1B2C3".to_string()],
+            }),
+        ),
+        (
+            "service/update.json",
+            CommandBody::Update(UpdateCommandArgs {
+                url: "http://localhost:8765/dist/opener-1.0.3.bin".to_string(),
+                size: 524288,
+                checksum: "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+                    .to_string(),
+                version: "1.0.3".to_string(),
+            }),
+        ),
+    ];
+
+    for (index, (name, body)) in fixtures.into_iter().enumerate() {
+        check(name, &command(index as u64, body));
+    }
+}
+
+#[test]
+fn command_signature() {
+    let auth = auth();
+
+    let command: Command = serde_json::from_value(load("service/set.json")).unwrap();
+
+    assert!(command.verify(&auth.login, &auth.password, &auth.device_nonce));
+
+    // Command from previous session
+    assert!(!command.verify(&auth.login, &auth.password, "0123456789abcdef"));
+
+    assert!(!command.verify(&auth.login, "wrong", &auth.device_nonce));
+
+    // Part of login moved to password gives another key
+    let login = &auth.login[..auth.login.len() - 1];
+    let password = format!("{}{}", &auth.login[auth.login.len() - 1..], auth.password);
+    assert!(!command.verify(login, &password, &auth.device_nonce));
+
+    let mut replayed = command.clone();
+    replayed.counter += 1;
+    assert!(!replayed.verify(&auth.login, &auth.password, &auth.device_nonce));
+
+    let mut renamed = command.clone();
+    renamed.body = CommandBody::Info(InfoCommandArgs {});
+    assert!(!renamed.verify(&auth.login, &auth.password, &auth.device_nonce));
+
+    // Arguments changed on the way to controller
+    let mut tampered = command;
+    tampered.body = CommandBody::Set(SetCommandArgs {
+        barrier_model: BARRIER_MODEL.to_string(),
+        barrier_algorithm: "TWO_DOORS".to_string(),
+    });
+    assert!(!tampered.verify(&auth.login, &auth.password, &auth.device_nonce));
+}
+
+#[test]
+fn command_arguments_are_signed() {
+    let auth = auth();
+
+    let command: Command = serde_json::from_value(load("service/update.json")).unwrap();
+    assert!(command.verify(&auth.login, &auth.password, &auth.device_nonce));
+
+    let mut tampered = command.clone();
+    if let CommandBody::Update(args) = &mut tampered.body {
+        args.url = "http://attacker/opener-1.0.3.bin".to_string();
+    }
+    assert!(!tampered.verify(&auth.login, &auth.password, &auth.device_nonce));
+
+    let command: Command = serde_json::from_value(load("service/add_tags.json")).unwrap();
+    assert!(command.verify(&auth.login, &auth.password, &auth.device_nonce));
+
+    let mut tampered = command;
+    if let CommandBody::AddTags(args) = &mut tampered.body {
+        args.tags[0] = "04FFFFFF".to_string();
+    }
+    assert!(!tampered.verify(&auth.login, &auth.password, &auth.device_nonce));
+}
+
+#[test]
+fn canonical_arguments_have_sorted_keys() {
+    let body = CommandBody::Set(SetCommandArgs {
+        barrier_model: BARRIER_MODEL.to_string(),
+        barrier_algorithm: "OPEN".to_string(),
+    });
+
+    assert_eq!(
+        body.canonical_arguments(),
+        format!(
+            r#"{{"barrier_algorithm":"OPEN","barrier_model":"{}"}}"#,
+            BARRIER_MODEL
+        )
+    );
+
+    assert_eq!(
+        CommandBody::Open(BarrierCommandArgs { door: None }).canonical_arguments(),
+        "{}"
+    );
+}
+
+/// Checks that message is restored from every encoding, binary ones must be more compact
+fn check_encodings<T>(name: &str)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    let message: T = serde_json::from_value(load(name)).unwrap();
+
+    let json = Encoding::Json.encode(&message).unwrap();
+
+    for encoding in Encoding::ALL {
+        let data = encoding.encode(&message).unwrap();
+        let decoded: T = encoding.decode(&data).unwrap();
+
+        assert_eq!(decoded, message, "{} in {}", name, encoding.name());
+
+        if encoding.is_binary() {
+            assert!(data.len() < json.len(), "{} in {}", name, encoding.name());
+        }
+    }
+}
+
+#[test]
+fn binary_encodings() {
+    for name in [
+        "hello",
+        "hello_legacy",
+        "set",
+        "set_without_id",
+        "error",
+        "error_tags",
+        "info",
+        "open",
+        "close",
+        "add_tags",
+        "remove_tags",
+        "update",
+        "progress",
+        "event",
+        "telemetry",
+    ] {
+        check_encodings::<DeviceMessage>(&format!("device/{}.json", name));
+    }
+
+    for name in [
+        "set",
+        "info",
+        "open",
+        "open_door",
+        "close",
+        "add_tags",
+        "remove_tags",
+        "update",
+    ] {
+        check_encodings::<Command>(&format!("service/{}.json", name));
+    }
+
+    check_encodings::<ServiceMessage>("service/welcome.json");
+    check_encodings::<ServiceMessage>("service/error.json");
+}
+
+#[test]
+fn encoding_names() {
+    for encoding in Encoding::ALL {
+        assert_eq!(Encoding::from_name(encoding.name()), Some(encoding));
+        assert_eq!(
+            Encoding::from_subprotocol(encoding.subprotocol()),
+            Some(encoding)
+        );
+    }
+
+    assert_eq!(Encoding::from_name("xml"), None);
+    assert_eq!(Encoding::default(), Encoding::Json);
+}
+
+#[test]
+fn error_codes() {
+    for code in 101..=115 {
+        let error_code = ErrorCode::try_from(code).unwrap();
+        assert_eq!(u32::from(error_code), code);
+    }
+
+    assert_eq!(ErrorCode::try_from(100), Err(100));
+    assert_eq!(ErrorCode::try_from(116), Err(116));
+}
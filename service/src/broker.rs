@@ -0,0 +1,278 @@
+//! Delivers events to GraphQL subscriptions, every subscriber has bounded queue
+//! so slow client can not make service grow memory without limit
+
+use futures::task::{Context, Poll, Waker};
+use futures::Stream;
+use slab::Slab;
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fmt::Write;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+const DEFAULT_CAPACITY: usize = 100;
+
+/// What to do with event when queue of subscriber is full
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverflowPolicy {
+    /// The oldest event in queue is dropped
+    DropOldest,
+    /// Subscription is finished, client has to subscribe again
+    Disconnect,
+}
+
+impl OverflowPolicy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            OverflowPolicy::DropOldest => "drop_oldest",
+            OverflowPolicy::Disconnect => "disconnect",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BrokerConfig {
+    /// Maximum number of events waiting in queue of subscriber
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for BrokerConfig {
+    fn default() -> Self {
+        BrokerConfig {
+            capacity: DEFAULT_CAPACITY,
+            overflow: OverflowPolicy::DropOldest,
+        }
+    }
+}
+
+impl BrokerConfig {
+    /// Reads `BROKER_CAPACITY` and `BROKER_OVERFLOW_POLICY` (`drop_oldest` or `disconnect`)
+    pub fn from_env() -> Self {
+        let capacity = env::var("BROKER_CAPACITY")
+            .ok()
+            .and_then(|c| c.parse().ok())
+            .filter(|c| *c > 0)
+            .unwrap_or(DEFAULT_CAPACITY);
+
+        let overflow = match env::var("BROKER_OVERFLOW_POLICY").as_deref() {
+            Ok("disconnect") => OverflowPolicy::Disconnect,
+            _ => OverflowPolicy::DropOldest,
+        };
+
+        BrokerConfig { capacity, overflow }
+    }
+}
+
+/// Counters of broker since start
+#[derive(Default)]
+struct Metrics {
+    subscribed: AtomicU64,
+    unsubscribed: AtomicU64,
+    published: AtomicU64,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+    disconnected: AtomicU64,
+}
+
+struct QueueState<T> {
+    events: VecDeque<T>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+/// Events waiting to be read by subscriber
+struct Queue<T>(Mutex<QueueState<T>>);
+
+impl<T> Queue<T> {
+    fn new(capacity: usize) -> Self {
+        Queue(Mutex::new(QueueState {
+            events: VecDeque::with_capacity(capacity),
+            waker: None,
+            closed: false,
+        }))
+    }
+
+    fn close(&self) {
+        let mut state = self.0.lock().unwrap();
+
+        state.closed = true;
+
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Subscribers of one event type, it is locked separately from other types
+struct Topic<T>(Mutex<Slab<Arc<Queue<T>>>>);
+
+/// Broker shared by server actor and GraphQL schema
+#[derive(Clone)]
+pub struct Broker {
+    config: BrokerConfig,
+    topics: Arc<RwLock<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>>,
+    metrics: Arc<Metrics>,
+}
+
+impl Broker {
+    pub fn new(config: BrokerConfig) -> Self {
+        Broker {
+            config,
+            topics: Default::default(),
+            metrics: Default::default(),
+        }
+    }
+
+    /// Returns topic of event type if somebody has ever subscribed to it
+    fn topic<T: Send + 'static>(&self) -> Option<Arc<Topic<T>>> {
+        let topic = self.topics.read().unwrap().get(&TypeId::of::<T>())?.clone();
+
+        topic.downcast().ok()
+    }
+
+    /// Sends event to all subscribers of its type
+    pub fn publish<T: Clone + Send + 'static>(&self, event: T) {
+        self.metrics.published.fetch_add(1, Ordering::Relaxed);
+
+        let topic = match self.topic::<T>() {
+            Some(topic) => topic,
+            None => return,
+        };
+
+        let mut subscribers = topic.0.lock().unwrap();
+        let mut overflowed = Vec::new();
+
+        for (key, queue) in subscribers.iter() {
+            let mut state = queue.0.lock().unwrap();
+
+            if state.closed {
+                continue;
+            }
+
+            if state.events.len() >= self.config.capacity {
+                match self.config.overflow {
+                    OverflowPolicy::DropOldest => {
+                        state.events.pop_front();
+                        self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                    OverflowPolicy::Disconnect => {
+                        overflowed.push(key);
+                        continue;
+                    }
+                }
+            }
+
+            state.events.push_back(event.clone());
+            self.metrics.delivered.fetch_add(1, Ordering::Relaxed);
+
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+
+        for key in overflowed {
+            log::warn!("Subscriber is disconnected as its queue is full");
+
+            subscribers.remove(key).close();
+            self.metrics.disconnected.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns stream of events of given type published since now
+    pub fn subscribe<T: Clone + Send + 'static>(&self) -> impl Stream<Item = T> {
+        let topic = match self.topic::<T>() {
+            Some(topic) => topic,
+            None => {
+                let mut topics = self.topics.write().unwrap();
+
+                let topic = topics
+                    .entry(TypeId::of::<T>())
+                    .or_insert_with(|| Arc::new(Topic::<T>(Default::default())))
+                    .clone();
+
+                topic.downcast().ok().unwrap()
+            }
+        };
+
+        let queue = Arc::new(Queue::new(self.config.capacity));
+        let key = topic.0.lock().unwrap().insert(queue.clone());
+
+        self.metrics.subscribed.fetch_add(1, Ordering::Relaxed);
+
+        BrokerStream {
+            key,
+            queue,
+            topic,
+            metrics: self.metrics.clone(),
+        }
+    }
+
+    /// Returns configuration and counters, one per line
+    pub fn report(&self) -> String {
+        let m = &self.metrics;
+        let subscribed = m.subscribed.load(Ordering::Relaxed);
+        let unsubscribed = m.unsubscribed.load(Ordering::Relaxed);
+
+        let mut report = String::new();
+
+        let _ = writeln!(report, "capacity: {}", self.config.capacity);
+        let _ = writeln!(report, "overflow policy: {}", self.config.overflow.as_str());
+        let _ = writeln!(report, "subscribers: {}", subscribed - unsubscribed);
+        let _ = writeln!(report, "published: {}", m.published.load(Ordering::Relaxed));
+        let _ = writeln!(report, "delivered: {}", m.delivered.load(Ordering::Relaxed));
+        let _ = writeln!(report, "dropped: {}", m.dropped.load(Ordering::Relaxed));
+        let _ = writeln!(
+            report,
+            "disconnected: {}",
+            m.disconnected.load(Ordering::Relaxed)
+        );
+
+        report
+    }
+}
+
+struct BrokerStream<T: Send + 'static> {
+    key: usize,
+    queue: Arc<Queue<T>>,
+    topic: Arc<Topic<T>>,
+    metrics: Arc<Metrics>,
+}
+
+impl<T: Send + 'static> Drop for BrokerStream<T> {
+    fn drop(&mut self) {
+        let mut subscribers = self.topic.0.lock().unwrap();
+
+        // Disconnected subscriber is already removed and its key could be reused
+        if subscribers
+            .get(self.key)
+            .is_some_and(|q| Arc::ptr_eq(q, &self.queue))
+        {
+            subscribers.remove(self.key);
+        }
+
+        self.metrics.unsubscribed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl<T: Send + 'static> Stream for BrokerStream<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = self.queue.0.lock().unwrap();
+
+        if let Some(event) = state.events.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+
+        if state.closed {
+            return Poll::Ready(None);
+        }
+
+        state.waker = Some(cx.waker().clone());
+
+        Poll::Pending
+    }
+}
@@ -0,0 +1,162 @@
+//! Connects service instances sharing database: every instance knows which one holds
+//! session of opener, commands are routed to it and events reach subscribers of all instances
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use mongodb::Database;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::broker::Broker;
+use crate::graphql::{
+    OpenerCommandResult, OpenerConnectionChanged, OpenerEvent, OpenerUpdateProgress,
+};
+
+mod memory;
+mod mongo;
+
+pub use memory::MemoryBus;
+pub use mongo::MongoBus;
+
+/// Instance refreshes registered sessions with this interval, session which is not
+/// refreshed for several intervals belongs to stopped instance
+pub const SESSION_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Bus shared by server actor and its tasks with broker of local subscribers
+#[derive(Clone)]
+pub struct Cluster {
+    bus: Arc<dyn ClusterBus>,
+    broker: Broker,
+}
+
+impl Cluster {
+    pub fn new(bus: Arc<dyn ClusterBus>, broker: Broker) -> Self {
+        Cluster { bus, broker }
+    }
+
+    /// Publishes event to subscribers of this and other instances
+    pub(crate) async fn publish<E: Into<ClusterEvent>>(&self, event: E) {
+        let event = event.into();
+
+        self.publish_local(event.clone());
+
+        if let Err(e) = self.bus.broadcast(ClusterMessage::Event(event)).await {
+            log::error!("Failed to broadcast event to cluster: {}", e);
+        }
+    }
+
+    /// Passes event to subscribers of this instance
+    pub(crate) fn publish_local(&self, event: ClusterEvent) {
+        match event.0 {
+            EventKind::ConnectionChanged(e) => self.broker.publish(e),
+            EventKind::CommandResult(e) => self.broker.publish(e),
+            EventKind::UpdateProgress(e) => self.broker.publish(e),
+            EventKind::Event(e) => self.broker.publish(e),
+        }
+    }
+}
+
+impl Deref for Cluster {
+    type Target = dyn ClusterBus;
+
+    fn deref(&self) -> &Self::Target {
+        self.bus.as_ref()
+    }
+}
+
+/// Message passed between service instances
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", content = "data")]
+pub enum ClusterMessage {
+    /// Asks instance holding session of opener to send its queued commands
+    Dispatch {
+        #[serde(rename = "serialNumber")]
+        serial_number: String,
+    },
+    /// Event for GraphQL subscribers of instance
+    Event(ClusterEvent),
+}
+
+/// Event published by one instance for subscribers of all instances
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClusterEvent(EventKind);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", content = "event")]
+enum EventKind {
+    ConnectionChanged(OpenerConnectionChanged),
+    CommandResult(OpenerCommandResult),
+    UpdateProgress(OpenerUpdateProgress),
+    Event(OpenerEvent),
+}
+
+impl From<OpenerConnectionChanged> for ClusterEvent {
+    fn from(event: OpenerConnectionChanged) -> Self {
+        ClusterEvent(EventKind::ConnectionChanged(event))
+    }
+}
+
+impl From<OpenerCommandResult> for ClusterEvent {
+    fn from(event: OpenerCommandResult) -> Self {
+        ClusterEvent(EventKind::CommandResult(event))
+    }
+}
+
+impl From<OpenerUpdateProgress> for ClusterEvent {
+    fn from(event: OpenerUpdateProgress) -> Self {
+        ClusterEvent(EventKind::UpdateProgress(event))
+    }
+}
+
+impl From<OpenerEvent> for ClusterEvent {
+    fn from(event: OpenerEvent) -> Self {
+        ClusterEvent(EventKind::Event(event))
+    }
+}
+
+/// Transport between service instances with registry of opener sessions
+#[async_trait]
+pub trait ClusterBus: Send + Sync {
+    /// Unique id of this instance
+    fn instance_id(&self) -> &str;
+
+    /// Records that session of opener is held by this instance
+    async fn register_session(&self, serial_number: &str) -> Result<()>;
+
+    /// Removes session of opener if it is still held by this instance
+    async fn unregister_session(&self, serial_number: &str) -> Result<()>;
+
+    /// Marks sessions held by this instance alive, it is called with
+    /// [`SESSION_HEARTBEAT_INTERVAL`]
+    async fn refresh_sessions(&self, serial_numbers: &[String]) -> Result<()>;
+
+    /// Returns id of live instance holding session of opener
+    async fn session_owner(&self, serial_number: &str) -> Result<Option<String>>;
+
+    /// Sends message to given instance
+    async fn send(&self, instance_id: &str, message: ClusterMessage) -> Result<()>;
+
+    /// Sends message to all other instances
+    async fn broadcast(&self, message: ClusterMessage) -> Result<()>;
+
+    /// Returns stream of messages sent to this instance by others
+    fn subscribe(&self) -> BoxStream<'static, ClusterMessage>;
+}
+
+/// Creates bus chosen by `CLUSTER_BUS` environment variable: `memory` (default)
+/// for single instance or `mongo` for several instances sharing database
+pub fn init_cluster(db: &Database, broker: Broker) -> Cluster {
+    // Id must be unique and stable across restarts if several instances share database
+    let instance_id = env::var("INSTANCE_ID").unwrap_or_else(|_| "default".to_string());
+
+    let bus: Arc<dyn ClusterBus> = match env::var("CLUSTER_BUS").as_deref() {
+        Ok("mongo") => Arc::new(MongoBus::new(db.clone(), instance_id)),
+        _ => Arc::new(MemoryBus::new(instance_id)),
+    };
+
+    Cluster::new(bus, broker)
+}
@@ -0,0 +1,126 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::channel::mpsc::{self, UnboundedSender};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use super::{ClusterBus, ClusterMessage};
+
+#[derive(Default)]
+struct Hub {
+    /// Instance holding session by serial number of opener
+    sessions: HashMap<String, String>,
+    subscribers: HashMap<String, Vec<UnboundedSender<ClusterMessage>>>,
+}
+
+impl Hub {
+    fn deliver(&mut self, instance_id: &str, message: &ClusterMessage) {
+        if let Some(subscribers) = self.subscribers.get_mut(instance_id) {
+            subscribers.retain(|s| s.unbounded_send(message.clone()).is_ok());
+        }
+    }
+}
+
+/// Bus of instances running in one process, it is used when service has single instance
+#[derive(Clone)]
+pub struct MemoryBus {
+    instance_id: String,
+    hub: Arc<Mutex<Hub>>,
+}
+
+impl MemoryBus {
+    pub fn new(instance_id: String) -> Self {
+        MemoryBus {
+            instance_id,
+            hub: Arc::new(Mutex::new(Hub::default())),
+        }
+    }
+
+    /// Returns bus of another instance connected to the same hub
+    pub fn join(&self, instance_id: String) -> Self {
+        MemoryBus {
+            instance_id,
+            hub: self.hub.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl ClusterBus for MemoryBus {
+    fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    async fn register_session(&self, serial_number: &str) -> Result<()> {
+        let mut hub = self.hub.lock().unwrap();
+
+        hub.sessions
+            .insert(serial_number.to_string(), self.instance_id.clone());
+
+        Ok(())
+    }
+
+    async fn unregister_session(&self, serial_number: &str) -> Result<()> {
+        let mut hub = self.hub.lock().unwrap();
+
+        if hub.sessions.get(serial_number) == Some(&self.instance_id) {
+            hub.sessions.remove(serial_number);
+        }
+
+        Ok(())
+    }
+
+    /// Sessions are lost together with process, so they are always alive
+    async fn refresh_sessions(&self, _: &[String]) -> Result<()> {
+        Ok(())
+    }
+
+    async fn session_owner(&self, serial_number: &str) -> Result<Option<String>> {
+        Ok(self
+            .hub
+            .lock()
+            .unwrap()
+            .sessions
+            .get(serial_number)
+            .cloned())
+    }
+
+    async fn send(&self, instance_id: &str, message: ClusterMessage) -> Result<()> {
+        self.hub.lock().unwrap().deliver(instance_id, &message);
+
+        Ok(())
+    }
+
+    async fn broadcast(&self, message: ClusterMessage) -> Result<()> {
+        let mut hub = self.hub.lock().unwrap();
+
+        let instances: Vec<String> = hub
+            .subscribers
+            .keys()
+            .filter(|id| **id != self.instance_id)
+            .cloned()
+            .collect();
+
+        for instance_id in instances {
+            hub.deliver(&instance_id, &message);
+        }
+
+        Ok(())
+    }
+
+    fn subscribe(&self) -> BoxStream<'static, ClusterMessage> {
+        let (tx, rx) = mpsc::unbounded();
+
+        self.hub
+            .lock()
+            .unwrap()
+            .subscribers
+            .entry(self.instance_id.clone())
+            .or_default()
+            .push(tx);
+
+        rx.boxed()
+    }
+}
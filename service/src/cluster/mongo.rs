@@ -0,0 +1,271 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use bson::oid::ObjectId;
+use futures::lock::Mutex;
+use futures::stream::{self, BoxStream};
+use futures::StreamExt;
+use mongodb::bson::doc;
+use mongodb::options::{FindOptions, UpdateOptions};
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{ClusterBus, ClusterMessage, SESSION_HEARTBEAT_INTERVAL};
+
+/// Interval of polling for messages of other instances
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Session which is not refreshed during this period belongs to stopped instance,
+/// it is also removed by TTL index of `clusterSessions`
+const SESSION_TTL_MS: i64 = 3 * SESSION_HEARTBEAT_INTERVAL.as_millis() as i64;
+
+/// Describes instance holding session of opener
+#[derive(Serialize, Deserialize, Debug)]
+struct ClusterSessionEntity {
+    /// Serial number of opener
+    #[serde(rename = "_id")]
+    serial_number: String,
+    instance: String,
+    #[serde(rename = "updatedAt")]
+    updated_at: bson::DateTime,
+}
+
+/// Describes message sent from one instance to another or to all of them
+#[derive(Serialize, Deserialize, Debug)]
+struct ClusterMessageEntity {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    sender: String,
+    /// Absent for message sent to all instances
+    target: Option<String>,
+    message: bson::Document,
+    #[serde(rename = "createdAt")]
+    created_at: bson::DateTime,
+}
+
+/// Bus of instances sharing database, messages are passed through shared collection
+/// `clusterMessages` and sessions are registered in `clusterSessions`
+#[derive(Clone)]
+pub struct MongoBus {
+    instance_id: String,
+    sessions: Collection<ClusterSessionEntity>,
+    messages: Collection<ClusterMessageEntity>,
+    /// Messages of instance are inserted one by one, so they become visible
+    /// in order of their ids
+    inserting: Arc<Mutex<()>>,
+}
+
+impl MongoBus {
+    pub fn new(db: Database, instance_id: String) -> Self {
+        MongoBus {
+            instance_id,
+            sessions: db.collection("clusterSessions"),
+            messages: db.collection("clusterMessages"),
+            inserting: Arc::new(Mutex::new(())),
+        }
+    }
+
+    async fn insert(&self, target: Option<&str>, message: ClusterMessage) -> Result<()> {
+        let _inserting = self.inserting.lock().await;
+
+        let message = ClusterMessageEntity {
+            id: ObjectId::new(),
+            sender: self.instance_id.clone(),
+            target: target.map(str::to_string),
+            message: bson::to_document(&message)?,
+            created_at: bson::DateTime::now(),
+        };
+
+        self.messages.insert_one(message, None).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ClusterBus for MongoBus {
+    fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    async fn register_session(&self, serial_number: &str) -> Result<()> {
+        let update = doc! {
+            "$set": {
+                "instance": &self.instance_id,
+                "updatedAt": bson::DateTime::now(),
+            }
+        };
+
+        let options = UpdateOptions::builder().upsert(true).build();
+
+        self.sessions
+            .update_one(doc! { "_id": serial_number }, update, options)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn unregister_session(&self, serial_number: &str) -> Result<()> {
+        let filter = doc! {
+            "_id": serial_number,
+            "instance": &self.instance_id,
+        };
+
+        self.sessions.delete_one(filter, None).await?;
+
+        Ok(())
+    }
+
+    async fn refresh_sessions(&self, serial_numbers: &[String]) -> Result<()> {
+        if serial_numbers.is_empty() {
+            return Ok(());
+        }
+
+        let filter = doc! {
+            "_id": { "$in": serial_numbers },
+            "instance": &self.instance_id,
+        };
+
+        let update = doc! {
+            "$set": { "updatedAt": bson::DateTime::now() }
+        };
+
+        let result = self.sessions.update_many(filter, update, None).await?;
+
+        if result.matched_count as usize == serial_numbers.len() {
+            return Ok(());
+        }
+
+        // Sessions removed by TTL index while instance was not able to refresh them
+        // are registered again unless opener is connected to another instance
+        let filter = doc! { "_id": { "$in": serial_numbers } };
+        let mut cursor = self.sessions.find(filter, None).await?;
+
+        let mut registered = Vec::new();
+        while let Some(session) = cursor.next().await {
+            registered.push(session?.serial_number);
+        }
+
+        for serial_number in serial_numbers {
+            if !registered.contains(serial_number) {
+                self.register_session(serial_number).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn session_owner(&self, serial_number: &str) -> Result<Option<String>> {
+        let alive_since = bson::DateTime::now().timestamp_millis() - SESSION_TTL_MS;
+
+        let filter = doc! {
+            "_id": serial_number,
+            "updatedAt": { "$gte": bson::DateTime::from_millis(alive_since) },
+        };
+
+        let session = self.sessions.find_one(filter, None).await?;
+
+        Ok(session.map(|s| s.instance))
+    }
+
+    async fn send(&self, instance_id: &str, message: ClusterMessage) -> Result<()> {
+        self.insert(Some(instance_id), message).await
+    }
+
+    async fn broadcast(&self, message: ClusterMessage) -> Result<()> {
+        self.insert(None, message).await
+    }
+
+    fn subscribe(&self) -> BoxStream<'static, ClusterMessage> {
+        let poller = Poller {
+            instance_id: self.instance_id.clone(),
+            messages: self.messages.clone(),
+            since: since(bson::DateTime::now()),
+            last_read: HashMap::new(),
+            received: VecDeque::new(),
+        };
+
+        stream::unfold(poller, |mut poller| async move {
+            loop {
+                if let Some(message) = poller.received.pop_front() {
+                    return Some((message, poller));
+                }
+
+                tokio::time::sleep(POLL_INTERVAL).await;
+
+                if let Err(e) = poller.poll().await {
+                    log::error!("Failed to read cluster messages: {}", e);
+                }
+            }
+        })
+        .boxed()
+    }
+}
+
+/// Returns the least id of message sent at given time
+fn since(time: bson::DateTime) -> ObjectId {
+    let mut bytes = [0; 12];
+    bytes[..4].copy_from_slice(&((time.timestamp_millis() / 1000) as u32).to_be_bytes());
+
+    ObjectId::from_bytes(bytes)
+}
+
+/// Reads messages sent to instance since it is subscribed
+///
+/// Ids of messages are increasing for each sender, but not across senders
+/// because of clock difference, so the last read id is kept for every sender
+struct Poller {
+    instance_id: String,
+    messages: Collection<ClusterMessageEntity>,
+    /// Messages of senders not read yet are read since this id
+    since: ObjectId,
+    /// The last read message of each sender
+    last_read: HashMap<String, ObjectId>,
+    received: VecDeque<ClusterMessage>,
+}
+
+impl Poller {
+    async fn poll(&mut self) -> Result<()> {
+        let mut known: Vec<&String> = self.last_read.keys().collect();
+        known.push(&self.instance_id);
+
+        let mut unread = vec![doc! {
+            "sender": { "$nin": known },
+            "_id": { "$gt": self.since },
+        }];
+
+        for (sender, last) in &self.last_read {
+            unread.push(doc! {
+                "sender": sender,
+                "_id": { "$gt": last },
+            });
+        }
+
+        // Messages to other instances are read too, so that the last read id
+        // of their sender is moved past them
+        let filter = doc! { "$or": unread };
+
+        let options = FindOptions::builder().sort(doc! { "_id": 1 }).build();
+
+        let mut cursor = self.messages.find(filter, options).await?;
+
+        while let Some(entity) = cursor.next().await {
+            let entity = entity?;
+
+            self.last_read.insert(entity.sender, entity.id);
+
+            if matches!(&entity.target, Some(target) if target != &self.instance_id) {
+                continue;
+            }
+
+            match bson::from_document(entity.message) {
+                Ok(message) => self.received.push_back(message),
+                Err(e) => log::error!("Failed to parse cluster message {}: {}", entity.id, e),
+            }
+        }
+
+        Ok(())
+    }
+}
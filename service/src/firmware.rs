@@ -0,0 +1,72 @@
+//! Firmware files for over-the-air update of controllers
+
+use std::env;
+use std::path::PathBuf;
+
+use actix_web::{web, HttpResponse};
+use anyhow::{bail, Result};
+use lazy_static::lazy_static;
+
+use crate::listener::{ListenerConfig, DEVICE_BIND};
+
+lazy_static! {
+    /// Directory with firmware files
+    static ref FIRMWARE_DIR: String =
+        env::var("FIRMWARE_DIR").unwrap_or_else(|_| "/openers/dist".to_string());
+
+    /// Base URL used by controllers to download firmware files,
+    /// they are served only by listener for controllers
+    static ref FIRMWARE_URL: String = env::var("FIRMWARE_URL").unwrap_or_else(|_| {
+        format!("{}/dist", ListenerConfig::from_env("DEVICE_", DEVICE_BIND).local_url())
+    });
+}
+
+/// Describes firmware file sent to controller in UPDATE command
+pub(crate) struct FirmwareInfo {
+    pub url: String,
+    pub size: u64,
+    pub checksum: String,
+}
+
+fn firmware_path(file: &str) -> Result<PathBuf> {
+    if file.is_empty() || file.starts_with('.') || file.contains(['/', '\\']) {
+        bail!("Wrong firmware file name");
+    }
+
+    Ok(PathBuf::from(FIRMWARE_DIR.as_str()).join(file))
+}
+
+/// Returns download URL, size and sha256 checksum of firmware file
+pub(crate) async fn get_firmware_info(file: &str) -> Result<Option<FirmwareInfo>> {
+    let path = firmware_path(file)?;
+
+    let data = match async_std::fs::read(&path).await {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+        Ok(data) => data,
+    };
+
+    Ok(Some(FirmwareInfo {
+        url: format!("{}/{}", FIRMWARE_URL.trim_end_matches('/'), file),
+        size: data.len() as u64,
+        checksum: sha256::digest_bytes(&data),
+    }))
+}
+
+pub async fn get_firmware(file: web::Path<String>) -> HttpResponse {
+    let path = match firmware_path(&file) {
+        Err(_) => return HttpResponse::BadRequest().finish(),
+        Ok(path) => path,
+    };
+
+    match async_std::fs::read(&path).await {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            log::error!("Failed to read firmware file {}: {}", file, e);
+            HttpResponse::InternalServerError().finish()
+        }
+        Ok(data) => HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .body(data),
+    }
+}
@@ -3,26 +3,39 @@ use async_graphql::*;
 mod auth;
 pub(crate) mod barrier_manufacturer;
 pub(crate) mod barrier_model;
+mod command;
 mod error;
+mod event;
 mod opener;
 pub(crate) mod role;
-pub(crate) mod simple_broker;
+mod schedule;
+mod telemetry;
 pub(crate) mod user;
 
 pub(crate) use error::Error;
 
+pub(crate) use event::{Event, OpenerEvent};
+
 pub(crate) use opener::{
     CommandStatus, CommandType, OpenerCommandResult, OpenerConnectionChanged, OpenerError,
+    OpenerUpdateProgress, TagResult, UpdateStage,
 };
 
 #[derive(MergedObject, Default)]
-pub struct Mutation(auth::AuthMutation, opener::OpenerMutation);
+pub struct Mutation(
+    auth::AuthMutation,
+    opener::OpenerMutation,
+    command::CommandMutation,
+    schedule::ScheduleMutation,
+);
 
 #[derive(MergedObject, Default)]
 pub struct Query(
     role::RoleQuery,
     user::UserQuery,
     opener::OpenerQuery,
+    command::CommandQuery,
+    schedule::ScheduleQuery,
     barrier_model::BarrierModelQuery,
     barrier_manufacturer::BarrierManufacturerQuery,
 );
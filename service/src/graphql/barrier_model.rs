@@ -13,7 +13,7 @@ use std::convert::TryFrom;
 use std::convert::TryInto;
 
 /// Algorithms supported by barriers
-#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug)]
 pub enum BarrierAlgorithm {
     /// Algorithm with impulse for open and close at the same pin
     OpenClose,
@@ -0,0 +1,373 @@
+use super::error::{Error, *};
+use super::opener::{CommandStatus, CommandType, OpenerError};
+use crate::auth::Claims;
+use crate::graphql::auth::{check_token, CheckTokenResult};
+use crate::graphql::user::{NestedUserResult, UserLoader};
+use crate::persistence::command::{
+    cancel_queued_command, get_command_by_id, get_commands_by_sn, get_queue_position, CommandEntity,
+};
+use crate::persistence::opener::get_opener_by_sn;
+use async_graphql::dataloader::DataLoader;
+use async_graphql::*;
+use bson::oid::ObjectId;
+use mongodb::Database;
+use std::convert::{TryFrom, TryInto};
+use std::str::FromStr;
+
+const COMMANDS_PAGE_MAX_SIZE: u32 = 100;
+
+/// Describes command sent to controller and its outcome
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub(crate) struct Command {
+    id: ID,
+    serial_number: String,
+    command_type: CommandType,
+
+    /// Arguments sent to controller
+    arguments: Json<serde_json::Value>,
+
+    status: CommandStatus,
+
+    /// Error returned by controller or set by service on timeout or disconnect
+    error: Option<OpenerError>,
+
+    created_at: i64,
+    completed_at: Option<i64>,
+
+    #[graphql(skip)]
+    user_id: Option<String>,
+}
+
+#[ComplexObject]
+impl Command {
+    /// Position of queued command in opener's queue starting from 1
+    async fn position(&self, ctx: &Context<'_>) -> Result<Option<u64>> {
+        if self.status != CommandStatus::Queued {
+            return Ok(None);
+        }
+
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        let id = ObjectId::from_str(&self.id)?;
+
+        let position = get_queue_position(db, &self.serial_number, &id)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(Some(position))
+    }
+
+    /// User who sent command
+    async fn issuer(&self, ctx: &Context<'_>) -> Option<NestedUserResult> {
+        let user_id = self.user_id.as_ref()?;
+
+        let data_loader = ctx
+            .data::<DataLoader<UserLoader>>()
+            .expect("Can't get user data loader");
+
+        let user = match data_loader.load_one(user_id.clone()).await {
+            Err(e) => return Some(NestedUserResult::InternalServerError(e.message.into())),
+            Ok(u) => u,
+        };
+
+        match user {
+            Some(user) => Some(NestedUserResult::User(user)),
+            None => Some(NestedUserResult::NotFoundError(NotFoundError::new(
+                "Not found",
+                "User",
+            ))),
+        }
+    }
+}
+
+#[derive(Union)]
+enum CommandResult {
+    Command(Box<Command>),
+    InternalServerError(InternalServerError),
+    UnauthorizedError(UnauthorizedError),
+    PermissionDeniedError(PermissionDeniedError),
+    TokenIsExpiredError(TokenIsExpiredError),
+    IsInvalidError(IsInvalidError),
+}
+
+impl From<Error> for CommandResult {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::InternalServerError(e) => CommandResult::InternalServerError(e),
+            Error::UnauthorizedError(e) => CommandResult::UnauthorizedError(e),
+            Error::PermissionDeniedError(e) => CommandResult::PermissionDeniedError(e),
+            Error::TokenIsExpiredError(e) => CommandResult::TokenIsExpiredError(e),
+            Error::IsInvalidError(e) => CommandResult::IsInvalidError(e),
+            _ => panic!("Can not cast from Error to CommandResult"),
+        }
+    }
+}
+
+/// Page of commands ordered from newest to oldest
+#[derive(SimpleObject)]
+pub(crate) struct Commands {
+    items: Vec<Command>,
+
+    /// Id of the last command on page, it is used as `after` to get next page
+    end_cursor: Option<ID>,
+
+    has_next_page: bool,
+}
+
+#[derive(Union)]
+pub(crate) enum CommandsResult {
+    Commands(Commands),
+    InternalServerError(InternalServerError),
+    IsInvalidError(IsInvalidError),
+}
+
+/// Returns page of commands sent to opener
+pub(crate) async fn get_commands_page(
+    db: &Database,
+    serial_number: &str,
+    first: u32,
+    after: Option<ID>,
+) -> CommandsResult {
+    if first == 0 || first > COMMANDS_PAGE_MAX_SIZE {
+        return CommandsResult::IsInvalidError(IsInvalidError::new(
+            "Page size must be from 1 to 100",
+            "first",
+        ));
+    }
+
+    if let Some(after) = after.as_ref() {
+        if ObjectId::from_str(after).is_err() {
+            return CommandsResult::IsInvalidError(IsInvalidError::new("Invalid param", "after"));
+        }
+    }
+
+    // One more command is requested to know if there is next page
+    let commands = match get_commands_by_sn(
+        db,
+        serial_number,
+        first as i64 + 1,
+        after.as_ref().map(|a| a.as_str()),
+    )
+    .await
+    {
+        Err(e) => {
+            log::error!("Failed to get commands of opener {}: {}", serial_number, e);
+            return CommandsResult::InternalServerError(e.into());
+        }
+        Ok(c) => c,
+    };
+
+    let has_next_page = commands.len() > first as usize;
+
+    let commands: Result<Vec<Command>, _> = commands
+        .iter()
+        .take(first as usize)
+        .map(Command::try_from)
+        .collect();
+
+    let commands = match commands {
+        Err(e) => {
+            log::error!("Failed to convert commands: {}", e);
+            return CommandsResult::InternalServerError(e.into());
+        }
+        Ok(c) => c,
+    };
+
+    CommandsResult::Commands(Commands {
+        end_cursor: commands.last().map(|c| c.id.clone()),
+        items: commands,
+        has_next_page,
+    })
+}
+
+#[derive(Union)]
+enum CancelCommandResult {
+    Command(Box<Command>),
+    InternalServerError(InternalServerError),
+    UnauthorizedError(UnauthorizedError),
+    PermissionDeniedError(PermissionDeniedError),
+    TokenIsExpiredError(TokenIsExpiredError),
+    NotFoundError(NotFoundError),
+    IsInvalidError(IsInvalidError),
+}
+
+impl From<Error> for CancelCommandResult {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::InternalServerError(e) => CancelCommandResult::InternalServerError(e),
+            Error::UnauthorizedError(e) => CancelCommandResult::UnauthorizedError(e),
+            Error::PermissionDeniedError(e) => CancelCommandResult::PermissionDeniedError(e),
+            Error::TokenIsExpiredError(e) => CancelCommandResult::TokenIsExpiredError(e),
+            Error::NotFoundError(e) => CancelCommandResult::NotFoundError(e),
+            Error::IsInvalidError(e) => CancelCommandResult::IsInvalidError(e),
+            _ => panic!("Can not cast from Error to CancelCommandResult"),
+        }
+    }
+}
+
+/// Non-admin users can view and control only commands of theirs openers
+async fn can_access(
+    db: &Database,
+    claims: &Claims,
+    role_name: &str,
+    command: &CommandEntity,
+) -> Result<bool, Error> {
+    if role_name == "admin" {
+        return Ok(true);
+    }
+
+    let opener = match get_opener_by_sn(db, &command.serial_number).await {
+        Err(e) => return Err(Error::InternalServerError(e.into())),
+        Ok(o) => o,
+    };
+
+    let user_id = opener.and_then(|o| o.user_id).map(|id| id.to_string());
+
+    Ok(user_id.as_ref() == Some(&claims.user_id))
+}
+
+#[derive(Default)]
+pub(super) struct CommandMutation;
+
+#[Object]
+impl CommandMutation {
+    /// Removes command from opener's queue if it is not sent yet
+    async fn cancel_command(&self, ctx: &Context<'_>, id: ID) -> CancelCommandResult {
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        let token: (&Claims, String) =
+            match check_token(ctx, |role| role.access_rights.openers.edit).await {
+                CheckTokenResult::Err(e) => return e.into(),
+                CheckTokenResult::Ok { claims, role_name } => (claims, role_name),
+            };
+
+        let id = match ObjectId::from_str(&id) {
+            Err(_) => {
+                return CancelCommandResult::IsInvalidError(IsInvalidError::new(
+                    "Invalid param",
+                    "id",
+                ))
+            }
+            Ok(id) => id,
+        };
+
+        let command = match get_command_by_id(db, &id).await {
+            Err(e) => return CancelCommandResult::InternalServerError(e.into()),
+            Ok(c) => c,
+        };
+
+        let command = match command {
+            Some(c) => c,
+            None => {
+                return CancelCommandResult::NotFoundError(NotFoundError::new(
+                    "Not found",
+                    "Command",
+                ))
+            }
+        };
+
+        match can_access(db, token.0, &token.1, &command).await {
+            Err(e) => return e.into(),
+            Ok(false) => {
+                return CancelCommandResult::PermissionDeniedError("Permission denied".into())
+            }
+            Ok(true) => {}
+        }
+
+        match cancel_queued_command(db, &id).await {
+            Err(e) => return CancelCommandResult::InternalServerError(e.into()),
+            Ok(false) => {
+                return CancelCommandResult::IsInvalidError(IsInvalidError::new(
+                    "Command is not queued",
+                    "id",
+                ))
+            }
+            Ok(true) => {}
+        }
+
+        let command = match get_command_by_id(db, &id).await {
+            Err(e) => return CancelCommandResult::InternalServerError(e.into()),
+            Ok(c) => c.unwrap(),
+        };
+
+        let command = match Command::try_from(&command) {
+            Err(e) => {
+                log::error!("Failed to convert command {}", e);
+                return CancelCommandResult::InternalServerError(e.into());
+            }
+            Ok(c) => c,
+        };
+
+        CancelCommandResult::Command(Box::new(command))
+    }
+}
+
+#[derive(Default)]
+pub(super) struct CommandQuery;
+
+#[Object]
+impl CommandQuery {
+    async fn command(&self, ctx: &Context<'_>, id: ID) -> Option<CommandResult> {
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        let token: (&Claims, String) =
+            match check_token(ctx, |role| role.access_rights.openers.view).await {
+                CheckTokenResult::Err(e) => return Some(e.into()),
+                CheckTokenResult::Ok { claims, role_name } => (claims, role_name),
+            };
+
+        let id = match ObjectId::from_str(&id) {
+            Err(_) => {
+                return Some(CommandResult::IsInvalidError(IsInvalidError::new(
+                    "Invalid param",
+                    "id",
+                )))
+            }
+            Ok(id) => id,
+        };
+
+        let command = match get_command_by_id(db, &id).await {
+            Err(e) => return Some(CommandResult::InternalServerError(e.into())),
+            Ok(c) => c?,
+        };
+
+        match can_access(db, token.0, &token.1, &command).await {
+            Err(e) => return Some(e.into()),
+            Ok(false) => {
+                return Some(CommandResult::PermissionDeniedError(
+                    "Permission denied".into(),
+                ))
+            }
+            Ok(true) => {}
+        }
+
+        let command = match Command::try_from(&command) {
+            Err(e) => {
+                log::error!("Failed to convert command {}", e);
+                return Some(CommandResult::InternalServerError(e.into()));
+            }
+            Ok(c) => c,
+        };
+
+        Some(CommandResult::Command(Box::new(command)))
+    }
+}
+
+impl TryFrom<&CommandEntity> for Command {
+    type Error = &'static str;
+
+    fn try_from(command: &CommandEntity) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: ID::from(command.id),
+            serial_number: command.serial_number.clone(),
+            command_type: command.command_type.as_str().try_into()?,
+            arguments: Json(bson::Bson::Document(command.arguments.clone()).into_relaxed_extjson()),
+            status: command.status.as_str().try_into()?,
+            error: command.error.as_ref().map(|e| e.into()),
+            created_at: command.created_at.timestamp_millis(),
+            completed_at: command.completed_at.map(|t| t.timestamp_millis()),
+            user_id: command.user_id.map(|id| id.to_string()),
+        })
+    }
+}
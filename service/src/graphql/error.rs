@@ -141,6 +141,19 @@ impl<T: Display> From<T> for DeviceIsNotConnectedError {
     }
 }
 
+#[derive(SimpleObject)]
+pub(crate) struct CommandIsNotSupportedError {
+    pub message: String,
+}
+
+impl<T: Display> From<T> for CommandIsNotSupportedError {
+    fn from(e: T) -> Self {
+        Self {
+            message: e.to_string(),
+        }
+    }
+}
+
 #[derive(Interface)]
 #[graphql(field(name = "message", type = "String"))]
 #[allow(clippy::enum_variant_names)]
@@ -155,4 +168,5 @@ pub(crate) enum Error {
     NoUpdateDataProvidedError(NoUpdateDataProvidedError),
     DeviceIsBusyError(DeviceIsBusyError),
     DeviceIsNotConnectedError(DeviceIsNotConnectedError),
+    CommandIsNotSupportedError(CommandIsNotSupportedError),
 }
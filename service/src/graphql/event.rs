@@ -0,0 +1,158 @@
+use super::error::*;
+use crate::persistence::event::{get_events_by_sn, EventEntity};
+use async_graphql::*;
+use bson::oid::ObjectId;
+use mongodb::Database;
+use serde::{Deserialize, Serialize};
+use std::convert::{TryFrom, TryInto};
+use std::str::FromStr;
+
+const EVENTS_PAGE_MAX_SIZE: u32 = 100;
+
+/// Describes types of events occurred with controller
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum EventType {
+    /// Controller sent message breaking protocol, connection is closed
+    ProtocolViolation,
+    /// Controller connected while another connection with its serial number is active
+    PossibleClone,
+    /// Barrier is opened by remote control
+    OpenedByRemote,
+    /// Tag is scanned by reader
+    TagScanned,
+    /// Obstacle is detected in barrier's way
+    ObstacleDetected,
+    /// Controller is powered on after power loss
+    PowerRestored,
+}
+
+impl TryFrom<&str> for EventType {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "PROTOCOL_VIOLATION" => Ok(EventType::ProtocolViolation),
+            "POSSIBLE_CLONE" => Ok(EventType::PossibleClone),
+            "OPENED_BY_REMOTE" => Ok(EventType::OpenedByRemote),
+            "TAG_SCANNED" => Ok(EventType::TagScanned),
+            "OBSTACLE_DETECTED" => Ok(EventType::ObstacleDetected),
+            "POWER_RESTORED" => Ok(EventType::PowerRestored),
+            _ => Err("Wrong event type"),
+        }
+    }
+}
+
+/// Describes event occurred with controller
+#[derive(SimpleObject, Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Event {
+    id: ID,
+    serial_number: String,
+    event_type: EventType,
+    description: String,
+
+    /// Additional data depending on event type
+    data: Json<serde_json::Value>,
+
+    created_at: i64,
+}
+
+/// Event published to subscribers of `openerEvents`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct OpenerEvent {
+    pub serial_number: String,
+    pub event: Event,
+    /// Owner of opener
+    pub user_id: Option<String>,
+}
+
+/// Page of events ordered from newest to oldest
+#[derive(SimpleObject)]
+pub(crate) struct Events {
+    items: Vec<Event>,
+
+    /// Id of the last event on page, it is used as `after` to get next page
+    end_cursor: Option<ID>,
+
+    has_next_page: bool,
+}
+
+#[derive(Union)]
+pub(crate) enum EventsResult {
+    Events(Events),
+    InternalServerError(InternalServerError),
+    IsInvalidError(IsInvalidError),
+}
+
+/// Returns page of events of opener
+pub(crate) async fn get_events_page(
+    db: &Database,
+    serial_number: &str,
+    first: u32,
+    after: Option<ID>,
+) -> EventsResult {
+    if first == 0 || first > EVENTS_PAGE_MAX_SIZE {
+        return EventsResult::IsInvalidError(IsInvalidError::new(
+            "Page size must be from 1 to 100",
+            "first",
+        ));
+    }
+
+    if let Some(after) = after.as_ref() {
+        if ObjectId::from_str(after).is_err() {
+            return EventsResult::IsInvalidError(IsInvalidError::new("Invalid param", "after"));
+        }
+    }
+
+    // One more event is requested to know if there is next page
+    let events = match get_events_by_sn(
+        db,
+        serial_number,
+        first as i64 + 1,
+        after.as_ref().map(|a| a.as_str()),
+    )
+    .await
+    {
+        Err(e) => {
+            log::error!("Failed to get events of opener {}: {}", serial_number, e);
+            return EventsResult::InternalServerError(e.into());
+        }
+        Ok(e) => e,
+    };
+
+    let has_next_page = events.len() > first as usize;
+
+    let events: Result<Vec<Event>, _> = events
+        .iter()
+        .take(first as usize)
+        .map(Event::try_from)
+        .collect();
+
+    let events = match events {
+        Err(e) => {
+            log::error!("Failed to convert events: {}", e);
+            return EventsResult::InternalServerError(e.into());
+        }
+        Ok(e) => e,
+    };
+
+    EventsResult::Events(Events {
+        end_cursor: events.last().map(|e| e.id.clone()),
+        items: events,
+        has_next_page,
+    })
+}
+
+impl TryFrom<&EventEntity> for Event {
+    type Error = &'static str;
+
+    fn try_from(event: &EventEntity) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: ID::from(event.id),
+            serial_number: event.serial_number.clone(),
+            event_type: event.event_type.as_str().try_into()?,
+            description: event.description.clone(),
+            data: Json(bson::Bson::Document(event.data.clone()).into_relaxed_extjson()),
+            created_at: event.created_at.timestamp_millis(),
+        })
+    }
+}
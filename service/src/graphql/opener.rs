@@ -1,32 +1,50 @@
 use crate::auth::Claims;
+use crate::broker::Broker;
+use crate::firmware::get_firmware_info;
 use crate::graphql::auth::{check_token, CheckTokenResult};
 use crate::graphql::error::{Error, *};
-use crate::graphql::simple_broker::SimpleBroker;
 use crate::graphql::user::{NestedUserResult, UserLoader};
 use crate::persistence::barrier_model::get_barrier_model_by_id;
 use crate::persistence::opener::{
-    create_opener, get_opener_by_id, get_opener_by_sn, get_openers, set_command_to_opener,
-    update_opener, NewOpenerEntity, OpenerEntity, OpenerErrorEntity, UpdateOpenerEntity,
+    create_opener, get_opener_by_id, get_opener_by_sn, get_openers, regenerate_opener_secret,
+    set_command_to_opener, update_opener, NewOpenerEntity, OpenerEntity, OpenerErrorEntity,
+    OpenerInfoEntity, UpdateOpenerEntity, UpdateProgressEntity,
 };
 use crate::persistence::role::{get_role_by_id, RoleEntity};
-use crate::server::OpenerServer;
+use crate::server::{supports_command, OpenerServer, MAX_QUEUED_COMMANDS};
 use actix::prelude::*;
 use async_graphql::dataloader::DataLoader;
 use async_graphql::Context;
 use async_graphql::*;
 use futures::{Stream, StreamExt};
 use mongodb::Database;
+use serde::{Deserialize, Serialize};
 use std::convert::{TryFrom, TryInto};
 
-use super::barrier_model::{BarrierModelLoader, NestedBarrierModelResult};
+use super::barrier_model::{BarrierAlgorithm, BarrierModelLoader, NestedBarrierModelResult};
+use super::command::{get_commands_page, CommandsResult};
+use super::event::{get_events_page, Event, EventsResult, OpenerEvent};
+use super::schedule::{get_schedules, SchedulesResult};
+use super::telemetry::{get_telemetry_history, Telemetry, TelemetryHistoryResult};
+use crate::persistence::command::count_queued_commands;
+
+/// Maximum time in seconds to keep command for disconnected opener
+const MAX_DELIVERY_PERIOD: u32 = 7 * 24 * 3600;
 
 /// Describes statuses of commands for controller
-#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug)]
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum CommandStatus {
     Ready,
+    /// Command waits in opener's queue
+    Queued,
+    /// Command is delivered to controller and waits for reply
     Pending,
     Success,
     Failed,
+    /// Queued command is cancelled by user
+    Cancelled,
+    /// Command was not delivered before its expiry
+    Expired,
 }
 
 impl TryFrom<&str> for CommandStatus {
@@ -35,22 +53,27 @@ impl TryFrom<&str> for CommandStatus {
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         match value {
             "READY" => Ok(CommandStatus::Ready),
+            "QUEUED" => Ok(CommandStatus::Queued),
             "PENDING" => Ok(CommandStatus::Pending),
             "SUCCESS" => Ok(CommandStatus::Success),
             "FAILED" => Ok(CommandStatus::Failed),
+            "CANCELLED" => Ok(CommandStatus::Cancelled),
+            "EXPIRED" => Ok(CommandStatus::Expired),
             _ => Err("Wrong command status"),
         }
     }
 }
 
 /// Describes types of commands for controller
-#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug)]
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum CommandType {
     Info,
     Set,
     AddTags,
     RemoveTags,
     Update,
+    Open,
+    Close,
 }
 
 impl TryFrom<&str> for CommandType {
@@ -63,18 +86,79 @@ impl TryFrom<&str> for CommandType {
             "ADD_TAGS" => Ok(CommandType::AddTags),
             "REMOVE_TAGS" => Ok(CommandType::RemoveTags),
             "UPDATE" => Ok(CommandType::Update),
+            "OPEN" => Ok(CommandType::Open),
+            "CLOSE" => Ok(CommandType::Close),
             _ => Err("Wrong command type"),
         }
     }
 }
 
-/// Describes error data returned by controller
+/// Describes stages of firmware update on controller
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum UpdateStage {
+    Downloading,
+    Verifying,
+    Installing,
+}
+
+impl TryFrom<&str> for UpdateStage {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "DOWNLOADING" => Ok(UpdateStage::Downloading),
+            "VERIFYING" => Ok(UpdateStage::Verifying),
+            "INSTALLING" => Ok(UpdateStage::Installing),
+            _ => Err("Wrong update stage"),
+        }
+    }
+}
+
+/// Describes progress of firmware update reported by controller
 #[derive(SimpleObject, Debug, Clone)]
+pub(crate) struct UpdateProgress {
+    pub stage: UpdateStage,
+    pub percent: u32,
+    pub changed_at: i64,
+}
+
+/// Describes result of processing of single tag by controller
+#[derive(SimpleObject, Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct TagResult {
+    pub tag: String,
+
+    /// Error code, null if tag is processed successfully
+    pub code: Option<u32>,
+}
+
+/// Describes error data returned by controller
+#[derive(SimpleObject, Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct OpenerError {
     pub serial_number: String,
     pub code: u32,
     pub description: String,
     pub details: Option<String>,
+
+    /// Per tag results for failed ADD_TAGS and REMOVE_TAGS commands
+    pub tags: Option<Vec<TagResult>>,
+}
+
+/// Describes data returned by controller in reply to INFO command
+#[derive(SimpleObject, Debug, Clone)]
+pub(crate) struct OpenerInfo {
+    /// Firmware version
+    pub version: String,
+
+    /// Seconds since controller start
+    pub uptime: i64,
+
+    /// Barrier model id stored on controller
+    pub barrier_model_id: String,
+
+    /// Barrier algorithm stored on controller (null if controller sent unknown one)
+    pub barrier_algorithm: Option<BarrierAlgorithm>,
+
+    pub received_at: i64,
 }
 
 #[derive(SimpleObject)]
@@ -89,15 +173,28 @@ pub(crate) struct Opener {
     lng: Option<f64>,
     login: String,
     password: String,
+
+    /// Secret of controller to authenticate in HELLO,
+    /// it is returned only by createOpener and regenerateOpenerSecret
+    secret: Option<String>,
+
+    /// Version of protocol negotiated in the last HELLO
+    protocol_version: Option<u32>,
+
+    /// Commands supported by controller according to the last HELLO
+    capabilities: Option<Vec<String>>,
+
     connected: bool,
     created_at: i64,
     updated_at: Option<i64>,
     last_error: Option<OpenerError>,
     last_command_type: Option<CommandType>,
     command_status: CommandStatus,
+    info: Option<OpenerInfo>,
+    update_progress: Option<UpdateProgress>,
 
-    #[graphql(skip)]
-    nonce: Option<String>,
+    /// The last TELEMETRY of controller
+    telemetry: Option<Telemetry>,
 
     #[graphql(skip)]
     barrier_model_id: Option<String>,
@@ -108,6 +205,50 @@ pub(crate) struct Opener {
 
 #[ComplexObject]
 impl Opener {
+    /// Commands sent to opener from newest to oldest
+    async fn commands(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 20)] first: u32,
+        #[graphql(desc = "Id of the last command from previous page")] after: Option<ID>,
+    ) -> CommandsResult {
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        get_commands_page(db, &self.serial_number, first, after).await
+    }
+
+    /// Events occurred with opener from newest to oldest
+    async fn events(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 20)] first: u32,
+        #[graphql(desc = "Id of the last event from previous page")] after: Option<ID>,
+    ) -> EventsResult {
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        get_events_page(db, &self.serial_number, first, after).await
+    }
+
+    /// Schedules of commands sent to opener
+    async fn schedules(&self, ctx: &Context<'_>) -> SchedulesResult {
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        get_schedules(db, &self.serial_number).await
+    }
+
+    /// Telemetry of opener from oldest to newest, time is in milliseconds
+    async fn telemetry_history(
+        &self,
+        ctx: &Context<'_>,
+        from: i64,
+        #[graphql(desc = "Current time if it is not given")] to: Option<i64>,
+        #[graphql(default = 100)] first: u32,
+    ) -> TelemetryHistoryResult {
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        get_telemetry_history(db, &self.serial_number, from, to, first).await
+    }
+
     async fn owner(&self, ctx: &Context<'_>) -> Option<NestedUserResult> {
         self.user_id.as_ref()?;
 
@@ -208,6 +349,30 @@ impl From<Error> for OpenersResult {
     }
 }
 
+#[derive(Union)]
+enum RegenerateOpenerSecretResult {
+    Opener(Box<Opener>),
+    InternalServerError(InternalServerError),
+    UnauthorizedError(UnauthorizedError),
+    PermissionDeniedError(PermissionDeniedError),
+    TokenIsExpiredError(TokenIsExpiredError),
+    NotFoundError(NotFoundError),
+}
+
+impl From<Error> for RegenerateOpenerSecretResult {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::InternalServerError(e) => RegenerateOpenerSecretResult::InternalServerError(e),
+            Error::UnauthorizedError(e) => RegenerateOpenerSecretResult::UnauthorizedError(e),
+            Error::PermissionDeniedError(e) => {
+                RegenerateOpenerSecretResult::PermissionDeniedError(e)
+            }
+            Error::TokenIsExpiredError(e) => RegenerateOpenerSecretResult::TokenIsExpiredError(e),
+            _ => panic!("Can not cast from Error to RegenerateOpenerSecretResult"),
+        }
+    }
+}
+
 #[derive(Union)]
 enum CreateOpenerResult {
     Opener(Box<Opener>),
@@ -270,6 +435,7 @@ enum SetParamsCommandResult {
     DeviceIsBusyError(DeviceIsBusyError),
     DeviceIsNotConnectedError(DeviceIsNotConnectedError),
     NoUpdateDataProvidedError(NoUpdateDataProvidedError),
+    CommandIsNotSupportedError(CommandIsNotSupportedError),
 }
 
 impl From<Error> for SetParamsCommandResult {
@@ -288,11 +454,148 @@ impl From<Error> for SetParamsCommandResult {
             Error::NoUpdateDataProvidedError(e) => {
                 SetParamsCommandResult::NoUpdateDataProvidedError(e)
             }
+            Error::CommandIsNotSupportedError(e) => {
+                SetParamsCommandResult::CommandIsNotSupportedError(e)
+            }
             _ => panic!("Can not cast from Error to SetParamsCommandResult"),
         }
     }
 }
 
+#[derive(Union)]
+enum InfoCommandResult {
+    Opener(Box<Opener>),
+    InternalServerError(InternalServerError),
+    UnauthorizedError(UnauthorizedError),
+    PermissionDeniedError(PermissionDeniedError),
+    TokenIsExpiredError(TokenIsExpiredError),
+    NotFoundError(NotFoundError),
+    DeviceIsBusyError(DeviceIsBusyError),
+    DeviceIsNotConnectedError(DeviceIsNotConnectedError),
+    CommandIsNotSupportedError(CommandIsNotSupportedError),
+}
+
+impl From<Error> for InfoCommandResult {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::InternalServerError(e) => InfoCommandResult::InternalServerError(e),
+            Error::UnauthorizedError(e) => InfoCommandResult::UnauthorizedError(e),
+            Error::PermissionDeniedError(e) => InfoCommandResult::PermissionDeniedError(e),
+            Error::TokenIsExpiredError(e) => InfoCommandResult::TokenIsExpiredError(e),
+            Error::NotFoundError(e) => InfoCommandResult::NotFoundError(e),
+            Error::DeviceIsBusyError(e) => InfoCommandResult::DeviceIsBusyError(e),
+            Error::DeviceIsNotConnectedError(e) => InfoCommandResult::DeviceIsNotConnectedError(e),
+            Error::CommandIsNotSupportedError(e) => {
+                InfoCommandResult::CommandIsNotSupportedError(e)
+            }
+            _ => panic!("Can not cast from Error to InfoCommandResult"),
+        }
+    }
+}
+
+#[derive(Union)]
+enum BarrierCommandResult {
+    Opener(Box<Opener>),
+    InternalServerError(InternalServerError),
+    UnauthorizedError(UnauthorizedError),
+    PermissionDeniedError(PermissionDeniedError),
+    TokenIsExpiredError(TokenIsExpiredError),
+    NotFoundError(NotFoundError),
+    IsInvalidError(IsInvalidError),
+    DeviceIsBusyError(DeviceIsBusyError),
+    DeviceIsNotConnectedError(DeviceIsNotConnectedError),
+    CommandIsNotSupportedError(CommandIsNotSupportedError),
+}
+
+impl From<Error> for BarrierCommandResult {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::InternalServerError(e) => BarrierCommandResult::InternalServerError(e),
+            Error::UnauthorizedError(e) => BarrierCommandResult::UnauthorizedError(e),
+            Error::PermissionDeniedError(e) => BarrierCommandResult::PermissionDeniedError(e),
+            Error::TokenIsExpiredError(e) => BarrierCommandResult::TokenIsExpiredError(e),
+            Error::NotFoundError(e) => BarrierCommandResult::NotFoundError(e),
+            Error::IsInvalidError(e) => BarrierCommandResult::IsInvalidError(e),
+            Error::DeviceIsBusyError(e) => BarrierCommandResult::DeviceIsBusyError(e),
+            Error::DeviceIsNotConnectedError(e) => {
+                BarrierCommandResult::DeviceIsNotConnectedError(e)
+            }
+            Error::CommandIsNotSupportedError(e) => {
+                BarrierCommandResult::CommandIsNotSupportedError(e)
+            }
+            _ => panic!("Can not cast from Error to BarrierCommandResult"),
+        }
+    }
+}
+
+#[derive(Union)]
+enum TagsCommandResult {
+    Opener(Box<Opener>),
+    InternalServerError(InternalServerError),
+    UnauthorizedError(UnauthorizedError),
+    PermissionDeniedError(PermissionDeniedError),
+    TokenIsExpiredError(TokenIsExpiredError),
+    NotFoundError(NotFoundError),
+    IsInvalidError(IsInvalidError),
+    DeviceIsBusyError(DeviceIsBusyError),
+    DeviceIsNotConnectedError(DeviceIsNotConnectedError),
+    CommandIsNotSupportedError(CommandIsNotSupportedError),
+}
+
+impl From<Error> for TagsCommandResult {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::InternalServerError(e) => TagsCommandResult::InternalServerError(e),
+            Error::UnauthorizedError(e) => TagsCommandResult::UnauthorizedError(e),
+            Error::PermissionDeniedError(e) => TagsCommandResult::PermissionDeniedError(e),
+            Error::TokenIsExpiredError(e) => TagsCommandResult::TokenIsExpiredError(e),
+            Error::NotFoundError(e) => TagsCommandResult::NotFoundError(e),
+            Error::IsInvalidError(e) => TagsCommandResult::IsInvalidError(e),
+            Error::DeviceIsBusyError(e) => TagsCommandResult::DeviceIsBusyError(e),
+            Error::DeviceIsNotConnectedError(e) => TagsCommandResult::DeviceIsNotConnectedError(e),
+            Error::CommandIsNotSupportedError(e) => {
+                TagsCommandResult::CommandIsNotSupportedError(e)
+            }
+            _ => panic!("Can not cast from Error to TagsCommandResult"),
+        }
+    }
+}
+
+#[derive(Union)]
+enum UpdateCommandResult {
+    Opener(Box<Opener>),
+    InternalServerError(InternalServerError),
+    UnauthorizedError(UnauthorizedError),
+    PermissionDeniedError(PermissionDeniedError),
+    TokenIsExpiredError(TokenIsExpiredError),
+    NotFoundError(NotFoundError),
+    IsInvalidError(IsInvalidError),
+    DeviceIsBusyError(DeviceIsBusyError),
+    DeviceIsNotConnectedError(DeviceIsNotConnectedError),
+    CommandIsNotSupportedError(CommandIsNotSupportedError),
+}
+
+impl From<Error> for UpdateCommandResult {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::InternalServerError(e) => UpdateCommandResult::InternalServerError(e),
+            Error::UnauthorizedError(e) => UpdateCommandResult::UnauthorizedError(e),
+            Error::PermissionDeniedError(e) => UpdateCommandResult::PermissionDeniedError(e),
+            Error::TokenIsExpiredError(e) => UpdateCommandResult::TokenIsExpiredError(e),
+            Error::NotFoundError(e) => UpdateCommandResult::NotFoundError(e),
+            Error::IsInvalidError(e) => UpdateCommandResult::IsInvalidError(e),
+            Error::DeviceIsBusyError(e) => UpdateCommandResult::DeviceIsBusyError(e),
+            Error::DeviceIsNotConnectedError(e) => {
+                UpdateCommandResult::DeviceIsNotConnectedError(e)
+            }
+            Error::CommandIsNotSupportedError(e) => {
+                UpdateCommandResult::CommandIsNotSupportedError(e)
+            }
+            _ => panic!("Can not cast from Error to UpdateCommandResult"),
+        }
+    }
+}
+
 fn user_id_default() -> Option<ID> {
     None
 }
@@ -305,6 +608,19 @@ struct CreateOpenerInput {
 #[derive(InputObject)]
 struct SetParamsCommandInput {
     barrier_model_id: Option<String>,
+
+    /// If opener is not connected, command is kept for given number of seconds
+    /// and sent after its next HELLO, otherwise command is refused
+    deliver_within: Option<u32>,
+}
+
+#[derive(InputObject)]
+struct UpdateCommandInput {
+    /// Name of firmware file in firmware directory of service
+    file: String,
+
+    /// Version of firmware in file
+    version: String,
 }
 
 #[derive(InputObject)]
@@ -326,6 +642,220 @@ fn check_update_data(new_opener: &UpdateOpenerInput) -> bool {
         || new_opener.password.is_some()
 }
 
+/// Finds opener by id and checks that it can accept new command to its queue
+/// Command for disconnected opener is refused unless it is `offline` one
+async fn get_opener_for_command(
+    db: &Database,
+    id: &str,
+    command: &str,
+    offline: bool,
+) -> Result<Opener, Error> {
+    let opener = match get_opener_by_id(db, id).await {
+        Err(e) => return Err(Error::InternalServerError(e.into())),
+        Ok(o) => o,
+    };
+
+    if opener.is_none() {
+        return Err(Error::NotFoundError(NotFoundError::new(
+            "Not found",
+            "Opener",
+        )));
+    }
+
+    let opener = match Opener::try_from(&opener.unwrap()) {
+        Err(e) => {
+            log::error!("Failed to convert opener {}", e);
+            return Err(Error::InternalServerError(e.into()));
+        }
+        Ok(o) => o,
+    };
+
+    if !opener.connected && !offline {
+        return Err(Error::DeviceIsNotConnectedError(
+            "Opener is not connected".into(),
+        ));
+    }
+
+    if !supports_command(opener.capabilities.as_ref(), command) {
+        return Err(Error::CommandIsNotSupportedError(
+            "Command is not supported by opener".into(),
+        ));
+    }
+
+    let queued = match count_queued_commands(db, &opener.serial_number).await {
+        Err(e) => return Err(Error::InternalServerError(e.into())),
+        Ok(q) => q,
+    };
+
+    if queued >= MAX_QUEUED_COMMANDS {
+        return Err(Error::DeviceIsBusyError("Command queue is full".into()));
+    }
+
+    Ok(opener)
+}
+
+/// Non-admin users can control only theirs openers
+fn can_control(claims: &Claims, role_name: &str, opener: &Opener) -> bool {
+    role_name == "admin" || opener.user_id.as_ref() == Some(&claims.user_id)
+}
+
+/// Checks and sends ADD_TAGS or REMOVE_TAGS command to controller
+async fn tags_command(
+    ctx: &Context<'_>,
+    id: ID,
+    tags: Vec<String>,
+    command: &str,
+) -> TagsCommandResult {
+    let db = ctx.data::<Database>().expect("Can't get db connection");
+
+    let token: (&Claims, String) =
+        match check_token(ctx, |role| role.access_rights.openers.edit).await {
+            CheckTokenResult::Err(e) => return e.into(),
+            CheckTokenResult::Ok { claims, role_name } => (claims, role_name),
+        };
+
+    if tags.is_empty() {
+        return TagsCommandResult::IsInvalidError(IsInvalidError::new(
+            "Tags are not provided",
+            "tags",
+        ));
+    }
+
+    let opener = match get_opener_for_command(db, &id, command, false).await {
+        Err(e) => return e.into(),
+        Ok(o) => o,
+    };
+
+    if !can_control(token.0, &token.1, &opener) {
+        return TagsCommandResult::PermissionDeniedError("Permission denied".into());
+    }
+
+    let srv = ctx
+        .data::<Addr<OpenerServer>>()
+        .expect("Can't get opener server")
+        .clone();
+
+    let message = crate::server::message::TagsCommand {
+        user_id: token.0.user_id.clone(),
+        serial_number: opener.serial_number.clone(),
+        command: command.to_string(),
+        tags,
+    };
+
+    tokio::spawn(async move {
+        if let Err(e) = srv.send(message).await {
+            log::error!("Failed to send tags command to server: {}", e);
+        }
+    });
+
+    TagsCommandResult::Opener(Box::new(opener))
+}
+
+/// Checks that barrier model of opener supports command and door
+pub(super) async fn check_barrier_door(
+    db: &Database,
+    barrier_model_id: Option<&String>,
+    door: Option<u32>,
+    command: &str,
+) -> Result<(), Error> {
+    let barrier_model_id = match barrier_model_id {
+        Some(id) => id,
+        None => {
+            return Err(Error::IsInvalidError(IsInvalidError::new(
+                "Barrier model is not set",
+                "barrierModel",
+            )))
+        }
+    };
+
+    let model = match get_barrier_model_by_id(db, barrier_model_id).await {
+        Err(e) => return Err(Error::InternalServerError(e.into())),
+        Ok(m) => m,
+    };
+
+    let model = match model {
+        Some(model) => model,
+        None => {
+            return Err(Error::NotFoundError(NotFoundError::new(
+                "Not found",
+                "BarrierModel",
+            )))
+        }
+    };
+
+    let algorithm = match BarrierAlgorithm::try_from(model.algorithm.as_str()) {
+        Err(e) => {
+            log::error!("Failed to convert barrier algorithm {}", e);
+            return Err(Error::InternalServerError(e.into()));
+        }
+        Ok(a) => a,
+    };
+
+    match algorithm {
+        BarrierAlgorithm::Open if command == crate::server::command::CLOSE_COMMAND => Err(
+            Error::CommandIsNotSupportedError("Barrier model supports only open command".into()),
+        ),
+        BarrierAlgorithm::TwoDoors if !matches!(door, Some(1) | Some(2)) => Err(
+            Error::IsInvalidError(IsInvalidError::new("Door must be 1 or 2", "door")),
+        ),
+        BarrierAlgorithm::TwoDoors => Ok(()),
+        _ if door.is_some() => Err(Error::IsInvalidError(IsInvalidError::new(
+            "Door is supported only by barriers with two doors",
+            "door",
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Checks and sends OPEN or CLOSE command to controller
+async fn barrier_command(
+    ctx: &Context<'_>,
+    id: ID,
+    door: Option<u32>,
+    command: &str,
+) -> BarrierCommandResult {
+    let db = ctx.data::<Database>().expect("Can't get db connection");
+
+    let token: (&Claims, String) =
+        match check_token(ctx, |role| role.access_rights.openers.edit).await {
+            CheckTokenResult::Err(e) => return e.into(),
+            CheckTokenResult::Ok { claims, role_name } => (claims, role_name),
+        };
+
+    let opener = match get_opener_for_command(db, &id, command, false).await {
+        Err(e) => return e.into(),
+        Ok(o) => o,
+    };
+
+    if !can_control(token.0, &token.1, &opener) {
+        return BarrierCommandResult::PermissionDeniedError("Permission denied".into());
+    }
+
+    if let Err(e) = check_barrier_door(db, opener.barrier_model_id.as_ref(), door, command).await {
+        return e.into();
+    }
+
+    let srv = ctx
+        .data::<Addr<OpenerServer>>()
+        .expect("Can't get opener server")
+        .clone();
+
+    let message = crate::server::message::BarrierCommand {
+        user_id: token.0.user_id.clone(),
+        serial_number: opener.serial_number.clone(),
+        command: command.to_string(),
+        door,
+    };
+
+    tokio::spawn(async move {
+        if let Err(e) = srv.send(message).await {
+            log::error!("Failed to send barrier command to server: {}", e);
+        }
+    });
+
+    BarrierCommandResult::Opener(Box::new(opener))
+}
+
 #[derive(Default)]
 pub(super) struct OpenerMutation;
 
@@ -360,7 +890,7 @@ impl OpenerMutation {
             Ok(opener) => opener,
         };
 
-        let opener = match Opener::try_from(&opener) {
+        let mut opener_dto = match Opener::try_from(&opener) {
             Err(e) => {
                 log::error!("Failed to convert opener {}", e);
                 return CreateOpenerResult::InternalServerError(e.into());
@@ -368,7 +898,47 @@ impl OpenerMutation {
             Ok(m) => m,
         };
 
-        CreateOpenerResult::Opener(Box::new(opener))
+        opener_dto.secret = opener.secret;
+
+        CreateOpenerResult::Opener(Box::new(opener_dto))
+    }
+
+    /// Issues new secret for controller, old secret stops working immediately
+    async fn regenerate_opener_secret(
+        &self,
+        ctx: &Context<'_>,
+        serial_number: String,
+    ) -> RegenerateOpenerSecretResult {
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        if let CheckTokenResult::Err(e) =
+            check_token(ctx, |role| role.access_rights.openers.create).await
+        {
+            return e.into();
+        }
+
+        let opener = match regenerate_opener_secret(db, &serial_number).await {
+            Err(e) => return RegenerateOpenerSecretResult::InternalServerError(e.into()),
+            Ok(None) => {
+                return RegenerateOpenerSecretResult::NotFoundError(NotFoundError::new(
+                    "Not found",
+                    "Opener",
+                ))
+            }
+            Ok(Some(opener)) => opener,
+        };
+
+        let mut opener_dto = match Opener::try_from(&opener) {
+            Err(e) => {
+                log::error!("Failed to convert opener {}", e);
+                return RegenerateOpenerSecretResult::InternalServerError(e.into());
+            }
+            Ok(m) => m,
+        };
+
+        opener_dto.secret = opener.secret;
+
+        RegenerateOpenerSecretResult::Opener(Box::new(opener_dto))
     }
 
     async fn update_opener(
@@ -446,7 +1016,10 @@ impl OpenerMutation {
             password: new_opener.password,
             nonce: None,
             version: None,
+            protocol_version: None,
+            capabilities: None,
             connected: None,
+            session_owner: None,
             barrier_model_id: None,
         };
 
@@ -474,11 +1047,11 @@ impl OpenerMutation {
     ) -> SetParamsCommandResult {
         let db = ctx.data::<Database>().expect("Can't get db connection");
 
-        if let CheckTokenResult::Err(e) =
-            check_token(ctx, |role| role.access_rights.openers.edit).await
-        {
-            return e.into();
-        }
+        let token: (&Claims, String) =
+            match check_token(ctx, |role| role.access_rights.openers.edit).await {
+                CheckTokenResult::Err(e) => return e.into(),
+                CheckTokenResult::Ok { claims, role_name } => (claims, role_name),
+            };
 
         if params.barrier_model_id.is_none() {
             return SetParamsCommandResult::NoUpdateDataProvidedError(
@@ -486,44 +1059,34 @@ impl OpenerMutation {
             );
         }
 
-        let opener = match get_opener_by_id(db, &id).await {
-            Err(e) => return SetParamsCommandResult::InternalServerError(e.into()),
-            Ok(o) => o,
-        };
-
-        if opener.is_none() {
-            return SetParamsCommandResult::NotFoundError(NotFoundError::new(
-                "Not found",
-                "Opener",
+        if params
+            .deliver_within
+            .is_some_and(|d| d == 0 || d > MAX_DELIVERY_PERIOD)
+        {
+            return SetParamsCommandResult::IsInvalidError(IsInvalidError::new(
+                "Delivery period must be from 1 second to 7 days",
+                "deliverWithin",
             ));
         }
 
-        let opener = opener.unwrap();
-
-        let opener = match Opener::try_from(&opener) {
-            Err(e) => {
-                log::error!("Failed to convert opener {}", e);
-                return SetParamsCommandResult::InternalServerError(e.into());
-            }
+        let opener = match get_opener_for_command(
+            db,
+            &id,
+            crate::server::command::SET_COMMAND,
+            params.deliver_within.is_some(),
+        )
+        .await
+        {
+            Err(e) => return e.into(),
             Ok(o) => o,
         };
 
-        if !opener.connected {
-            return SetParamsCommandResult::DeviceIsNotConnectedError(
-                "Opener is not connected".into(),
-            );
-        }
-
-        if opener.command_status == CommandStatus::Pending {
-            return SetParamsCommandResult::DeviceIsBusyError("Opener is busy".into());
+        if !can_control(token.0, &token.1, &opener) {
+            return SetParamsCommandResult::PermissionDeniedError("Permission denied".into());
         }
 
         let is_new_model = opener.barrier_model_id != params.barrier_model_id;
 
-        let command_status = if is_new_model { "PENDING" } else { "SUCCESS" };
-
-        let last_command_type = "SET";
-
         if is_new_model {
             let model = match get_barrier_model_by_id(db, params.barrier_model_id.as_ref().unwrap())
                 .await
@@ -547,12 +1110,11 @@ impl OpenerMutation {
                 .clone();
 
             let command = crate::server::message::SetCommand {
-                login: opener.login.clone(),
-                password: opener.password.clone(),
-                nonce: opener.nonce.as_ref().unwrap().clone(),
+                user_id: token.0.user_id.clone(),
                 serial_number: opener.serial_number.clone(),
                 barrier_model: params.barrier_model_id.as_ref().unwrap().clone(),
                 barrier_algorithm: model.algorithm,
+                deliver_within: params.deliver_within,
             };
 
             tokio::spawn(async move {
@@ -560,15 +1122,16 @@ impl OpenerMutation {
                     log::error!("Failed to send set command to server: {}", e);
                 }
             });
+
+            return SetParamsCommandResult::Opener(Box::new(opener));
         }
 
-        let opener = match set_command_to_opener(
-            db,
-            &opener.serial_number,
-            command_status,
-            last_command_type,
-        )
-        .await
+        // Model is already set, but status of command in progress must not be changed
+        if opener.command_status == CommandStatus::Pending {
+            return SetParamsCommandResult::Opener(Box::new(opener));
+        }
+
+        let opener = match set_command_to_opener(db, &opener.serial_number, "SUCCESS", "SET").await
         {
             Err(e) => return SetParamsCommandResult::InternalServerError(e.into()),
             Ok(o) => o,
@@ -586,6 +1149,154 @@ impl OpenerMutation {
 
         SetParamsCommandResult::Opener(Box::new(opener))
     }
+
+    /// Requests firmware version, uptime and current settings from controller
+    async fn info_command(&self, ctx: &Context<'_>, id: ID) -> InfoCommandResult {
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        let token: (&Claims, String) =
+            match check_token(ctx, |role| role.access_rights.openers.edit).await {
+                CheckTokenResult::Err(e) => return e.into(),
+                CheckTokenResult::Ok { claims, role_name } => (claims, role_name),
+            };
+
+        let opener = match get_opener_for_command(
+            db,
+            &id,
+            crate::server::command::INFO_COMMAND,
+            false,
+        )
+        .await
+        {
+            Err(e) => return e.into(),
+            Ok(o) => o,
+        };
+
+        if !can_control(token.0, &token.1, &opener) {
+            return InfoCommandResult::PermissionDeniedError("Permission denied".into());
+        }
+
+        let srv = ctx
+            .data::<Addr<OpenerServer>>()
+            .expect("Can't get opener server")
+            .clone();
+
+        let command = crate::server::message::InfoCommand {
+            user_id: token.0.user_id.clone(),
+            serial_number: opener.serial_number.clone(),
+        };
+
+        tokio::spawn(async move {
+            if let Err(e) = srv.send(command).await {
+                log::error!("Failed to send info command to server: {}", e);
+            }
+        });
+
+        InfoCommandResult::Opener(Box::new(opener))
+    }
+
+    /// Opens barrier connected to controller
+    async fn open_barrier(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        #[graphql(desc = "Door index (1 or 2), only for barriers with TWO_DOORS algorithm")]
+        door: Option<u32>,
+    ) -> BarrierCommandResult {
+        barrier_command(ctx, id, door, crate::server::command::OPEN_COMMAND).await
+    }
+
+    /// Closes barrier connected to controller, not supported by barriers with OPEN algorithm
+    async fn close_barrier(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        #[graphql(desc = "Door index (1 or 2), only for barriers with TWO_DOORS algorithm")]
+        door: Option<u32>,
+    ) -> BarrierCommandResult {
+        barrier_command(ctx, id, door, crate::server::command::CLOSE_COMMAND).await
+    }
+
+    /// Adds RFID tags to controller
+    async fn add_tags(&self, ctx: &Context<'_>, id: ID, tags: Vec<String>) -> TagsCommandResult {
+        tags_command(ctx, id, tags, crate::server::command::ADD_TAGS_COMMAND).await
+    }
+
+    /// Starts over-the-air firmware update of controller
+    async fn update_command(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        firmware: UpdateCommandInput,
+    ) -> UpdateCommandResult {
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        let token: (&Claims, String) =
+            match check_token(ctx, |role| role.access_rights.openers.edit).await {
+                CheckTokenResult::Err(e) => return e.into(),
+                CheckTokenResult::Ok { claims, role_name } => (claims, role_name),
+            };
+
+        let opener =
+            match get_opener_for_command(db, &id, crate::server::command::UPDATE_COMMAND, false)
+                .await
+            {
+                Err(e) => return e.into(),
+                Ok(o) => o,
+            };
+
+        if !can_control(token.0, &token.1, &opener) {
+            return UpdateCommandResult::PermissionDeniedError("Permission denied".into());
+        }
+
+        let info = match get_firmware_info(&firmware.file).await {
+            Err(e) => {
+                log::error!("Failed to get firmware file {}: {}", firmware.file, e);
+                return UpdateCommandResult::IsInvalidError(IsInvalidError::new(
+                    "Invalid param",
+                    "file",
+                ));
+            }
+            Ok(i) => i,
+        };
+
+        let info = match info {
+            Some(i) => i,
+            None => {
+                return UpdateCommandResult::NotFoundError(NotFoundError::new(
+                    "Not found",
+                    "Firmware",
+                ))
+            }
+        };
+
+        let srv = ctx
+            .data::<Addr<OpenerServer>>()
+            .expect("Can't get opener server")
+            .clone();
+
+        let command = crate::server::message::UpdateCommand {
+            user_id: token.0.user_id.clone(),
+            serial_number: opener.serial_number.clone(),
+            url: info.url,
+            size: info.size,
+            checksum: info.checksum,
+            version: firmware.version,
+        };
+
+        tokio::spawn(async move {
+            if let Err(e) = srv.send(command).await {
+                log::error!("Failed to send update command to server: {}", e);
+            }
+        });
+
+        UpdateCommandResult::Opener(Box::new(opener))
+    }
+
+    /// Removes RFID tags from controller
+    async fn remove_tags(&self, ctx: &Context<'_>, id: ID, tags: Vec<String>) -> TagsCommandResult {
+        tags_command(ctx, id, tags, crate::server::command::REMOVE_TAGS_COMMAND).await
+    }
 }
 
 #[derive(Default)]
@@ -689,12 +1400,14 @@ impl TryFrom<&OpenerEntity> for Opener {
             serial_number: opener.serial_number.clone(),
             version: opener.version.clone(),
             alias: opener.alias.clone(),
-            nonce: opener.nonce.clone(),
             description: opener.description.clone(),
             lat: opener.lat,
             lng: opener.lng,
             login: opener.login.clone(),
             password: opener.password.clone(),
+            secret: None,
+            protocol_version: opener.protocol_version,
+            capabilities: opener.capabilities.clone(),
             connected: opener.connected,
             created_at: opener.created_at.timestamp_millis(),
             updated_at: opener.updated_at.map(|t| t.timestamp_millis()),
@@ -707,10 +1420,41 @@ impl TryFrom<&OpenerEntity> for Opener {
                 .map(|t| t.as_str().try_into())
                 .transpose()?,
             last_error: opener.last_error.as_ref().map(|e| e.into()),
+            info: opener.info.as_ref().map(|i| i.into()),
+            telemetry: opener.telemetry.as_ref().map(|t| t.into()),
+            update_progress: opener
+                .update_progress
+                .as_ref()
+                .map(|p| p.try_into())
+                .transpose()?,
         })
     }
 }
 
+impl TryFrom<&UpdateProgressEntity> for UpdateProgress {
+    type Error = &'static str;
+
+    fn try_from(progress: &UpdateProgressEntity) -> Result<Self, Self::Error> {
+        Ok(Self {
+            stage: progress.stage.as_str().try_into()?,
+            percent: progress.percent,
+            changed_at: progress.changed_at.timestamp_millis(),
+        })
+    }
+}
+
+impl From<&OpenerInfoEntity> for OpenerInfo {
+    fn from(info: &OpenerInfoEntity) -> Self {
+        Self {
+            version: info.version.clone(),
+            uptime: info.uptime,
+            barrier_model_id: info.barrier_model.clone(),
+            barrier_algorithm: info.barrier_algorithm.as_str().try_into().ok(),
+            received_at: info.received_at.timestamp_millis(),
+        }
+    }
+}
+
 impl From<&OpenerErrorEntity> for OpenerError {
     fn from(error: &OpenerErrorEntity) -> Self {
         Self {
@@ -718,11 +1462,19 @@ impl From<&OpenerErrorEntity> for OpenerError {
             code: error.code,
             description: error.description.clone(),
             details: error.details.clone(),
+            tags: error.tags.as_ref().map(|tags| {
+                tags.iter()
+                    .map(|t| TagResult {
+                        tag: t.tag.clone(),
+                        code: t.code,
+                    })
+                    .collect()
+            }),
         }
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub(crate) struct OpenerConnectionChanged {
     pub serial_number: String,
     pub connected: bool,
@@ -740,7 +1492,7 @@ impl OpenerConnectionChanged {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub(crate) struct OpenerCommandResult {
     pub serial_number: String,
     pub command_type: CommandType,
@@ -768,6 +1520,29 @@ impl OpenerCommandResult {
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct OpenerUpdateProgress {
+    pub serial_number: String,
+    pub stage: UpdateStage,
+    pub percent: u32,
+    pub user_id: Option<String>,
+}
+
+#[Object]
+impl OpenerUpdateProgress {
+    async fn serial_number(&self) -> &String {
+        &self.serial_number
+    }
+
+    async fn stage(&self) -> &UpdateStage {
+        &self.stage
+    }
+
+    async fn percent(&self) -> u32 {
+        self.percent
+    }
+}
+
 #[derive(Default)]
 pub(super) struct OpenerSubscription;
 
@@ -802,15 +1577,17 @@ impl OpenerSubscription {
             None => return Err("Unauthorized".into()),
         };
 
-        Ok(
-            SimpleBroker::<OpenerConnectionChanged>::subscribe().filter(move |event| {
+        let broker = ctx.data::<Broker>().expect("Can\'t get broker");
+
+        Ok(broker
+            .subscribe::<OpenerConnectionChanged>()
+            .filter(move |event| {
                 log::info!("Event: {:?}", event);
 
                 let res = check_user(&claims, &role, &event.user_id);
 
                 async move { res }
-            }),
-        )
+            }))
     }
 
     async fn opener_command(
@@ -846,8 +1623,11 @@ impl OpenerSubscription {
             None => return Err("Unauthorized".into()),
         };
 
-        Ok(
-            SimpleBroker::<OpenerCommandResult>::subscribe().filter(move |event| {
+        let broker = ctx.data::<Broker>().expect("Can\'t get broker");
+
+        Ok(broker
+            .subscribe::<OpenerCommandResult>()
+            .filter(move |event| {
                 log::info!("Event: {:?}", event);
 
                 let res = check_user(&claims, &role, &event.user_id);
@@ -870,8 +1650,99 @@ impl OpenerSubscription {
                         Ok(opener) => opener.is_some(),
                     }
                 }
-            }),
-        )
+            }))
+    }
+
+    async fn opener_update_progress(
+        &self,
+        ctx: &Context<'_>,
+        serial_number: String,
+        access_token: String,
+    ) -> Result<impl Stream<Item = OpenerUpdateProgress>> {
+        let claims = match crate::auth::decode_claims(&access_token) {
+            Ok(claims) => claims,
+            Err(_) => return Err("Unauthorized".into()),
+        };
+
+        let claims = match claims {
+            Some(claims) => claims,
+            None => return Err("Unauthorized".into()),
+        };
+
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        let role = get_role_by_id(db, &claims.role_id).await;
+
+        let role = match role {
+            Ok(role) => role,
+            Err(_) => return Err("Internal server error".into()),
+        };
+
+        let role = match role {
+            Some(role) => role,
+            None => return Err("Unauthorized".into()),
+        };
+
+        let broker = ctx.data::<Broker>().expect("Can\'t get broker");
+
+        Ok(broker
+            .subscribe::<OpenerUpdateProgress>()
+            .filter(move |event| {
+                log::info!("Event: {:?}", event);
+
+                let res = check_user(&claims, &role, &event.user_id)
+                    && event.serial_number == serial_number;
+
+                async move { res }
+            }))
+    }
+
+    /// Events of opener or of all available openers if serial number is not given
+    async fn opener_events(
+        &self,
+        ctx: &Context<'_>,
+        serial_number: Option<String>,
+        access_token: String,
+    ) -> Result<impl Stream<Item = Event>> {
+        let claims = match crate::auth::decode_claims(&access_token) {
+            Ok(claims) => claims,
+            Err(_) => return Err("Unauthorized".into()),
+        };
+
+        let claims = match claims {
+            Some(claims) => claims,
+            None => return Err("Unauthorized".into()),
+        };
+
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        let role = get_role_by_id(db, &claims.role_id).await;
+
+        let role = match role {
+            Ok(role) => role,
+            Err(_) => return Err("Internal server error".into()),
+        };
+
+        let role = match role {
+            Some(role) => role,
+            None => return Err("Unauthorized".into()),
+        };
+
+        let broker = ctx.data::<Broker>().expect("Can\'t get broker");
+
+        Ok(broker
+            .subscribe::<OpenerEvent>()
+            .filter(move |event| {
+                log::info!("Event: {:?}", event);
+
+                let res = check_user(&claims, &role, &event.user_id)
+                    && serial_number
+                        .as_ref()
+                        .is_none_or(|sn| *sn == event.serial_number);
+
+                async move { res }
+            })
+            .map(|event| event.event))
     }
 }
 
@@ -0,0 +1,671 @@
+use super::command::Command;
+use super::error::{Error, *};
+use super::opener::check_barrier_door;
+use crate::auth::Claims;
+use crate::graphql::auth::{check_token, CheckTokenResult};
+use crate::persistence::command::get_command_by_id;
+use crate::persistence::opener::{get_opener_by_id, get_opener_by_sn, OpenerEntity};
+use crate::persistence::schedule::{
+    create_schedule, delete_schedule, get_schedule_by_id, get_schedule_runs, get_schedules_by_sn,
+    replace_schedule, ScheduleEntity, ScheduleRunEntity,
+};
+use crate::scheduler::{
+    parse_time_zone, schedule_rule, ScheduleRule, CATCH_UP_MISSED_RUNS, SKIP_MISSED_RUNS,
+};
+use async_graphql::*;
+use bson::oid::ObjectId;
+use chrono::{Local, Utc};
+use mongodb::Database;
+use std::convert::{TryFrom, TryInto};
+use std::str::FromStr;
+
+const SCHEDULE_RUNS_PAGE_MAX_SIZE: u32 = 100;
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum ScheduledCommandType {
+    Open,
+    Close,
+}
+
+impl ScheduledCommandType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ScheduledCommandType::Open => crate::server::command::OPEN_COMMAND,
+            ScheduledCommandType::Close => crate::server::command::CLOSE_COMMAND,
+        }
+    }
+}
+
+impl TryFrom<&str> for ScheduledCommandType {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "OPEN" => Ok(ScheduledCommandType::Open),
+            "CLOSE" => Ok(ScheduledCommandType::Close),
+            _ => Err("Wrong scheduled command type"),
+        }
+    }
+}
+
+/// What to do with run which could not be done at planned time
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum MissedRunPolicy {
+    /// Run is recorded as missed and command is not sent
+    Skip,
+    /// The latest missed run is done once, command for disconnected opener
+    /// is kept until the next run
+    CatchUp,
+}
+
+impl MissedRunPolicy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MissedRunPolicy::Skip => SKIP_MISSED_RUNS,
+            MissedRunPolicy::CatchUp => CATCH_UP_MISSED_RUNS,
+        }
+    }
+}
+
+impl TryFrom<&str> for MissedRunPolicy {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            SKIP_MISSED_RUNS => Ok(MissedRunPolicy::Skip),
+            CATCH_UP_MISSED_RUNS => Ok(MissedRunPolicy::CatchUp),
+            _ => Err("Wrong missed run policy"),
+        }
+    }
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+pub enum ScheduleRunStatus {
+    /// Command is queued to connected opener
+    Sent,
+    /// Command is kept until disconnected opener connects
+    Queued,
+    /// Command is not sent as opener is not connected or service was not running
+    Missed,
+    Failed,
+}
+
+impl TryFrom<&str> for ScheduleRunStatus {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "SENT" => Ok(ScheduleRunStatus::Sent),
+            "QUEUED" => Ok(ScheduleRunStatus::Queued),
+            "MISSED" => Ok(ScheduleRunStatus::Missed),
+            "FAILED" => Ok(ScheduleRunStatus::Failed),
+            _ => Err("Wrong schedule run status"),
+        }
+    }
+}
+
+/// Describes OPEN or CLOSE command sent to opener by schedule
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub(crate) struct Schedule {
+    id: ID,
+    serial_number: String,
+    name: String,
+    command_type: ScheduledCommandType,
+    door: Option<u32>,
+
+    /// Days of week from 1 (Monday) to 7 (Sunday) of weekly schedule
+    days: Option<Vec<u32>>,
+
+    /// Local time `HH:MM` of weekly schedule
+    time: Option<String>,
+
+    /// Cron expression with seconds, e.g. `0 0 8 * * Mon-Fri`
+    cron: Option<String>,
+
+    /// IANA name of time zone, e.g. `Europe/Moscow`
+    time_zone: String,
+
+    missed_run_policy: MissedRunPolicy,
+    enabled: bool,
+
+    /// Absent for disabled schedule
+    next_run_at: Option<i64>,
+
+    last_run_at: Option<i64>,
+    created_at: i64,
+}
+
+#[ComplexObject]
+impl Schedule {
+    /// Runs of schedule from newest to oldest
+    async fn runs(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 20)] first: u32,
+        #[graphql(desc = "Id of the last run from previous page")] after: Option<ID>,
+    ) -> ScheduleRunsResult {
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        get_schedule_runs_page(db, &self.id, first, after).await
+    }
+}
+
+/// Describes one run of schedule
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub(crate) struct ScheduleRun {
+    id: ID,
+
+    /// Time when command should be sent
+    planned_at: i64,
+
+    /// Time when run is done
+    started_at: i64,
+
+    status: ScheduleRunStatus,
+    reason: Option<String>,
+
+    /// Number of runs missed before this one while service was not running
+    skipped: u32,
+
+    #[graphql(skip)]
+    command_id: Option<String>,
+}
+
+#[ComplexObject]
+impl ScheduleRun {
+    /// Command sent by run with its result
+    async fn command(&self, ctx: &Context<'_>) -> Result<Option<Command>> {
+        let command_id = match self.command_id.as_ref() {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        let command_id = ObjectId::from_str(command_id)?;
+
+        let command = get_command_by_id(db, &command_id)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(command.as_ref().map(Command::try_from).transpose()?)
+    }
+}
+
+#[derive(SimpleObject)]
+pub(crate) struct Schedules {
+    items: Vec<Schedule>,
+}
+
+#[derive(Union)]
+pub(crate) enum SchedulesResult {
+    Schedules(Schedules),
+    InternalServerError(InternalServerError),
+}
+
+/// Page of runs ordered from newest to oldest
+#[derive(SimpleObject)]
+pub(crate) struct ScheduleRuns {
+    items: Vec<ScheduleRun>,
+
+    /// Id of the last run on page, it is used as `after` to get next page
+    end_cursor: Option<ID>,
+
+    has_next_page: bool,
+}
+
+#[derive(Union)]
+pub(crate) enum ScheduleRunsResult {
+    ScheduleRuns(ScheduleRuns),
+    InternalServerError(InternalServerError),
+    IsInvalidError(IsInvalidError),
+}
+
+#[derive(Union)]
+enum ScheduleResult {
+    Schedule(Box<Schedule>),
+    InternalServerError(InternalServerError),
+    UnauthorizedError(UnauthorizedError),
+    PermissionDeniedError(PermissionDeniedError),
+    TokenIsExpiredError(TokenIsExpiredError),
+    NotFoundError(NotFoundError),
+    IsInvalidError(IsInvalidError),
+    CommandIsNotSupportedError(CommandIsNotSupportedError),
+}
+
+impl From<Error> for ScheduleResult {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::InternalServerError(e) => ScheduleResult::InternalServerError(e),
+            Error::UnauthorizedError(e) => ScheduleResult::UnauthorizedError(e),
+            Error::PermissionDeniedError(e) => ScheduleResult::PermissionDeniedError(e),
+            Error::TokenIsExpiredError(e) => ScheduleResult::TokenIsExpiredError(e),
+            Error::NotFoundError(e) => ScheduleResult::NotFoundError(e),
+            Error::IsInvalidError(e) => ScheduleResult::IsInvalidError(e),
+            Error::CommandIsNotSupportedError(e) => ScheduleResult::CommandIsNotSupportedError(e),
+            _ => panic!("Can not cast from Error to ScheduleResult"),
+        }
+    }
+}
+
+/// Either cron expression or days and time are given
+#[derive(InputObject)]
+struct ScheduleInput {
+    name: String,
+    command_type: ScheduledCommandType,
+
+    /// Door of barrier with two doors
+    door: Option<u32>,
+
+    /// Days of week from 1 (Monday) to 7 (Sunday)
+    days: Option<Vec<u32>>,
+
+    /// Local time `HH:MM`
+    time: Option<String>,
+
+    /// Cron expression with seconds, e.g. `0 0 8 * * Mon-Fri`
+    cron: Option<String>,
+
+    /// IANA name of time zone, e.g. `Europe/Moscow`
+    time_zone: String,
+
+    #[graphql(default_with = "MissedRunPolicy::Skip")]
+    missed_run_policy: MissedRunPolicy,
+
+    #[graphql(default = true)]
+    enabled: bool,
+}
+
+/// Returns schedules of opener
+pub(crate) async fn get_schedules(db: &Database, serial_number: &str) -> SchedulesResult {
+    let schedules = match get_schedules_by_sn(db, serial_number).await {
+        Err(e) => {
+            log::error!("Failed to get schedules of opener {}: {}", serial_number, e);
+            return SchedulesResult::InternalServerError(e.into());
+        }
+        Ok(s) => s,
+    };
+
+    let schedules: Result<Vec<Schedule>, _> = schedules.iter().map(Schedule::try_from).collect();
+
+    match schedules {
+        Err(e) => {
+            log::error!("Failed to convert schedules: {}", e);
+            SchedulesResult::InternalServerError(e.into())
+        }
+        Ok(items) => SchedulesResult::Schedules(Schedules { items }),
+    }
+}
+
+async fn get_schedule_runs_page(
+    db: &Database,
+    schedule_id: &str,
+    first: u32,
+    after: Option<ID>,
+) -> ScheduleRunsResult {
+    if first == 0 || first > SCHEDULE_RUNS_PAGE_MAX_SIZE {
+        return ScheduleRunsResult::IsInvalidError(IsInvalidError::new(
+            "Page size must be from 1 to 100",
+            "first",
+        ));
+    }
+
+    if let Some(after) = after.as_ref() {
+        if ObjectId::from_str(after).is_err() {
+            return ScheduleRunsResult::IsInvalidError(IsInvalidError::new(
+                "Invalid param",
+                "after",
+            ));
+        }
+    }
+
+    // One more run is requested to know if there is next page
+    let runs = match get_schedule_runs(
+        db,
+        schedule_id,
+        first as i64 + 1,
+        after.as_ref().map(|a| a.as_str()),
+    )
+    .await
+    {
+        Err(e) => {
+            log::error!("Failed to get runs of schedule {}: {}", schedule_id, e);
+            return ScheduleRunsResult::InternalServerError(e.into());
+        }
+        Ok(r) => r,
+    };
+
+    let has_next_page = runs.len() > first as usize;
+
+    let runs: Result<Vec<ScheduleRun>, _> = runs
+        .iter()
+        .take(first as usize)
+        .map(ScheduleRun::try_from)
+        .collect();
+
+    let runs = match runs {
+        Err(e) => {
+            log::error!("Failed to convert schedule runs: {}", e);
+            return ScheduleRunsResult::InternalServerError(e.into());
+        }
+        Ok(r) => r,
+    };
+
+    ScheduleRunsResult::ScheduleRuns(ScheduleRuns {
+        end_cursor: runs.last().map(|r| r.id.clone()),
+        items: runs,
+        has_next_page,
+    })
+}
+
+/// Non-admin users can manage only schedules of theirs openers
+fn can_manage(claims: &Claims, role_name: &str, opener: &OpenerEntity) -> bool {
+    role_name == "admin" || opener.user_id.map(|id| id.to_string()) == Some(claims.user_id.clone())
+}
+
+/// Returns opener of existing schedule checking that user can manage it
+async fn get_schedule_with_opener(
+    db: &Database,
+    claims: &Claims,
+    role_name: &str,
+    id: &str,
+) -> Result<(ScheduleEntity, OpenerEntity), Error> {
+    let schedule = match get_schedule_by_id(db, id).await {
+        Err(e) => return Err(Error::InternalServerError(e.into())),
+        Ok(s) => s,
+    };
+
+    let schedule = match schedule {
+        Some(s) => s,
+        None => {
+            return Err(Error::NotFoundError(NotFoundError::new(
+                "Not found",
+                "Schedule",
+            )))
+        }
+    };
+
+    let opener = match get_opener_by_sn(db, &schedule.serial_number).await {
+        Err(e) => return Err(Error::InternalServerError(e.into())),
+        Ok(o) => o,
+    };
+
+    let opener = match opener {
+        Some(o) => o,
+        None => {
+            return Err(Error::NotFoundError(NotFoundError::new(
+                "Not found",
+                "Opener",
+            )))
+        }
+    };
+
+    if !can_manage(claims, role_name, &opener) {
+        return Err(Error::PermissionDeniedError("Permission denied".into()));
+    }
+
+    Ok((schedule, opener))
+}
+
+/// Checks input and calculates the first run of schedule
+async fn build_schedule(
+    db: &Database,
+    opener: &OpenerEntity,
+    input: ScheduleInput,
+    id: ObjectId,
+    user_id: &str,
+    created_at: bson::DateTime,
+) -> Result<ScheduleEntity, Error> {
+    let invalid = |description: &str, field: &str| {
+        Error::IsInvalidError(IsInvalidError::new(description, field))
+    };
+
+    if input.name.trim().is_empty() {
+        return Err(invalid("Name is empty", "name"));
+    }
+
+    let rule = match (&input.cron, &input.days, &input.time) {
+        (Some(cron), None, None) => ScheduleRule::cron(cron).map_err(|e| invalid(e, "cron"))?,
+        (None, Some(days), Some(time)) => {
+            ScheduleRule::weekly(days, time).map_err(|e| invalid(e, "days"))?
+        }
+        _ => {
+            return Err(invalid(
+                "Either cron expression or days and time must be given",
+                "cron",
+            ))
+        }
+    };
+
+    let tz = parse_time_zone(&input.time_zone).map_err(|e| invalid(e, "timeZone"))?;
+
+    let command_type = input.command_type.as_str();
+
+    let barrier_model_id = opener.barrier_model_id.map(|id| id.to_hex());
+
+    check_barrier_door(db, barrier_model_id.as_ref(), input.door, command_type).await?;
+
+    let next_run_at = match input.enabled {
+        false => None,
+        true => match rule.next_after(Utc::now(), tz) {
+            Some(next) => Some(bson::DateTime::from(next)),
+            None => return Err(invalid("Schedule never runs", "cron")),
+        },
+    };
+
+    Ok(ScheduleEntity {
+        id,
+        serial_number: opener.serial_number.clone(),
+        user_id: ObjectId::from_str(user_id).ok(),
+        name: input.name,
+        command_type: command_type.to_string(),
+        door: input.door,
+        days: input.days,
+        time: input.time,
+        cron: input.cron,
+        time_zone: input.time_zone,
+        missed_run_policy: input.missed_run_policy.as_str().to_string(),
+        enabled: input.enabled,
+        next_run_at,
+        last_run_at: None,
+        created_at,
+        updated_at: bson::DateTime::from(Local::now()),
+    })
+}
+
+fn schedule_result(schedule: &ScheduleEntity) -> ScheduleResult {
+    match Schedule::try_from(schedule) {
+        Err(e) => {
+            log::error!("Failed to convert schedule {}", e);
+            ScheduleResult::InternalServerError(e.into())
+        }
+        Ok(s) => ScheduleResult::Schedule(Box::new(s)),
+    }
+}
+
+#[derive(Default)]
+pub(super) struct ScheduleMutation;
+
+#[Object]
+impl ScheduleMutation {
+    /// Creates schedule sending OPEN or CLOSE command to opener
+    async fn create_schedule(
+        &self,
+        ctx: &Context<'_>,
+        opener_id: ID,
+        schedule: ScheduleInput,
+    ) -> ScheduleResult {
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        let token: (&Claims, String) =
+            match check_token(ctx, |role| role.access_rights.openers.edit).await {
+                CheckTokenResult::Err(e) => return e.into(),
+                CheckTokenResult::Ok { claims, role_name } => (claims, role_name),
+            };
+
+        let opener = match get_opener_by_id(db, &opener_id).await {
+            Err(e) => return ScheduleResult::InternalServerError(e.into()),
+            Ok(o) => o,
+        };
+
+        let opener = match opener {
+            Some(o) => o,
+            None => {
+                return ScheduleResult::NotFoundError(NotFoundError::new("Not found", "Opener"))
+            }
+        };
+
+        if !can_manage(token.0, &token.1, &opener) {
+            return ScheduleResult::PermissionDeniedError("Permission denied".into());
+        }
+
+        let schedule = match build_schedule(
+            db,
+            &opener,
+            schedule,
+            ObjectId::new(),
+            &token.0.user_id,
+            bson::DateTime::from(Local::now()),
+        )
+        .await
+        {
+            Err(e) => return e.into(),
+            Ok(s) => s,
+        };
+
+        if let Err(e) = create_schedule(db, &schedule).await {
+            return ScheduleResult::InternalServerError(e.into());
+        }
+
+        schedule_result(&schedule)
+    }
+
+    /// Replaces settings of schedule, the next run is calculated again
+    async fn update_schedule(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        schedule: ScheduleInput,
+    ) -> ScheduleResult {
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        let token: (&Claims, String) =
+            match check_token(ctx, |role| role.access_rights.openers.edit).await {
+                CheckTokenResult::Err(e) => return e.into(),
+                CheckTokenResult::Ok { claims, role_name } => (claims, role_name),
+            };
+
+        let (old, opener) = match get_schedule_with_opener(db, token.0, &token.1, &id).await {
+            Err(e) => return e.into(),
+            Ok(s) => s,
+        };
+
+        let mut schedule = match build_schedule(
+            db,
+            &opener,
+            schedule,
+            old.id,
+            &token.0.user_id,
+            old.created_at,
+        )
+        .await
+        {
+            Err(e) => return e.into(),
+            Ok(s) => s,
+        };
+
+        schedule.last_run_at = old.last_run_at;
+
+        match replace_schedule(db, &schedule).await {
+            Err(e) => ScheduleResult::InternalServerError(e.into()),
+            Ok(None) => ScheduleResult::NotFoundError(NotFoundError::new("Not found", "Schedule")),
+            Ok(Some(schedule)) => schedule_result(&schedule),
+        }
+    }
+
+    /// Removes schedule, its runs are kept
+    async fn delete_schedule(&self, ctx: &Context<'_>, id: ID) -> ScheduleResult {
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        let token: (&Claims, String) =
+            match check_token(ctx, |role| role.access_rights.openers.edit).await {
+                CheckTokenResult::Err(e) => return e.into(),
+                CheckTokenResult::Ok { claims, role_name } => (claims, role_name),
+            };
+
+        if let Err(e) = get_schedule_with_opener(db, token.0, &token.1, &id).await {
+            return e.into();
+        }
+
+        match delete_schedule(db, &id).await {
+            Err(e) => ScheduleResult::InternalServerError(e.into()),
+            Ok(None) => ScheduleResult::NotFoundError(NotFoundError::new("Not found", "Schedule")),
+            Ok(Some(schedule)) => schedule_result(&schedule),
+        }
+    }
+}
+
+#[derive(Default)]
+pub(super) struct ScheduleQuery;
+
+#[Object]
+impl ScheduleQuery {
+    async fn schedule(&self, ctx: &Context<'_>, id: ID) -> ScheduleResult {
+        let db = ctx.data::<Database>().expect("Can't get db connection");
+
+        let token: (&Claims, String) =
+            match check_token(ctx, |role| role.access_rights.openers.view).await {
+                CheckTokenResult::Err(e) => return e.into(),
+                CheckTokenResult::Ok { claims, role_name } => (claims, role_name),
+            };
+
+        match get_schedule_with_opener(db, token.0, &token.1, &id).await {
+            Err(e) => e.into(),
+            Ok((schedule, _)) => schedule_result(&schedule),
+        }
+    }
+}
+
+impl TryFrom<&ScheduleEntity> for Schedule {
+    type Error = &'static str;
+
+    fn try_from(schedule: &ScheduleEntity) -> Result<Self, Self::Error> {
+        // Stored schedule is checked to be valid on creation
+        schedule_rule(schedule)?;
+
+        Ok(Self {
+            id: ID::from(schedule.id),
+            serial_number: schedule.serial_number.clone(),
+            name: schedule.name.clone(),
+            command_type: schedule.command_type.as_str().try_into()?,
+            door: schedule.door,
+            days: schedule.days.clone(),
+            time: schedule.time.clone(),
+            cron: schedule.cron.clone(),
+            time_zone: schedule.time_zone.clone(),
+            missed_run_policy: schedule.missed_run_policy.as_str().try_into()?,
+            enabled: schedule.enabled,
+            next_run_at: schedule.next_run_at.map(|t| t.timestamp_millis()),
+            last_run_at: schedule.last_run_at.map(|t| t.timestamp_millis()),
+            created_at: schedule.created_at.timestamp_millis(),
+        })
+    }
+}
+
+impl TryFrom<&ScheduleRunEntity> for ScheduleRun {
+    type Error = &'static str;
+
+    fn try_from(run: &ScheduleRunEntity) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: ID::from(run.id),
+            planned_at: run.planned_at.timestamp_millis(),
+            started_at: run.created_at.timestamp_millis(),
+            status: run.status.as_str().try_into()?,
+            reason: run.reason.clone(),
+            skipped: run.skipped,
+            command_id: run.command_id.map(|id| id.to_hex()),
+        })
+    }
+}
@@ -0,0 +1,112 @@
+use super::error::*;
+use crate::persistence::telemetry::{get_telemetry_by_sn, TelemetryEntity};
+use async_graphql::*;
+use chrono::{Local, TimeZone};
+use mongodb::Database;
+
+const TELEMETRY_HISTORY_MAX_SIZE: u32 = 1000;
+
+/// Describes state of controller reported in TELEMETRY
+#[derive(SimpleObject, Debug, Clone)]
+pub(crate) struct Telemetry {
+    /// Signal strength of network connection in dBm
+    signal_strength: Option<i32>,
+
+    /// Temperature inside controller in degrees Celsius
+    temperature: Option<f64>,
+
+    /// Supply voltage in volts
+    voltage: Option<f64>,
+
+    /// Seconds since controller start
+    uptime: i64,
+
+    received_at: i64,
+}
+
+/// Telemetry received in requested period from oldest to newest
+#[derive(SimpleObject)]
+pub(crate) struct TelemetryHistory {
+    items: Vec<Telemetry>,
+
+    /// There are more samples in period than returned, period should be narrowed
+    has_more: bool,
+}
+
+#[derive(Union)]
+pub(crate) enum TelemetryHistoryResult {
+    TelemetryHistory(TelemetryHistory),
+    InternalServerError(InternalServerError),
+    IsInvalidError(IsInvalidError),
+}
+
+/// Returns telemetry of opener in period given in milliseconds
+pub(crate) async fn get_telemetry_history(
+    db: &Database,
+    serial_number: &str,
+    from: i64,
+    to: Option<i64>,
+    first: u32,
+) -> TelemetryHistoryResult {
+    if first == 0 || first > TELEMETRY_HISTORY_MAX_SIZE {
+        return TelemetryHistoryResult::IsInvalidError(IsInvalidError::new(
+            "Number of samples must be from 1 to 1000",
+            "first",
+        ));
+    }
+
+    let from = match Local.timestamp_millis_opt(from).single() {
+        Some(from) => from,
+        None => {
+            return TelemetryHistoryResult::IsInvalidError(IsInvalidError::new(
+                "Invalid param",
+                "from",
+            ))
+        }
+    };
+
+    let to = match to {
+        None => Local::now(),
+        Some(to) => match Local.timestamp_millis_opt(to).single() {
+            Some(to) if to > from => to,
+            _ => {
+                return TelemetryHistoryResult::IsInvalidError(IsInvalidError::new(
+                    "Invalid param",
+                    "to",
+                ))
+            }
+        },
+    };
+
+    // One more sample is requested to know if there are more of them
+    let samples = match get_telemetry_by_sn(db, serial_number, from, to, first as i64 + 1).await {
+        Err(e) => {
+            log::error!("Failed to get telemetry of opener {}: {}", serial_number, e);
+            return TelemetryHistoryResult::InternalServerError(e.into());
+        }
+        Ok(s) => s,
+    };
+
+    let has_more = samples.len() > first as usize;
+
+    TelemetryHistoryResult::TelemetryHistory(TelemetryHistory {
+        items: samples
+            .iter()
+            .take(first as usize)
+            .map(Telemetry::from)
+            .collect(),
+        has_more,
+    })
+}
+
+impl From<&TelemetryEntity> for Telemetry {
+    fn from(telemetry: &TelemetryEntity) -> Self {
+        Self {
+            signal_strength: telemetry.signal_strength,
+            temperature: telemetry.temperature,
+            voltage: telemetry.voltage,
+            uptime: telemetry.uptime,
+            received_at: telemetry.created_at.timestamp_millis(),
+        }
+    }
+}
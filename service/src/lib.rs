@@ -8,6 +8,8 @@ use std::time::Instant;
 
 use crate::graphql::user::UserLoader;
 use crate::session::WsOpenerSession;
+use crate::tls::ClientIdentity;
+use acs_protocol::Encoding;
 use actix::prelude::*;
 use actix_web::{web, Error, HttpRequest, HttpResponse, Responder};
 use actix_web_actors::ws;
@@ -21,12 +23,27 @@ use graphql::barrier_model::BarrierModelLoader;
 use graphql::role::RoleLoader;
 use mongodb::{options::ClientOptions, options::ResolverConfig, Database};
 use opentelemetry::global;
+use serde::Deserialize;
 
 mod auth;
+pub mod broker;
+pub mod cluster;
+mod firmware;
 mod graphql;
+pub mod listener;
 mod persistence;
+pub mod scheduler;
 pub mod server;
 mod session;
+pub mod tls;
+mod traffic;
+
+pub use broker::{Broker, BrokerConfig};
+pub use cluster::init_cluster;
+pub use firmware::get_firmware;
+pub use listener::ListenerConfig;
+pub use tls::TlsConfig;
+pub use traffic::Traffic;
 
 pub fn init_tracer() -> Result<()> {
     global::set_text_map_propagator(opentelemetry_jaeger::Propagator::new());
@@ -63,21 +80,85 @@ pub async fn get_count(openers_count: web::Data<Arc<AtomicUsize>>) -> impl Respo
     format!("Connected openers count: {}", current_count)
 }
 
+pub async fn get_traffic(traffic: web::Data<Arc<Traffic>>) -> impl Responder {
+    traffic.report()
+}
+
+pub async fn get_broker(broker: web::Data<Broker>) -> impl Responder {
+    broker.report()
+}
+
+#[derive(Deserialize)]
+struct WsQuery {
+    encoding: Option<String>,
+}
+
+/// Encoding is requested by controller in `Sec-WebSocket-Protocol` header
+/// or `encoding` query parameter, JSON is used by default
+fn get_encoding(req: &HttpRequest) -> Option<Encoding> {
+    let subprotocol = req
+        .headers()
+        .get("Sec-WebSocket-Protocol")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| {
+            h.split(',')
+                .find_map(|p| Encoding::from_subprotocol(p.trim()))
+        });
+
+    if subprotocol.is_some() {
+        return subprotocol;
+    }
+
+    match web::Query::<WsQuery>::from_query(req.query_string()) {
+        Ok(query) => match query.encoding.as_deref() {
+            Some(name) => Encoding::from_name(name),
+            None => Some(Encoding::Json),
+        },
+        Err(_) => None,
+    }
+}
+
 pub async fn ws_route(
     req: HttpRequest,
     stream: web::Payload,
     srv: web::Data<Addr<server::OpenerServer>>,
+    traffic: web::Data<Arc<Traffic>>,
 ) -> Result<HttpResponse, Error> {
     log::info!("ws_route");
-    ws::start(
-        WsOpenerSession {
-            id: None,
-            hb: Instant::now(),
-            addr: srv.get_ref().clone(),
-        },
-        &req,
-        stream,
-    )
+
+    let encoding = match get_encoding(&req) {
+        Some(encoding) => encoding,
+        None => return Ok(HttpResponse::BadRequest().body("Unsupported encoding")),
+    };
+
+    // Controller is identified by client certificate if service requires it
+    let certificate = match req.conn_data::<ClientIdentity>() {
+        None => None,
+        Some(ClientIdentity {
+            common_name: Some(common_name),
+        }) => Some(common_name.clone()),
+        Some(ClientIdentity { common_name: None }) => {
+            log::error!("Controller has not presented client certificate");
+            return Ok(HttpResponse::Unauthorized().body("Client certificate is required"));
+        }
+    };
+
+    let subprotocols = Encoding::ALL.map(|e| e.subprotocol());
+
+    let session = WsOpenerSession {
+        id: None,
+        connection_id: rand::random(),
+        hb: Instant::now(),
+        addr: srv.get_ref().clone(),
+        encoding,
+        traffic: traffic.get_ref().clone(),
+        violations: 0,
+        certificate,
+    };
+
+    ws::WsResponseBuilder::new(session, &req, stream)
+        .protocols(&subprotocols)
+        .start()
 }
 
 pub async fn index_playground() -> actix_web::Result<HttpResponse> {
@@ -91,6 +172,7 @@ pub async fn index_playground() -> actix_web::Result<HttpResponse> {
 pub fn create_schema_with_context(
     db: Database,
     opener_server: Addr<server::OpenerServer>,
+    broker: Broker,
 ) -> Schema<graphql::Query, graphql::Mutation, graphql::Subscription> {
     let role_dataloader =
         DataLoader::new(RoleLoader { db: db.clone() }, async_std::task::spawn).max_batch_size(100);
@@ -119,6 +201,7 @@ pub fn create_schema_with_context(
     .register_output_type::<graphql::Error>()
     .data(db)
     .data(opener_server)
+    .data(broker)
     .data(role_dataloader)
     .data(user_dataloader)
     .data(barrier_manufacturer_dataloader)
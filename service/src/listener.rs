@@ -0,0 +1,59 @@
+//! Settings of HTTP listeners, controllers connect to their own listener separate from API
+
+use std::env;
+use std::time::Duration;
+
+use crate::tls::TlsConfig;
+
+/// Default address of API listener
+pub const API_BIND: &str = "0.0.0.0:4000";
+
+/// Default address of listener for controllers
+pub const DEVICE_BIND: &str = "0.0.0.0:8765";
+
+/// Address, limits, TLS and timeouts of one listener, variables of listener
+/// have common prefix, e.g. `DEVICE_BIND` and `DEVICE_TLS_CERT`
+#[derive(Clone, Debug)]
+pub struct ListenerConfig {
+    /// Address to bind, e.g. `0.0.0.0:8765`
+    pub bind: String,
+    pub tls: Option<TlsConfig>,
+    /// Maximum number of concurrent connections of every worker
+    pub max_connections: usize,
+    /// Time to receive headers of the first request, e.g. websocket handshake
+    pub request_timeout: Duration,
+    /// Time to close connection gracefully
+    pub disconnect_timeout: Duration,
+}
+
+impl ListenerConfig {
+    pub fn from_env(prefix: &str, bind: &str) -> Self {
+        let var = |name: &str| env::var(format!("{}{}", prefix, name)).ok();
+        let number = |name: &str, default: u64| {
+            var(name)
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+
+        ListenerConfig {
+            bind: var("BIND").unwrap_or_else(|| bind.to_string()),
+            tls: TlsConfig::from_env(prefix),
+            max_connections: number("MAX_CONNECTIONS", 25_000) as usize,
+            request_timeout: Duration::from_millis(number("REQUEST_TIMEOUT", 5_000)),
+            disconnect_timeout: Duration::from_millis(number("DISCONNECT_TIMEOUT", 5_000)),
+        }
+    }
+
+    /// Returns URL of listener on this host, e.g. `https://localhost:8765`
+    pub fn local_url(&self) -> String {
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        let port = self.bind.rsplit(':').next().unwrap_or_default();
+
+        format!("{}://localhost:{}", scheme, port)
+    }
+
+    /// Client certificates are checked by `/ws` of listener
+    pub fn client_auth(&self) -> bool {
+        self.tls.as_ref().is_some_and(|tls| tls.client_ca.is_some())
+    }
+}
@@ -7,10 +7,14 @@ use actix_web::{guard, web, App, HttpServer};
 use anyhow::Result;
 use env_logger::Env;
 
-use acs_service::server::OpenerServer;
+use acs_service::listener::{API_BIND, DEVICE_BIND};
+use acs_service::scheduler::Scheduler;
+use acs_service::server::{OpenerServer, Shutdown};
+use acs_service::tls;
 use acs_service::{
-    create_schema_with_context, get_count, index_api, index_playground, index_subscriptions,
-    init_db, init_tracer, shutdown_tracer, ws_route,
+    create_schema_with_context, get_broker, get_count, get_firmware, get_traffic, index_api,
+    index_playground, index_subscriptions, init_cluster, init_db, init_tracer, shutdown_tracer,
+    ws_route, Broker, BrokerConfig, ListenerConfig, Traffic,
 };
 
 #[actix_web::main]
@@ -24,15 +28,33 @@ async fn main() -> Result<()> {
 
     let openers_count = Arc::new(AtomicUsize::new(0));
 
-    let opener_server = OpenerServer::new(openers_count.clone(), db.clone()).start();
+    let traffic = Arc::new(Traffic::default());
 
-    let schema = create_schema_with_context(db.clone(), opener_server.clone());
+    let broker = Broker::new(BrokerConfig::from_env());
 
-    HttpServer::new(move || {
+    let cluster = init_cluster(&db, broker.clone());
+
+    let opener_server = OpenerServer::new(openers_count.clone(), db.clone(), cluster).start();
+
+    Scheduler::new(db.clone(), opener_server.clone()).start();
+
+    let schema = create_schema_with_context(db.clone(), opener_server.clone(), broker.clone());
+
+    let server = opener_server.clone();
+
+    let api_config = ListenerConfig::from_env("API_", API_BIND);
+    let device_config = ListenerConfig::from_env("DEVICE_", DEVICE_BIND);
+
+    let device_traffic = traffic.clone();
+    let device_server = opener_server.clone();
+
+    let api = HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
             .wrap(Cors::permissive())
             .app_data(web::Data::new(openers_count.clone()))
+            .app_data(web::Data::new(traffic.clone()))
+            .app_data(web::Data::new(broker.clone()))
             .app_data(web::Data::new(opener_server.clone()))
             .app_data(web::Data::new(schema.clone()))
             .route("/playground", web::get().to(index_playground))
@@ -44,11 +66,51 @@ async fn main() -> Result<()> {
                     .to(index_subscriptions),
             )
             .route("/count/", web::get().to(get_count))
+            .route("/traffic/", web::get().to(get_traffic))
+            .route("/broker/", web::get().to(get_broker))
+    })
+    .max_connections(api_config.max_connections)
+    .client_request_timeout(api_config.request_timeout)
+    .client_disconnect_timeout(api_config.disconnect_timeout);
+
+    // Controllers connect to their own listener, it may have another firewall policy than API
+    let devices = HttpServer::new(move || {
+        App::new()
+            .wrap(Logger::default())
+            .app_data(web::Data::new(device_traffic.clone()))
+            .app_data(web::Data::new(device_server.clone()))
+            .route("/dist/{file}", web::get().to(get_firmware))
             .service(web::resource("/ws").to(ws_route))
     })
-    .bind("0.0.0.0:4000")?
-    .run()
-    .await?;
+    .on_connect(tls::on_connect(device_config.client_auth()))
+    .max_connections(device_config.max_connections)
+    .client_request_timeout(device_config.request_timeout)
+    .client_disconnect_timeout(device_config.disconnect_timeout);
+
+    let api = match &api_config.tls {
+        Some(tls) => api.bind_rustls(&api_config.bind, tls.server_config()?)?,
+        None => api.bind(&api_config.bind)?,
+    };
+
+    let devices = match &device_config.tls {
+        Some(tls) => devices.bind_rustls(&device_config.bind, tls.server_config()?)?,
+        None => devices.bind(&device_config.bind)?,
+    };
+
+    log::info!(
+        "API listens on {} (TLS: {}), controllers on {} (TLS: {}, client certificates: {})",
+        api_config.bind,
+        api_config.tls.is_some(),
+        device_config.bind,
+        device_config.tls.is_some(),
+        device_config.client_auth()
+    );
+
+    futures::try_join!(api.run(), devices.run())?;
+
+    if let Err(e) = server.send(Shutdown).await {
+        log::error!("Failed to release sessions: {}", e);
+    }
 
     shutdown_tracer();
 
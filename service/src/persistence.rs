@@ -1,8 +1,13 @@
+pub(crate) mod auth_failure;
 pub(crate) mod barrier_manufacturer;
 pub(crate) mod barrier_model;
 pub(crate) mod client;
+pub(crate) mod command;
 pub(crate) mod error;
+pub(crate) mod event;
 pub(crate) mod opener;
 pub(crate) mod role;
+pub(crate) mod schedule;
+pub(crate) mod telemetry;
 pub(crate) mod user;
 pub(crate) mod utils;
@@ -0,0 +1,50 @@
+use anyhow::Result;
+use bson::Document;
+use chrono::{DateTime, Local};
+use mongodb::bson::doc;
+use mongodb::Database;
+use serde::{Deserialize, Serialize};
+
+/// Describes failed attempt of controller to authenticate in HELLO
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct AuthFailureEntity {
+    #[serde(rename = "serialNumber")]
+    pub serial_number: String,
+    pub reason: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: bson::DateTime,
+}
+
+pub(crate) async fn create_auth_failure(
+    db: &Database,
+    serial_number: &str,
+    reason: &str,
+) -> Result<()> {
+    let failures = db.collection::<AuthFailureEntity>("authFailures");
+
+    let failure = AuthFailureEntity {
+        serial_number: serial_number.to_string(),
+        reason: reason.to_string(),
+        created_at: bson::DateTime::from(Local::now()),
+    };
+
+    failures.insert_one(&failure, None).await?;
+
+    Ok(())
+}
+
+/// Returns number of failed attempts of controller since given time
+pub(crate) async fn count_auth_failures(
+    db: &Database,
+    serial_number: &str,
+    since: DateTime<Local>,
+) -> Result<u64> {
+    let docs = db.collection::<Document>("authFailures");
+
+    let filter = doc! {
+        "serialNumber": serial_number,
+        "createdAt": { "$gte": bson::DateTime::from(since) }
+    };
+
+    Ok(docs.count_documents(filter, None).await?)
+}
@@ -21,6 +21,7 @@ pub(crate) struct BarrierModelEntity {
     pub manufacturer_id: ObjectId,
 }
 
+#[allow(clippy::unnecessary_unwrap)]
 pub(crate) async fn get_barrier_models(
     db: &Database,
     manufacturer_id: Option<String>,
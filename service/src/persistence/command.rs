@@ -0,0 +1,296 @@
+use crate::persistence::opener::OpenerErrorEntity;
+use anyhow::Result;
+use bson::oid::ObjectId;
+use bson::Document;
+use chrono::Local;
+use futures::StreamExt;
+use mongodb::bson::doc;
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+use mongodb::Database;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Describes command sent to controller and its outcome
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct CommandEntity {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    #[serde(rename = "serialNumber")]
+    pub serial_number: String,
+    #[serde(rename = "commandType")]
+    pub command_type: String,
+    pub arguments: Document,
+
+    /// User who sent command
+    #[serde(rename = "userId")]
+    pub user_id: Option<ObjectId>,
+
+    pub status: String,
+    pub error: Option<OpenerErrorEntity>,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: bson::DateTime,
+    #[serde(rename = "sentAt")]
+    pub sent_at: Option<bson::DateTime>,
+    #[serde(rename = "completedAt")]
+    pub completed_at: Option<bson::DateTime>,
+
+    /// Queued command is not sent after this time, it is set for commands
+    /// delivered when opener is online
+    #[serde(rename = "expiresAt", default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<bson::DateTime>,
+}
+
+/// Puts command to the end of opener's queue, returns id of command
+pub(crate) async fn create_command(
+    db: &Database,
+    serial_number: &str,
+    command_type: &str,
+    arguments: Document,
+    user_id: &str,
+    expires_at: Option<bson::DateTime>,
+) -> Result<String> {
+    let commands = db.collection::<CommandEntity>("commands");
+
+    let command = CommandEntity {
+        id: ObjectId::new(),
+        serial_number: serial_number.to_string(),
+        command_type: command_type.to_string(),
+        arguments,
+        user_id: ObjectId::from_str(user_id).ok(),
+        status: "QUEUED".to_string(),
+        error: None,
+        created_at: bson::DateTime::from(Local::now()),
+        sent_at: None,
+        completed_at: None,
+        expires_at,
+    };
+
+    commands.insert_one(&command, None).await?;
+
+    Ok(command.id.to_hex())
+}
+
+/// Takes the oldest queued command of opener and marks it as sent
+pub(crate) async fn take_queued_command(
+    db: &Database,
+    serial_number: &str,
+) -> Result<Option<CommandEntity>> {
+    let commands = db.collection::<CommandEntity>("commands");
+
+    let filter = doc! {
+        "serialNumber": serial_number,
+        "status": "QUEUED",
+        "$or": [
+            { "expiresAt": { "$exists": false } },
+            { "expiresAt": { "$gt": bson::DateTime::now() } },
+        ]
+    };
+
+    let update = doc! {
+        "$set": {
+            "status": "PENDING",
+            "sentAt": bson::DateTime::from(Local::now()),
+        }
+    };
+
+    let options = FindOneAndUpdateOptions::builder()
+        .sort(doc! { "_id": 1 })
+        .return_document(ReturnDocument::After)
+        .build();
+
+    Ok(commands
+        .find_one_and_update(filter, update, options)
+        .await?)
+}
+
+/// Sets EXPIRED status to queued commands which were not sent in time, returns them
+pub(crate) async fn expire_queued_commands(db: &Database) -> Result<Vec<CommandEntity>> {
+    let commands = db.collection::<CommandEntity>("commands");
+
+    let filter = doc! {
+        "status": "QUEUED",
+        "expiresAt": { "$lte": bson::DateTime::now() }
+    };
+
+    let update = doc! {
+        "$set": {
+            "status": "EXPIRED",
+            "completedAt": bson::DateTime::from(Local::now()),
+        }
+    };
+
+    let options = FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .build();
+
+    // Commands are taken one by one, so only one instance reports each of them
+    let mut expired = Vec::new();
+
+    while let Some(command) = commands
+        .find_one_and_update(filter.clone(), update.clone(), options.clone())
+        .await?
+    {
+        expired.push(command);
+    }
+
+    Ok(expired)
+}
+
+/// Sets CANCELLED status to command if it is still queued,
+/// returns `false` if command is already sent or completed
+pub(crate) async fn cancel_queued_command(db: &Database, id: &ObjectId) -> Result<bool> {
+    let docs = db.collection::<Document>("commands");
+
+    let filter = doc! {
+        "_id": id,
+        "status": "QUEUED"
+    };
+
+    let update = doc! {
+        "$set": {
+            "status": "CANCELLED",
+            "completedAt": bson::DateTime::from(Local::now()),
+        }
+    };
+
+    let result = docs.update_one(filter, update, None).await?;
+
+    Ok(result.modified_count > 0)
+}
+
+pub(crate) async fn count_queued_commands(db: &Database, serial_number: &str) -> Result<u64> {
+    let docs = db.collection::<Document>("commands");
+
+    let filter = doc! {
+        "serialNumber": serial_number,
+        "status": "QUEUED"
+    };
+
+    Ok(docs.count_documents(filter, None).await?)
+}
+
+/// Returns position of queued command in opener's queue starting from 1
+pub(crate) async fn get_queue_position(
+    db: &Database,
+    serial_number: &str,
+    id: &ObjectId,
+) -> Result<u64> {
+    let docs = db.collection::<Document>("commands");
+
+    let filter = doc! {
+        "serialNumber": serial_number,
+        "status": "QUEUED",
+        "_id": { "$lte": id }
+    };
+
+    Ok(docs.count_documents(filter, None).await?)
+}
+
+/// Sets outcome of command if it is still pending
+pub(crate) async fn complete_command(
+    db: &Database,
+    id: &str,
+    status: &str,
+    error: Option<OpenerErrorEntity>,
+) -> Result<()> {
+    let docs = db.collection::<Document>("commands");
+
+    let command = doc! {
+        "status": status,
+        "error": bson::to_bson(&error)?,
+        "completedAt": bson::DateTime::from(Local::now()),
+    };
+
+    let filter = doc! {
+        "_id": ObjectId::from_str(id)?,
+        "status": "PENDING"
+    };
+
+    let update = doc! {
+        "$set": command
+    };
+
+    docs.update_one(filter, update, None).await?;
+
+    Ok(())
+}
+
+/// Sets FAILED status and error to all pending commands of opener
+pub(crate) async fn fail_pending_commands(
+    db: &Database,
+    serial_number: &str,
+    error: &OpenerErrorEntity,
+) -> Result<()> {
+    let docs = db.collection::<Document>("commands");
+
+    let command = doc! {
+        "status": "FAILED",
+        "error": bson::to_bson(error)?,
+        "completedAt": bson::DateTime::from(Local::now()),
+    };
+
+    let filter = doc! {
+        "serialNumber": serial_number,
+        "status": "PENDING"
+    };
+
+    let update = doc! {
+        "$set": command
+    };
+
+    docs.update_many(filter, update, None).await?;
+
+    Ok(())
+}
+
+pub(crate) async fn get_command_by_id(
+    db: &Database,
+    id: &ObjectId,
+) -> Result<Option<CommandEntity>> {
+    let commands = db.collection::<CommandEntity>("commands");
+
+    let command = commands
+        .find_one(
+            doc! {
+                "_id": id
+            },
+            None,
+        )
+        .await?;
+
+    Ok(command)
+}
+
+/// Returns commands of opener from newest to oldest,
+/// `after` is id of the last command from previous page
+pub(crate) async fn get_commands_by_sn(
+    db: &Database,
+    serial_number: &str,
+    first: i64,
+    after: Option<&str>,
+) -> Result<Vec<CommandEntity>> {
+    let commands = db.collection::<CommandEntity>("commands");
+
+    let mut filter = doc! {
+        "serialNumber": serial_number
+    };
+
+    if let Some(after) = after {
+        filter.insert("_id", doc! { "$lt": ObjectId::from_str(after)? });
+    }
+
+    let options = FindOptions::builder()
+        .sort(doc! { "_id": -1 })
+        .limit(first)
+        .build();
+
+    let mut cursor = commands.find(filter, options).await?;
+
+    let mut commands: Vec<CommandEntity> = Vec::new();
+    while let Some(command) = cursor.next().await {
+        commands.push(command?);
+    }
+
+    Ok(commands)
+}
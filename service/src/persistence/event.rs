@@ -0,0 +1,85 @@
+use anyhow::Result;
+use bson::oid::ObjectId;
+use bson::Document;
+use chrono::Local;
+use futures::StreamExt;
+use mongodb::bson::doc;
+use mongodb::options::FindOptions;
+use mongodb::Database;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+pub(crate) const PROTOCOL_VIOLATION_EVENT: &str = "PROTOCOL_VIOLATION";
+pub(crate) const POSSIBLE_CLONE_EVENT: &str = "POSSIBLE_CLONE";
+
+/// Describes event occurred with controller
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct EventEntity {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    #[serde(rename = "serialNumber")]
+    pub serial_number: String,
+    #[serde(rename = "eventType")]
+    pub event_type: String,
+    pub description: String,
+    /// Additional data depending on event type
+    pub data: Document,
+    #[serde(rename = "createdAt")]
+    pub created_at: bson::DateTime,
+}
+
+pub(crate) async fn create_event(
+    db: &Database,
+    serial_number: &str,
+    event_type: &str,
+    description: &str,
+    data: Document,
+) -> Result<EventEntity> {
+    let events = db.collection::<EventEntity>("events");
+
+    let event = EventEntity {
+        id: ObjectId::new(),
+        serial_number: serial_number.to_string(),
+        event_type: event_type.to_string(),
+        description: description.to_string(),
+        data,
+        created_at: bson::DateTime::from(Local::now()),
+    };
+
+    events.insert_one(&event, None).await?;
+
+    Ok(event)
+}
+
+/// Returns events of opener from newest to oldest,
+/// `after` is id of the last event from previous page
+pub(crate) async fn get_events_by_sn(
+    db: &Database,
+    serial_number: &str,
+    first: i64,
+    after: Option<&str>,
+) -> Result<Vec<EventEntity>> {
+    let events = db.collection::<EventEntity>("events");
+
+    let mut filter = doc! {
+        "serialNumber": serial_number
+    };
+
+    if let Some(after) = after {
+        filter.insert("_id", doc! { "$lt": ObjectId::from_str(after)? });
+    }
+
+    let options = FindOptions::builder()
+        .sort(doc! { "_id": -1 })
+        .limit(first)
+        .build();
+
+    let mut cursor = events.find(filter, options).await?;
+
+    let mut events: Vec<EventEntity> = Vec::new();
+    while let Some(event) = cursor.next().await {
+        events.push(event?);
+    }
+
+    Ok(events)
+}
@@ -1,3 +1,4 @@
+use crate::persistence::telemetry::TelemetryEntity;
 use crate::persistence::utils::check_already_exists;
 use anyhow::Result;
 use bson::oid::ObjectId;
@@ -5,10 +6,14 @@ use bson::Document;
 use chrono::Local;
 use futures::StreamExt;
 use mongodb::bson::doc;
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
 use mongodb::Database;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
+/// Number of the last nonces of controller kept to detect their reuse
+const USED_NONCES_LIMIT: i32 = 100;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub(crate) struct NewOpenerEntity {
     #[serde(rename = "serialNumber")]
@@ -27,18 +32,53 @@ pub(crate) struct UpdateOpenerEntity {
     pub password: Option<String>,
     pub nonce: Option<String>,
     pub version: Option<String>,
+    #[serde(rename = "protocolVersion")]
+    pub protocol_version: Option<u32>,
+    pub capabilities: Option<Vec<String>>,
     pub connected: Option<bool>,
+    /// Instance of service holding session of controller
+    #[serde(rename = "sessionOwner")]
+    pub session_owner: Option<String>,
     #[serde(rename = "barrierModelId")]
     pub barrier_model_id: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct TagResultEntity {
+    pub tag: String,
+    pub code: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub(crate) struct OpenerErrorEntity {
     #[serde(rename = "serialNumber")]
     pub serial_number: String,
     pub code: u32,
     pub description: String,
     pub details: Option<String>,
+    pub tags: Option<Vec<TagResultEntity>>,
+}
+
+/// Describes data reported by controller in reply to INFO command
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct OpenerInfoEntity {
+    pub version: String,
+    pub uptime: i64,
+    #[serde(rename = "barrierModel")]
+    pub barrier_model: String,
+    #[serde(rename = "barrierAlgorithm")]
+    pub barrier_algorithm: String,
+    #[serde(rename = "receivedAt")]
+    pub received_at: bson::DateTime,
+}
+
+/// Describes progress of firmware update reported by controller
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct UpdateProgressEntity {
+    pub stage: String,
+    pub percent: u32,
+    #[serde(rename = "changedAt")]
+    pub changed_at: bson::DateTime,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -55,7 +95,23 @@ pub(crate) struct OpenerEntity {
     pub login: String,
     pub password: String,
     pub nonce: Option<String>,
+
+    /// Secret of controller to prove its identity in HELLO
+    pub secret: Option<String>,
+
+    /// Version of protocol negotiated in the last HELLO
+    #[serde(rename = "protocolVersion")]
+    pub protocol_version: Option<u32>,
+
+    /// Commands supported by controller according to the last HELLO
+    pub capabilities: Option<Vec<String>>,
+
     pub connected: bool,
+
+    /// Instance of service holding session of connected controller
+    #[serde(rename = "sessionOwner")]
+    pub session_owner: Option<String>,
+
     #[serde(rename = "createdAt")]
     pub created_at: bson::DateTime,
     #[serde(rename = "updatedAt")]
@@ -78,6 +134,14 @@ pub(crate) struct OpenerEntity {
 
     #[serde(rename = "commandStatus")]
     pub command_status: String,
+
+    pub info: Option<OpenerInfoEntity>,
+
+    #[serde(rename = "updateProgress")]
+    pub update_progress: Option<UpdateProgressEntity>,
+
+    /// The last TELEMETRY of controller
+    pub telemetry: Option<TelemetryEntity>,
 }
 
 pub(crate) async fn create_opener(
@@ -91,6 +155,7 @@ pub(crate) async fn create_opener(
         "connected": false,
         "login": "admin",
         "password": "admin",
+        "secret": generate_secret(),
         "createdAt": bson::DateTime::from(Local::now())
     };
 
@@ -107,6 +172,40 @@ pub(crate) async fn create_opener(
         .unwrap())
 }
 
+/// Replaces secret of controller, returns `None` if opener is not found
+pub(crate) async fn regenerate_opener_secret(
+    db: &Database,
+    serial_number: &str,
+) -> Result<Option<OpenerEntity>> {
+    let docs = db.collection::<Document>("openers");
+
+    let filter = doc! {
+        "serialNumber": serial_number
+    };
+
+    let update = doc! {
+        "$set": {
+            "secret": generate_secret(),
+            "updatedAt": bson::DateTime::from(Local::now()),
+        }
+    };
+
+    let result = docs.update_one(filter.clone(), update, None).await?;
+
+    if result.matched_count == 0 {
+        return Ok(None);
+    }
+
+    let openers = db.collection::<OpenerEntity>("openers");
+
+    Ok(openers.find_one(filter, None).await?)
+}
+
+fn generate_secret() -> String {
+    hex::encode(rand::random::<[u8; 32]>())
+}
+
+#[allow(clippy::unnecessary_unwrap)]
 pub(crate) async fn update_opener(
     db: &Database,
     serial_number: &String,
@@ -153,6 +252,14 @@ pub(crate) async fn update_opener(
         opener.insert("version", new_opener.version.clone());
     }
 
+    if let Some(protocol_version) = new_opener.protocol_version {
+        opener.insert("protocolVersion", protocol_version);
+    }
+
+    if new_opener.capabilities.is_some() {
+        opener.insert("capabilities", new_opener.capabilities.clone());
+    }
+
     if new_opener.nonce.is_some() {
         opener.insert("nonce", new_opener.nonce.clone());
     }
@@ -161,6 +268,10 @@ pub(crate) async fn update_opener(
         opener.insert("connected", new_opener.connected);
     }
 
+    if new_opener.session_owner.is_some() {
+        opener.insert("sessionOwner", new_opener.session_owner.clone());
+    }
+
     if new_opener.barrier_model_id.is_some() {
         opener.insert(
             "barrierModelId",
@@ -183,6 +294,34 @@ pub(crate) async fn update_opener(
     Ok(openers.find_one(filter, None).await?.unwrap())
 }
 
+/// Remembers nonce sent by controller in HELLO,
+/// returns `false` if nonce is one of the last used ones
+pub(crate) async fn register_nonce(
+    db: &Database,
+    serial_number: &str,
+    nonce: &str,
+) -> Result<bool> {
+    let docs = db.collection::<Document>("openers");
+
+    let filter = doc! {
+        "serialNumber": serial_number,
+        "usedNonces": { "$ne": nonce }
+    };
+
+    let update = doc! {
+        "$push": {
+            "usedNonces": {
+                "$each": [nonce],
+                "$slice": -USED_NONCES_LIMIT
+            }
+        }
+    };
+
+    let result = docs.update_one(filter, update, None).await?;
+
+    Ok(result.modified_count > 0)
+}
+
 pub(crate) async fn get_opener_by_id(db: &Database, id: &str) -> Result<Option<OpenerEntity>> {
     let openers = db.collection::<OpenerEntity>("openers");
 
@@ -242,12 +381,7 @@ pub(crate) async fn set_error_to_opener(
 
     let opener = doc! {
         "commandStatus": command_status,
-        "last_error": {
-            "serial_number": last_error.serial_number,
-            "code": last_error.code,
-            "description": last_error.description,
-            "details": last_error.details,
-        },
+        "lastError": bson::to_bson(&last_error)?,
         "commandStatusChangedAt": now,
         "updatedAt": now
     };
@@ -300,6 +434,235 @@ pub(crate) async fn set_command_to_opener_with_model(
     Ok(openers.find_one(filter, None).await?.unwrap())
 }
 
+pub(crate) async fn set_info_to_opener(
+    db: &Database,
+    serial_number: &str,
+    command_status: &str,
+    barrier_model_id: Option<ObjectId>,
+    info: OpenerInfoEntity,
+) -> Result<OpenerEntity> {
+    let docs = db.collection::<Document>("openers");
+
+    let now = bson::DateTime::from(Local::now());
+
+    let mut opener = doc! {
+        "commandStatus": command_status,
+        "commandStatusChangedAt": now,
+        "updatedAt": now,
+        "lastError": bson::Bson::Null,
+        "version": info.version.clone(),
+        "info": bson::to_bson(&info)?,
+    };
+
+    if let Some(barrier_model_id) = barrier_model_id {
+        opener.insert("barrierModelId", barrier_model_id);
+    }
+
+    let filter = doc! {
+        "serialNumber": serial_number
+    };
+
+    let update = doc! {
+        "$set": opener
+    };
+
+    docs.update_one(filter.clone(), update, None).await?;
+
+    let openers = db.collection::<OpenerEntity>("openers");
+
+    Ok(openers.find_one(filter, None).await?.unwrap())
+}
+
+pub(crate) async fn set_update_progress_to_opener(
+    db: &Database,
+    serial_number: &str,
+    progress: UpdateProgressEntity,
+) -> Result<OpenerEntity> {
+    let docs = db.collection::<Document>("openers");
+
+    let opener = doc! {
+        "updateProgress": bson::to_bson(&progress)?,
+        "updatedAt": bson::DateTime::from(Local::now())
+    };
+
+    let filter = doc! {
+        "serialNumber": serial_number
+    };
+
+    let update = doc! {
+        "$set": opener
+    };
+
+    docs.update_one(filter.clone(), update, None).await?;
+
+    let openers = db.collection::<OpenerEntity>("openers");
+
+    Ok(openers.find_one(filter, None).await?.unwrap())
+}
+
+/// Marks opener as disconnected if its session is held by given instance of service,
+/// returns `None` if opener is connected to another instance
+pub(crate) async fn disconnect_opener(
+    db: &Database,
+    serial_number: &str,
+    session_owner: &str,
+) -> Result<Option<OpenerEntity>> {
+    let openers = db.collection::<OpenerEntity>("openers");
+
+    let filter = doc! {
+        "serialNumber": serial_number,
+        "sessionOwner": session_owner,
+    };
+
+    let update = doc! {
+        "$set": {
+            "connected": false,
+            "updatedAt": bson::DateTime::from(Local::now()),
+        },
+        "$unset": { "sessionOwner": "" }
+    };
+
+    let options = FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .build();
+
+    Ok(openers.find_one_and_update(filter, update, options).await?)
+}
+
+/// Marks as disconnected all openers held by given instance of service and openers
+/// connected without recorded owner, returns released openers
+pub(crate) async fn release_openers(
+    db: &Database,
+    session_owner: &str,
+) -> Result<Vec<OpenerEntity>> {
+    let openers = db.collection::<OpenerEntity>("openers");
+
+    let filter = doc! {
+        "connected": true,
+        "$or": [
+            { "sessionOwner": session_owner },
+            { "sessionOwner": { "$exists": false } },
+        ]
+    };
+
+    let mut cursor = openers.find(filter.clone(), None).await?;
+
+    let mut released: Vec<OpenerEntity> = Vec::new();
+    while let Some(opener) = cursor.next().await {
+        released.push(opener?);
+    }
+
+    let serial_numbers: Vec<&str> = released.iter().map(|o| o.serial_number.as_str()).collect();
+
+    // Opener could connect to another instance since it is found
+    let mut filter = filter;
+    filter.insert("serialNumber", doc! { "$in": serial_numbers });
+
+    let update = doc! {
+        "$set": {
+            "connected": false,
+            "updatedAt": bson::DateTime::from(Local::now()),
+        },
+        "$unset": { "sessionOwner": "" }
+    };
+
+    openers.update_many(filter, update, None).await?;
+
+    Ok(released)
+}
+
+/// Keeps the last telemetry of controller to show it without time series query
+pub(crate) async fn set_telemetry_to_opener(
+    db: &Database,
+    serial_number: &str,
+    telemetry: &TelemetryEntity,
+) -> Result<()> {
+    let docs = db.collection::<Document>("openers");
+
+    let filter = doc! {
+        "serialNumber": serial_number
+    };
+
+    let update = doc! {
+        "$set": {
+            "telemetry": bson::to_bson(telemetry)?,
+        }
+    };
+
+    docs.update_one(filter, update, None).await?;
+
+    Ok(())
+}
+
+/// Returns openers which command is pending since given time or earlier
+pub(crate) async fn get_openers_with_pending_command(
+    db: &Database,
+    changed_before: bson::DateTime,
+) -> Result<Vec<OpenerEntity>> {
+    let openers = db.collection::<OpenerEntity>("openers");
+
+    let filter = doc! {
+        "commandStatus": "PENDING",
+        "commandStatusChangedAt": { "$lte": changed_before }
+    };
+
+    let mut cursor = openers.find(filter, None).await?;
+
+    let mut openers: Vec<OpenerEntity> = Vec::new();
+    while let Some(opener) = cursor.next().await {
+        openers.push(opener?);
+    }
+
+    Ok(openers)
+}
+
+/// Sets FAILED status and error to opener only if its command is still pending.
+/// If `changed_at` is given, command must be pending since exactly this time,
+/// so newer command sent in between is not affected.
+/// Returns `None` if there is no such pending command.
+pub(crate) async fn fail_pending_command(
+    db: &Database,
+    serial_number: &str,
+    changed_at: Option<bson::DateTime>,
+    last_error: OpenerErrorEntity,
+) -> Result<Option<OpenerEntity>> {
+    let docs = db.collection::<Document>("openers");
+
+    let now = bson::DateTime::from(Local::now());
+
+    let opener = doc! {
+        "commandStatus": "FAILED",
+        "lastError": bson::to_bson(&last_error)?,
+        "commandStatusChangedAt": now,
+        "updatedAt": now
+    };
+
+    let mut filter = doc! {
+        "serialNumber": serial_number,
+        "commandStatus": "PENDING"
+    };
+
+    if let Some(changed_at) = changed_at {
+        filter.insert("commandStatusChangedAt", changed_at);
+    }
+
+    let update = doc! {
+        "$set": opener
+    };
+
+    let result = docs.update_one(filter, update, None).await?;
+
+    if result.modified_count == 0 {
+        return Ok(None);
+    }
+
+    let openers = db.collection::<OpenerEntity>("openers");
+
+    Ok(openers
+        .find_one(doc! { "serialNumber": serial_number }, None)
+        .await?)
+}
+
 pub(crate) async fn get_opener_by_sn(
     db: &Database,
     serial_number: &String,
@@ -318,6 +681,7 @@ pub(crate) async fn get_opener_by_sn(
     Ok(opener)
 }
 
+#[allow(clippy::unnecessary_unwrap)]
 pub(crate) async fn get_openers(
     db: &Database,
     user_id: Option<&String>,
@@ -0,0 +1,237 @@
+use anyhow::Result;
+use bson::oid::ObjectId;
+use chrono::Local;
+use futures::StreamExt;
+use mongodb::bson::doc;
+use mongodb::options::{FindOneAndReplaceOptions, FindOptions, ReturnDocument};
+use mongodb::Database;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Describes command sent to opener by schedule
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ScheduleEntity {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    #[serde(rename = "serialNumber")]
+    pub serial_number: String,
+
+    /// User who created or changed schedule, commands are sent on his behalf
+    #[serde(rename = "userId")]
+    pub user_id: Option<ObjectId>,
+
+    pub name: String,
+    #[serde(rename = "commandType")]
+    pub command_type: String,
+    pub door: Option<u32>,
+
+    /// Days of week from 1 (Monday) and local time `HH:MM` of weekly schedule
+    pub days: Option<Vec<u32>>,
+    pub time: Option<String>,
+
+    /// Cron expression with seconds, it is used instead of days and time
+    pub cron: Option<String>,
+
+    #[serde(rename = "timeZone")]
+    pub time_zone: String,
+
+    /// SKIP or CATCH_UP
+    #[serde(rename = "missedRunPolicy")]
+    pub missed_run_policy: String,
+
+    pub enabled: bool,
+
+    /// Absent for disabled schedule
+    #[serde(rename = "nextRunAt")]
+    pub next_run_at: Option<bson::DateTime>,
+    #[serde(rename = "lastRunAt")]
+    pub last_run_at: Option<bson::DateTime>,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: bson::DateTime,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: bson::DateTime,
+}
+
+/// Describes one run of schedule
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct ScheduleRunEntity {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    #[serde(rename = "scheduleId")]
+    pub schedule_id: ObjectId,
+    #[serde(rename = "serialNumber")]
+    pub serial_number: String,
+
+    /// Time when command should be sent by schedule
+    #[serde(rename = "plannedAt")]
+    pub planned_at: bson::DateTime,
+
+    /// SENT, QUEUED, MISSED or FAILED
+    pub status: String,
+    pub reason: Option<String>,
+
+    /// Number of runs missed before this one while service was not running
+    pub skipped: u32,
+
+    /// Command sent by run, its result is stored with command
+    #[serde(rename = "commandId")]
+    pub command_id: Option<ObjectId>,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: bson::DateTime,
+}
+
+pub(crate) async fn create_schedule(db: &Database, schedule: &ScheduleEntity) -> Result<()> {
+    let schedules = db.collection::<ScheduleEntity>("schedules");
+
+    schedules.insert_one(schedule, None).await?;
+
+    Ok(())
+}
+
+/// Replaces settings of schedule, returns `None` if it is not found
+pub(crate) async fn replace_schedule(
+    db: &Database,
+    schedule: &ScheduleEntity,
+) -> Result<Option<ScheduleEntity>> {
+    let schedules = db.collection::<ScheduleEntity>("schedules");
+
+    let options = FindOneAndReplaceOptions::builder()
+        .return_document(ReturnDocument::After)
+        .build();
+
+    Ok(schedules
+        .find_one_and_replace(doc! { "_id": schedule.id }, schedule, options)
+        .await?)
+}
+
+pub(crate) async fn delete_schedule(db: &Database, id: &str) -> Result<Option<ScheduleEntity>> {
+    let schedules = db.collection::<ScheduleEntity>("schedules");
+
+    Ok(schedules
+        .find_one_and_delete(doc! { "_id": ObjectId::from_str(id)? }, None)
+        .await?)
+}
+
+pub(crate) async fn get_schedule_by_id(db: &Database, id: &str) -> Result<Option<ScheduleEntity>> {
+    let schedules = db.collection::<ScheduleEntity>("schedules");
+
+    Ok(schedules
+        .find_one(doc! { "_id": ObjectId::from_str(id)? }, None)
+        .await?)
+}
+
+/// Returns schedules of opener in order of creation
+pub(crate) async fn get_schedules_by_sn(
+    db: &Database,
+    serial_number: &str,
+) -> Result<Vec<ScheduleEntity>> {
+    let schedules = db.collection::<ScheduleEntity>("schedules");
+
+    let options = FindOptions::builder().sort(doc! { "_id": 1 }).build();
+
+    let mut cursor = schedules
+        .find(doc! { "serialNumber": serial_number }, options)
+        .await?;
+
+    let mut schedules: Vec<ScheduleEntity> = Vec::new();
+    while let Some(schedule) = cursor.next().await {
+        schedules.push(schedule?);
+    }
+
+    Ok(schedules)
+}
+
+/// Returns enabled schedules which time to run has come
+pub(crate) async fn get_due_schedules(
+    db: &Database,
+    now: bson::DateTime,
+) -> Result<Vec<ScheduleEntity>> {
+    let schedules = db.collection::<ScheduleEntity>("schedules");
+
+    let filter = doc! {
+        "enabled": true,
+        "nextRunAt": { "$lte": now }
+    };
+
+    let options = FindOptions::builder().sort(doc! { "nextRunAt": 1 }).build();
+
+    let mut cursor = schedules.find(filter, options).await?;
+
+    let mut schedules: Vec<ScheduleEntity> = Vec::new();
+    while let Some(schedule) = cursor.next().await {
+        schedules.push(schedule?);
+    }
+
+    Ok(schedules)
+}
+
+/// Moves schedule to its next run if it is not taken by another instance,
+/// returns `false` if run is already taken or schedule is changed
+pub(crate) async fn take_schedule_run(
+    db: &Database,
+    id: &ObjectId,
+    planned_at: bson::DateTime,
+    next_run_at: Option<bson::DateTime>,
+) -> Result<bool> {
+    let schedules = db.collection::<ScheduleEntity>("schedules");
+
+    let filter = doc! {
+        "_id": id,
+        "enabled": true,
+        "nextRunAt": planned_at
+    };
+
+    let update = doc! {
+        "$set": {
+            "nextRunAt": next_run_at,
+            "lastRunAt": bson::DateTime::from(Local::now()),
+        }
+    };
+
+    let result = schedules.update_one(filter, update, None).await?;
+
+    Ok(result.modified_count > 0)
+}
+
+pub(crate) async fn create_schedule_run(db: &Database, run: &ScheduleRunEntity) -> Result<()> {
+    let runs = db.collection::<ScheduleRunEntity>("scheduleRuns");
+
+    runs.insert_one(run, None).await?;
+
+    Ok(())
+}
+
+/// Returns runs of schedule from newest to oldest,
+/// `after` is id of the last run from previous page
+pub(crate) async fn get_schedule_runs(
+    db: &Database,
+    schedule_id: &str,
+    first: i64,
+    after: Option<&str>,
+) -> Result<Vec<ScheduleRunEntity>> {
+    let runs = db.collection::<ScheduleRunEntity>("scheduleRuns");
+
+    let mut filter = doc! {
+        "scheduleId": ObjectId::from_str(schedule_id)?
+    };
+
+    if let Some(after) = after {
+        filter.insert("_id", doc! { "$lt": ObjectId::from_str(after)? });
+    }
+
+    let options = FindOptions::builder()
+        .sort(doc! { "_id": -1 })
+        .limit(first)
+        .build();
+
+    let mut cursor = runs.find(filter, options).await?;
+
+    let mut runs: Vec<ScheduleRunEntity> = Vec::new();
+    while let Some(run) = cursor.next().await {
+        runs.push(run?);
+    }
+
+    Ok(runs)
+}
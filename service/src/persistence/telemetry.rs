@@ -0,0 +1,66 @@
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use futures::StreamExt;
+use mongodb::bson::doc;
+use mongodb::options::FindOptions;
+use mongodb::Database;
+use serde::{Deserialize, Serialize};
+
+/// Describes state of controller reported in TELEMETRY
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct TelemetryEntity {
+    #[serde(rename = "serialNumber")]
+    pub serial_number: String,
+    /// Signal strength in dBm
+    #[serde(rename = "signalStrength")]
+    pub signal_strength: Option<i32>,
+    /// Temperature in degrees Celsius
+    pub temperature: Option<f64>,
+    /// Supply voltage in volts
+    pub voltage: Option<f64>,
+    pub uptime: i64,
+    #[serde(rename = "createdAt")]
+    pub created_at: bson::DateTime,
+}
+
+/// Adds sample to time series of telemetry
+pub(crate) async fn create_telemetry(db: &Database, telemetry: &TelemetryEntity) -> Result<()> {
+    let samples = db.collection::<TelemetryEntity>("telemetry");
+
+    samples.insert_one(telemetry, None).await?;
+
+    Ok(())
+}
+
+/// Returns telemetry of opener received in given period from oldest to newest
+pub(crate) async fn get_telemetry_by_sn(
+    db: &Database,
+    serial_number: &str,
+    from: DateTime<Local>,
+    to: DateTime<Local>,
+    limit: i64,
+) -> Result<Vec<TelemetryEntity>> {
+    let samples = db.collection::<TelemetryEntity>("telemetry");
+
+    let filter = doc! {
+        "serialNumber": serial_number,
+        "createdAt": {
+            "$gte": bson::DateTime::from(from),
+            "$lt": bson::DateTime::from(to),
+        }
+    };
+
+    let options = FindOptions::builder()
+        .sort(doc! { "createdAt": 1 })
+        .limit(limit)
+        .build();
+
+    let mut cursor = samples.find(filter, options).await?;
+
+    let mut samples: Vec<TelemetryEntity> = Vec::new();
+    while let Some(sample) = cursor.next().await {
+        samples.push(sample?);
+    }
+
+    Ok(samples)
+}
@@ -0,0 +1,257 @@
+//! Sends OPEN and CLOSE commands to openers by their schedules and records every run
+
+use actix::prelude::*;
+use bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use lazy_static::lazy_static;
+use mongodb::Database;
+use std::env;
+use std::time::Duration;
+
+use crate::persistence::opener::get_opener_by_sn;
+use crate::persistence::schedule::{
+    create_schedule_run, get_due_schedules, take_schedule_run, ScheduleEntity, ScheduleRunEntity,
+};
+use crate::server::{message, OpenerServer};
+
+mod rule;
+
+pub use rule::{parse_time_zone, ScheduleRule};
+
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(15);
+
+pub(crate) const SKIP_MISSED_RUNS: &str = "SKIP";
+pub(crate) const CATCH_UP_MISSED_RUNS: &str = "CATCH_UP";
+
+lazy_static! {
+    /// Run which is late more than this number of seconds is missed,
+    /// e.g. service was not running at planned time
+    static ref MISSED_RUN_GRACE: i64 = env::var("SCHEDULE_MISSED_RUN_GRACE")
+        .ok()
+        .and_then(|t| t.parse().ok())
+        .unwrap_or(60);
+}
+
+/// Returns rule and time zone of stored schedule
+pub(crate) fn schedule_rule(schedule: &ScheduleEntity) -> Result<(ScheduleRule, Tz), &'static str> {
+    let rule = match (&schedule.cron, &schedule.days, &schedule.time) {
+        (Some(cron), _, _) => ScheduleRule::cron(cron)?,
+        (None, Some(days), Some(time)) => ScheduleRule::weekly(days, time)?,
+        _ => return Err("Schedule has neither cron expression nor days and time"),
+    };
+
+    Ok((rule, parse_time_zone(&schedule.time_zone)?))
+}
+
+/// Outcome of run stored in `scheduleRuns`
+struct RunOutcome {
+    status: &'static str,
+    reason: Option<String>,
+    command_id: Option<ObjectId>,
+}
+
+impl RunOutcome {
+    fn missed(reason: &str) -> Self {
+        RunOutcome {
+            status: "MISSED",
+            reason: Some(reason.to_string()),
+            command_id: None,
+        }
+    }
+
+    fn failed(reason: &str) -> Self {
+        RunOutcome {
+            status: "FAILED",
+            reason: Some(reason.to_string()),
+            command_id: None,
+        }
+    }
+}
+
+pub struct Scheduler {
+    db: Database,
+    server: Addr<OpenerServer>,
+    /// Previous check of schedules is not finished yet
+    running: bool,
+}
+
+impl Scheduler {
+    pub fn new(db: Database, server: Addr<OpenerServer>) -> Self {
+        Scheduler {
+            db,
+            server,
+            running: false,
+        }
+    }
+
+    async fn run_due_schedules(db: &Database, server: &Addr<OpenerServer>) {
+        let now = Utc::now();
+
+        let schedules = match get_due_schedules(db, bson::DateTime::from(now)).await {
+            Err(e) => {
+                log::error!("Failed to get due schedules: {}", e);
+                return;
+            }
+            Ok(schedules) => schedules,
+        };
+
+        for schedule in schedules {
+            Scheduler::run_schedule(db, server, schedule, now).await;
+        }
+    }
+
+    async fn run_schedule(
+        db: &Database,
+        server: &Addr<OpenerServer>,
+        schedule: ScheduleEntity,
+        now: DateTime<Utc>,
+    ) {
+        let planned_at = match schedule.next_run_at {
+            Some(planned_at) => planned_at,
+            None => return,
+        };
+
+        let planned = planned_at.to_chrono();
+
+        // Runs between planned one and now are missed too, they are not repeated,
+        // only the latest of them is done
+        let (next_run_at, skipped, latest) = match schedule_rule(&schedule) {
+            Ok((rule, tz)) => (
+                rule.next_after(now, tz),
+                rule.count_between(planned, now, tz),
+                rule.last_between(planned, now, tz),
+            ),
+            Err(e) => {
+                log::error!("Schedule {} is invalid: {}", schedule.id, e);
+                (None, 0, None)
+            }
+        };
+
+        // Several instances check schedules, only one of them takes the run
+        match take_schedule_run(
+            db,
+            &schedule.id,
+            planned_at,
+            next_run_at.map(bson::DateTime::from),
+        )
+        .await
+        {
+            Err(e) => {
+                log::error!("Failed to take run of schedule {}: {}", schedule.id, e);
+                return;
+            }
+            Ok(false) => return,
+            Ok(true) => {}
+        }
+
+        let outcome = if next_run_at.is_none() {
+            RunOutcome::failed("Schedule is invalid")
+        } else {
+            Scheduler::execute(db, server, &schedule, planned, now, next_run_at).await
+        };
+
+        log::info!(
+            "Schedule {} of opener {} is run with status {}",
+            schedule.id,
+            schedule.serial_number,
+            outcome.status
+        );
+
+        let run = ScheduleRunEntity {
+            id: ObjectId::new(),
+            schedule_id: schedule.id,
+            serial_number: schedule.serial_number.clone(),
+            planned_at: latest.map(bson::DateTime::from).unwrap_or(planned_at),
+            status: outcome.status.to_string(),
+            reason: outcome.reason,
+            skipped,
+            command_id: outcome.command_id,
+            created_at: bson::DateTime::from(now),
+        };
+
+        if let Err(e) = create_schedule_run(db, &run).await {
+            log::error!("Failed to record run of schedule {}: {}", schedule.id, e);
+        }
+    }
+
+    /// Sends command of schedule unless run is missed and schedule skips such runs
+    async fn execute(
+        db: &Database,
+        server: &Addr<OpenerServer>,
+        schedule: &ScheduleEntity,
+        planned: DateTime<Utc>,
+        now: DateTime<Utc>,
+        next_run_at: Option<DateTime<Utc>>,
+    ) -> RunOutcome {
+        let catch_up = schedule.missed_run_policy == CATCH_UP_MISSED_RUNS;
+
+        if (now - planned).num_seconds() > *MISSED_RUN_GRACE && !catch_up {
+            return RunOutcome::missed("Service was not running at planned time");
+        }
+
+        let opener = match get_opener_by_sn(db, &schedule.serial_number).await {
+            Err(e) => {
+                log::error!("Failed to get opener {}: {}", schedule.serial_number, e);
+                return RunOutcome::failed("Internal server error");
+            }
+            Ok(None) => return RunOutcome::failed("Opener not found"),
+            Ok(Some(opener)) => opener,
+        };
+
+        // Command for disconnected opener is kept until the next run of schedule
+        let expires_at = match (opener.connected, catch_up) {
+            (true, _) => None,
+            (false, true) => next_run_at.map(bson::DateTime::from),
+            (false, false) => return RunOutcome::missed("Opener is not connected"),
+        };
+
+        let command = message::ScheduledCommand {
+            user_id: schedule.user_id.map(|id| id.to_hex()).unwrap_or_default(),
+            serial_number: schedule.serial_number.clone(),
+            command: schedule.command_type.clone(),
+            door: schedule.door,
+            expires_at,
+        };
+
+        let id = match server.send(command).await {
+            Err(e) => {
+                log::error!("Failed to send scheduled command to server: {}", e);
+                return RunOutcome::failed("Internal server error");
+            }
+            Ok(Err(e)) => return RunOutcome::failed(&e),
+            Ok(Ok(id)) => id,
+        };
+
+        RunOutcome {
+            status: if expires_at.is_some() {
+                "QUEUED"
+            } else {
+                "SENT"
+            },
+            reason: None,
+            command_id: ObjectId::parse_str(&id).ok(),
+        }
+    }
+}
+
+impl Actor for Scheduler {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(SCHEDULER_INTERVAL, |act, ctx| {
+            if act.running {
+                return;
+            }
+
+            act.running = true;
+
+            let db = act.db.clone();
+            let server = act.server.clone();
+
+            let fut = async move { Scheduler::run_due_schedules(&db, &server).await };
+
+            ctx.spawn(actix::fut::wrap_future::<_, Self>(fut).map(|_, act, _| act.running = false));
+        });
+    }
+}
@@ -0,0 +1,105 @@
+use chrono::{DateTime, Datelike, Duration, LocalResult, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+/// Limit of runs counted between two times, it protects from too frequent cron schedules
+const MAX_COUNTED_RUNS: u32 = 10_000;
+
+/// Describes when scheduled command is run in time zone of schedule
+pub enum ScheduleRule {
+    /// Days of week from 1 (Monday) to 7 (Sunday) at the same local time
+    Weekly { days: Vec<u32>, time: NaiveTime },
+    /// Cron expression with seconds, e.g. `0 0 8 * * Mon-Fri`
+    Cron(Box<cron::Schedule>),
+}
+
+impl ScheduleRule {
+    /// Time is given as `HH:MM`
+    pub fn weekly(days: &[u32], time: &str) -> Result<Self, &'static str> {
+        if days.is_empty() || days.iter().any(|d| !(1..=7).contains(d)) {
+            return Err("Days must be from 1 (Monday) to 7 (Sunday)");
+        }
+
+        let time = NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| "Time must be HH:MM")?;
+
+        let mut days = days.to_vec();
+        days.sort_unstable();
+        days.dedup();
+
+        Ok(ScheduleRule::Weekly { days, time })
+    }
+
+    pub fn cron(expression: &str) -> Result<Self, &'static str> {
+        let schedule =
+            cron::Schedule::from_str(expression).map_err(|_| "Invalid cron expression")?;
+
+        Ok(ScheduleRule::Cron(Box::new(schedule)))
+    }
+
+    /// Returns the first run strictly after given time
+    pub fn next_after(&self, after: DateTime<Utc>, tz: Tz) -> Option<DateTime<Utc>> {
+        match self {
+            ScheduleRule::Weekly { days, time } => {
+                let today = after.with_timezone(&tz).date_naive();
+
+                // The same day of the next week is checked too
+                (0..=7)
+                    .map(|offset| today + Duration::days(offset))
+                    .filter(|date| days.contains(&date.weekday().number_from_monday()))
+                    .filter_map(|date| local_time(tz, date.and_time(*time)))
+                    .find(|run| *run > after)
+            }
+            ScheduleRule::Cron(schedule) => schedule
+                .after(&after.with_timezone(&tz))
+                .next()
+                .map(|run| run.with_timezone(&Utc)),
+        }
+    }
+
+    /// Counts runs after `from` up to `to` inclusive
+    pub fn count_between(&self, from: DateTime<Utc>, to: DateTime<Utc>, tz: Tz) -> u32 {
+        self.runs_between(from, to, tz).count() as u32
+    }
+
+    /// Returns the latest run after `from` up to `to` inclusive
+    pub fn last_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        tz: Tz,
+    ) -> Option<DateTime<Utc>> {
+        self.runs_between(from, to, tz).last()
+    }
+
+    fn runs_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        tz: Tz,
+    ) -> impl Iterator<Item = DateTime<Utc>> + '_ {
+        std::iter::successors(self.next_after(from, tz), move |run| {
+            self.next_after(*run, tz)
+        })
+        .take_while(move |run| *run <= to)
+        .take(MAX_COUNTED_RUNS as usize)
+    }
+}
+
+/// Time zone is given by IANA name, e.g. `Europe/Moscow`
+pub fn parse_time_zone(name: &str) -> Result<Tz, &'static str> {
+    name.parse().map_err(|_| "Unknown time zone")
+}
+
+/// Converts local time to UTC, time skipped by DST change is moved forward by an hour
+/// and repeated time is taken the first time
+fn local_time(tz: Tz, time: chrono::NaiveDateTime) -> Option<DateTime<Utc>> {
+    let local = match tz.from_local_datetime(&time) {
+        LocalResult::Single(t) => t,
+        LocalResult::Ambiguous(earliest, _) => earliest,
+        LocalResult::None => tz
+            .from_local_datetime(&(time + Duration::hours(1)))
+            .earliest()?,
+    };
+
+    Some(local.with_timezone(&Utc))
+}
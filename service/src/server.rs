@@ -1,43 +1,1012 @@
+use acs_protocol::{
+    negotiate_version, verify_hello_proof, ErrorCode, EventData, LEGACY_CAPABILITIES,
+    LEGACY_PROTOCOL_VERSION,
+};
 use actix::prelude::*;
+use chrono::{DateTime, FixedOffset, Local};
+use lazy_static::lazy_static;
+use mongodb::bson::doc;
 use mongodb::Database;
-use std::collections::HashMap;
-use std::convert::TryInto;
-
-use crate::graphql::simple_broker::SimpleBroker;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::convert::{TryFrom, TryInto};
+use std::env;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::cluster::{Cluster, ClusterMessage, SESSION_HEARTBEAT_INTERVAL};
 use crate::graphql::{
-    CommandStatus, CommandType, OpenerCommandResult, OpenerConnectionChanged, OpenerError,
+    CommandStatus, CommandType, Event, OpenerCommandResult, OpenerConnectionChanged, OpenerError,
+    OpenerEvent, OpenerUpdateProgress, TagResult, UpdateStage,
 };
+use crate::persistence::auth_failure::{count_auth_failures, create_auth_failure};
 use crate::persistence::barrier_model::get_barrier_model_by_id;
+use crate::persistence::command::{
+    complete_command, count_queued_commands, create_command, expire_queued_commands,
+    fail_pending_commands, take_queued_command, CommandEntity,
+};
+use crate::persistence::event::{create_event, POSSIBLE_CLONE_EVENT, PROTOCOL_VIOLATION_EVENT};
 use crate::persistence::opener::{
-    get_opener_by_sn, set_command_to_opener_with_model, set_error_to_opener, update_opener,
-    OpenerErrorEntity, UpdateOpenerEntity,
+    disconnect_opener, fail_pending_command, get_opener_by_sn, get_openers_with_pending_command,
+    register_nonce, release_openers, set_command_to_opener, set_command_to_opener_with_model,
+    set_error_to_opener, set_info_to_opener, set_telemetry_to_opener,
+    set_update_progress_to_opener, update_opener, OpenerEntity, OpenerErrorEntity,
+    OpenerInfoEntity, TagResultEntity, UpdateOpenerEntity, UpdateProgressEntity,
 };
+use crate::persistence::telemetry::{create_telemetry, TelemetryEntity};
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc,
 };
 
-pub(crate) mod command;
-pub(crate) mod message;
+pub mod command;
+pub mod message;
+
+use command::CommandBody;
+
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Maximum number of commands waiting in opener's queue
+pub(crate) const MAX_QUEUED_COMMANDS: u64 = 16;
+
+/// Controller must send random nonce of at least this length in HELLO
+const MIN_NONCE_LENGTH: usize = 16;
+
+/// Controller is refused without checks after this number of failed
+/// authentication attempts during the window in seconds
+const AUTH_FAILURES_LIMIT: u64 = 5;
+const AUTH_FAILURES_WINDOW: i64 = 600;
+
+lazy_static! {
+    /// Time in seconds to wait for reply to command from controller
+    static ref COMMAND_TIMEOUT: i64 = env::var("COMMAND_TIMEOUT")
+        .ok()
+        .and_then(|t| t.parse().ok())
+        .unwrap_or(30);
+
+    /// Time in seconds to wait for reply to UPDATE command,
+    /// it includes firmware download and installation
+    static ref UPDATE_COMMAND_TIMEOUT: i64 = env::var("UPDATE_COMMAND_TIMEOUT")
+        .ok()
+        .and_then(|t| t.parse().ok())
+        .unwrap_or(600);
+
+    /// Openers created before secrets were issued are accepted without proof
+    /// until this time, so their firmware can be updated
+    static ref LEGACY_AUTH_UNTIL: Option<DateTime<FixedOffset>> = env::var("LEGACY_AUTH_UNTIL")
+        .ok()
+        .and_then(|t| DateTime::parse_from_rfc3339(&t).ok());
+
+    static ref DUPLICATE_CONNECTION_POLICY: DuplicateConnectionPolicy =
+        match env::var("DUPLICATE_CONNECTION_POLICY").as_deref() {
+            Ok("refuse") => DuplicateConnectionPolicy::Refuse,
+            _ => DuplicateConnectionPolicy::Replace,
+        };
+}
+
+/// Command sent to controller and waiting for reply
+struct PendingCommand {
+    command: command::Command,
+    sent_at: Instant,
+}
+
+impl PendingCommand {
+    fn new(command: command::Command) -> Self {
+        PendingCommand {
+            command,
+            sent_at: Instant::now(),
+        }
+    }
+}
+
+/// Controller which passed checks of HELLO
+struct AcceptedConnect {
+    opener: OpenerEntity,
+    model_id: Option<String>,
+    protocol_version: u32,
+}
+
+/// What to do when controller with already connected serial number sends HELLO
+#[derive(Clone, Copy, PartialEq)]
+enum DuplicateConnectionPolicy {
+    /// The newest connection wins, the old session is closed
+    Replace,
+    /// The new connection is refused
+    Refuse,
+}
+
+impl DuplicateConnectionPolicy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DuplicateConnectionPolicy::Replace => "REPLACE",
+            DuplicateConnectionPolicy::Refuse => "REFUSE",
+        }
+    }
+}
+
+/// Connected controller
+struct Session {
+    /// Unique id of websocket connection
+    connection_id: u64,
+    addr: Recipient<command::Command>,
+    close: Recipient<message::Close>,
+    /// Nonce sent by controller in HELLO
+    device_nonce: String,
+    /// Nonce issued by service for session
+    nonce: String,
+    /// Counter of the last command sent in session
+    counter: u64,
+    /// Commands controller declared in HELLO, other commands are not sent to it
+    capabilities: HashSet<String>,
+}
+
+impl Session {
+    fn new(msg: message::Connect) -> Self {
+        Session {
+            connection_id: msg.connection_id,
+            addr: msg.addr,
+            close: msg.close,
+            device_nonce: msg.nonce,
+            nonce: hex::encode(rand::random::<[u8; 16]>()),
+            counter: 0,
+            capabilities: capabilities(msg.capabilities).into_iter().collect(),
+        }
+    }
+
+    /// Returns challenge for the next command, counter is never reused in session
+    fn next_challenge(&mut self) -> command::Challenge {
+        self.counter += 1;
+
+        command::Challenge {
+            device_nonce: self.device_nonce.clone(),
+            nonce: self.nonce.clone(),
+            counter: self.counter,
+        }
+    }
+}
 
 pub struct OpenerServer {
-    sessions: HashMap<String, Recipient<command::SetCommand>>,
-    commands: HashMap<String, command::Command>,
+    sessions: HashMap<String, Session>,
+    /// Commands waiting for reply by command id, only one command per opener
+    commands: HashMap<String, PendingCommand>,
+    /// Openers for which the next queued command is being prepared
+    dispatching: HashSet<String>,
     count: Arc<AtomicUsize>,
     db: Database,
+    cluster: Cluster,
 }
 
+/// Marks sessions of instance disconnected before service is stopped
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Shutdown;
+
 impl OpenerServer {
-    pub fn new(count: Arc<AtomicUsize>, db: Database) -> Self {
+    pub fn new(count: Arc<AtomicUsize>, db: Database, cluster: Cluster) -> Self {
         OpenerServer {
             sessions: HashMap::new(),
             commands: HashMap::new(),
+            dispatching: HashSet::new(),
             count,
             db,
+            cluster,
+        }
+    }
+
+    /// Puts command to the end of opener's queue stored in commands collection,
+    /// command with expiry is kept until opener connects
+    fn enqueue_command<T: Serialize>(
+        &mut self,
+        serial_number: String,
+        name: &str,
+        arguments: &T,
+        user_id: String,
+        expires_at: Option<bson::DateTime>,
+        ctx: &mut Context<Self>,
+    ) {
+        let fut = self.queue_command(serial_number, name, arguments, user_id, expires_at);
+
+        ctx.spawn(fut.map(|_, _, _| ()));
+    }
+
+    /// Returns future which queues command and resolves to its id
+    fn queue_command<T: Serialize>(
+        &self,
+        serial_number: String,
+        name: &str,
+        arguments: &T,
+        user_id: String,
+        expires_at: Option<bson::DateTime>,
+    ) -> ResponseActFuture<Self, Result<String, String>> {
+        let arguments = match bson::to_document(arguments) {
+            Err(e) => {
+                log::error!("Failed to serialize {} command arguments: {}", name, e);
+                return Box::pin(actix::fut::ready(Err(e.to_string())));
+            }
+            Ok(arguments) => arguments,
+        };
+
+        let db: Database = self.db.clone();
+        let cluster = self.cluster.clone();
+        let sn = serial_number.clone();
+        let name = name.to_string();
+
+        let fut = async move {
+            let opener = match get_opener_by_sn(&db, &sn).await? {
+                Some(opener) => opener,
+                None => return Err(anyhow::anyhow!("Opener not found")),
+            };
+
+            if !supports_command(opener.capabilities.as_ref(), &name) {
+                return Err(anyhow::anyhow!("Opener does not support {} command", name));
+            }
+
+            if count_queued_commands(&db, &sn).await? >= MAX_QUEUED_COMMANDS {
+                return Err(anyhow::anyhow!("Command queue is full"));
+            }
+
+            let id = create_command(&db, &sn, &name, arguments, &user_id, expires_at).await?;
+
+            if expires_at.is_some() {
+                OpenerServer::publish_command_status(
+                    &db,
+                    &cluster,
+                    &sn,
+                    &name,
+                    CommandStatus::Queued,
+                )
+                .await;
+            }
+
+            Ok::<String, anyhow::Error>(id)
+        };
+
+        let wrapped_future = actix::fut::wrap_future::<_, Self>(fut);
+
+        Box::pin(wrapped_future.map(move |result, actor, ctx| match result {
+            Ok(id) => {
+                log::info!("Command {} queued to opener {}", id, serial_number);
+
+                if actor.sessions.contains_key(&serial_number) {
+                    actor.dispatch(serial_number, ctx);
+                } else {
+                    actor.route_command(serial_number, ctx);
+                }
+
+                Ok(id)
+            }
+            Err(e) => {
+                log::error!("Failed to queue command to opener {}: {}", serial_number, e);
+                Err(e.to_string())
+            }
+        }))
+    }
+
+    /// Asks instance holding session of opener to send its queued commands
+    fn route_command(&self, serial_number: String, ctx: &mut Context<Self>) {
+        let cluster = self.cluster.clone();
+
+        let fut = async move {
+            let owner = match cluster.session_owner(&serial_number).await {
+                Err(e) => {
+                    log::error!("Failed to find session of opener {}: {}", serial_number, e);
+                    return;
+                }
+                Ok(Some(owner)) if owner != cluster.instance_id() => owner,
+                Ok(Some(_)) => return,
+                // Owner could stop without unregistering session, command stays queued
+                // until opener connects to any instance
+                Ok(None) => {
+                    log::info!(
+                        "Opener {} has no live session, command stays queued",
+                        serial_number
+                    );
+                    return;
+                }
+            };
+
+            log::info!(
+                "Route command of opener {} to instance {}",
+                serial_number,
+                owner
+            );
+
+            let message = ClusterMessage::Dispatch {
+                serial_number: serial_number.clone(),
+            };
+
+            if let Err(e) = cluster.send(&owner, message).await {
+                log::error!("Failed to route command of opener {}: {}", serial_number, e);
+            }
+        };
+
+        ctx.spawn(actix::fut::wrap_future::<_, Self>(fut));
+    }
+
+    /// Sends the next queued command to opener if it is connected
+    /// and does not process other command now
+    fn dispatch(&mut self, serial_number: String, ctx: &mut Context<Self>) {
+        if self.dispatching.contains(&serial_number)
+            || self
+                .commands
+                .values()
+                .any(|c| c.command.serial_number() == serial_number)
+        {
+            return;
+        }
+
+        let (challenge, capabilities) = match self.sessions.get_mut(&serial_number) {
+            Some(session) => (session.next_challenge(), session.capabilities.clone()),
+            None => return,
+        };
+
+        self.dispatching.insert(serial_number.clone());
+
+        let db: Database = self.db.clone();
+        let cluster = self.cluster.clone();
+        let sn = serial_number.clone();
+
+        let fut = async move {
+            OpenerServer::next_command(&db, &cluster, &sn, &challenge, &capabilities).await
+        };
+
+        let wrapped_future = actix::fut::wrap_future::<_, Self>(fut);
+
+        ctx.spawn(wrapped_future.map(move |result, actor, ctx| {
+            actor.dispatching.remove(&serial_number);
+
+            match result {
+                Ok(Some(command)) => actor.send_command(command, ctx),
+                Ok(None) => {}
+                Err(e) => log::error!(
+                    "Failed to dispatch command to opener {}: {}",
+                    serial_number,
+                    e
+                ),
+            }
+        }));
+    }
+
+    fn send_command(&mut self, command: command::Command, ctx: &mut Context<Self>) {
+        let serial_number = command.serial_number().to_string();
+
+        let addr = match self.sessions.get(&serial_number) {
+            Some(session) => session.addr.clone(),
+            None => {
+                // Opener is disconnected while command was prepared
+                let db: Database = self.db.clone();
+                let cluster = self.cluster.clone();
+
+                let fut = async move {
+                    if let Err(e) = OpenerServer::fail_command(
+                        &db,
+                        &cluster,
+                        &serial_number,
+                        None,
+                        ErrorCode::Disconnected.into(),
+                        "Controller disconnected",
+                    )
+                    .await
+                    {
+                        log::error!("Failed to fail command of opener {}: {}", serial_number, e);
+                    }
+                };
+
+                ctx.spawn(actix::fut::wrap_future::<_, Self>(fut));
+
+                return;
+            }
+        };
+
+        log::info!(
+            "Send {} command {} to opener {}",
+            command.name(),
+            command.id(),
+            serial_number
+        );
+
+        self.commands.insert(
+            command.id().to_string(),
+            PendingCommand::new(command.clone()),
+        );
+
+        let fut = async move {
+            let name = command.name().to_string();
+
+            if let Err(e) = addr.send(command).await {
+                log::error!("Failed to send {} command to controller: {}", name, e);
+            }
+        };
+
+        ctx.spawn(actix::fut::wrap_future::<_, Self>(fut));
+    }
+
+    /// Takes the oldest queued command of opener and marks opener as busy with it
+    async fn next_command(
+        db: &Database,
+        cluster: &Cluster,
+        serial_number: &str,
+        challenge: &command::Challenge,
+        capabilities: &HashSet<String>,
+    ) -> Result<Option<command::Command>, String> {
+        let opener = match get_opener_by_sn(db, &serial_number.to_string()).await {
+            Err(e) => {
+                log::error!("Failed to found opener {}: {}", serial_number, e);
+                return Err(e.to_string());
+            }
+            Ok(Some(opener)) => opener,
+            Ok(None) => return Err("Opener not found".to_string()),
+        };
+
+        let record = loop {
+            let record = match take_queued_command(db, serial_number).await {
+                Err(e) => {
+                    log::error!("Failed to get queued command of {}: {}", serial_number, e);
+                    return Err(e.to_string());
+                }
+                Ok(None) => return Ok(None),
+                Ok(Some(record)) => record,
+            };
+
+            if capabilities.contains(&record.command_type) {
+                break record;
+            }
+
+            // Command could be queued before controller reconnected with other firmware
+            log::error!(
+                "Opener {} does not support {} command",
+                serial_number,
+                record.command_type
+            );
+
+            OpenerServer::fail_unsupported_command(db, cluster, serial_number, &record).await?;
+        };
+
+        let id = record.id.to_hex();
+
+        let command = match command::Command::new(
+            id.clone(),
+            serial_number.to_string(),
+            &record.command_type,
+            record.arguments,
+            challenge,
+            &opener.login,
+            &opener.password,
+        ) {
+            Err(e) => {
+                log::error!("Failed to restore queued command {}: {}", id, e);
+
+                if let Err(e) = complete_command(db, &id, "FAILED", None).await {
+                    log::error!("Failed to update command {}: {}", id, e);
+                }
+
+                return Err(e.to_string());
+            }
+            Ok(command) => command,
+        };
+
+        if let Err(e) = set_command_to_opener(db, serial_number, "PENDING", command.name()).await {
+            log::error!("Failed to update opener {}: {}", serial_number, e);
+            return Err(e.to_string());
+        }
+
+        // Command kept for disconnected opener is delivered now
+        if record.expires_at.is_some() {
+            if let Ok(command_type) = record.command_type.as_str().try_into() {
+                cluster
+                    .publish(OpenerCommandResult {
+                        serial_number: serial_number.to_string(),
+                        command_type,
+                        command_status: CommandStatus::Pending,
+                        error: None,
+                        user_id: opener.user_id.map(|user_id| user_id.to_string()),
+                    })
+                    .await;
+            }
+        }
+
+        Ok(Some(command))
+    }
+
+    /// Fails queued command which controller does not support without sending it
+    async fn fail_unsupported_command(
+        db: &Database,
+        cluster: &Cluster,
+        serial_number: &str,
+        record: &CommandEntity,
+    ) -> Result<(), String> {
+        if let Err(e) =
+            set_command_to_opener(db, serial_number, "PENDING", &record.command_type).await
+        {
+            log::error!("Failed to update opener {}: {}", serial_number, e);
+            return Err(e.to_string());
+        }
+
+        OpenerServer::fail_command(
+            db,
+            cluster,
+            serial_number,
+            None,
+            ErrorCode::NotImplemented.into(),
+            "Command is not supported by controller",
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Removes command from in-flight ones when its reply is processed
+    /// and sends the next queued command
+    fn finish_command<F>(
+        id: String,
+        serial_number: String,
+        fut: F,
+    ) -> ResponseActFuture<Self, Result<(), String>>
+    where
+        F: Future<Output = Result<(), String>> + 'static,
+    {
+        let wrapped_future = actix::fut::wrap_future::<_, Self>(fut);
+
+        Box::pin(wrapped_future.map(move |result, actor, ctx| {
+            actor.commands.remove(&id);
+            actor.dispatch(serial_number, ctx);
+            result
+        }))
+    }
+
+    /// Finds pending command by id sent back by controller.
+    /// Controllers with old firmware do not send id back,
+    /// so the last command sent to controller is used for them.
+    fn find_command(&self, serial_number: &str, id: Option<&str>) -> Option<&PendingCommand> {
+        match id {
+            Some(id) => self
+                .commands
+                .get(id)
+                .filter(|c| c.command.serial_number() == serial_number),
+            None => self
+                .commands
+                .values()
+                .filter(|c| c.command.serial_number() == serial_number)
+                .max_by_key(|c| c.sent_at),
+        }
+    }
+
+    /// Fails command sent to replaced session, then sends queued commands to new session
+    fn replace_session(&mut self, serial_number: String, ctx: &mut Context<Self>) {
+        self.commands
+            .retain(|_, c| c.command.serial_number() != serial_number);
+
+        let db: Database = self.db.clone();
+        let cluster = self.cluster.clone();
+        let sn = serial_number.clone();
+
+        let fut = async move {
+            OpenerServer::fail_command(
+                &db,
+                &cluster,
+                &sn,
+                None,
+                ErrorCode::Disconnected.into(),
+                "Controller reconnected",
+            )
+            .await
+        };
+
+        let wrapped_future = actix::fut::wrap_future::<_, Self>(fut);
+
+        ctx.spawn(wrapped_future.map(move |result, actor, ctx| {
+            if let Err(e) = result {
+                log::error!("Failed to fail command of opener {}: {}", serial_number, e);
+            }
+
+            actor.dispatch(serial_number, ctx);
+        }));
+    }
+
+    /// Records event about two connections with the same serial number
+    fn report_clone(
+        &self,
+        serial_number: &str,
+        policy: DuplicateConnectionPolicy,
+        ctx: &mut Context<Self>,
+    ) {
+        log::error!(
+            "Opener {} is already connected, possible cloned device",
+            serial_number
+        );
+
+        let db: Database = self.db.clone();
+        let cluster = self.cluster.clone();
+        let sn = serial_number.to_string();
+
+        let fut = async move {
+            let data = doc! {
+                "policy": policy.as_str(),
+            };
+
+            if let Err(e) = OpenerServer::record_event(
+                &db,
+                &cluster,
+                &sn,
+                POSSIBLE_CLONE_EVENT,
+                "Second connection with the same serial number",
+                data,
+            )
+            .await
+            {
+                log::error!("Failed to record event of opener {}: {}", sn, e);
+            }
+        };
+
+        ctx.spawn(actix::fut::wrap_future::<_, Self>(fut));
+    }
+
+    /// Periodically refreshes sessions of instance in cluster,
+    /// other instances treat sessions which are not refreshed as stale
+    fn session_heartbeat(&self, ctx: &mut Context<Self>) {
+        ctx.run_interval(SESSION_HEARTBEAT_INTERVAL, |act, ctx| {
+            let cluster = act.cluster.clone();
+            let serial_numbers: Vec<String> = act.sessions.keys().cloned().collect();
+
+            let fut = async move {
+                if let Err(e) = cluster.refresh_sessions(&serial_numbers).await {
+                    log::error!("Failed to refresh sessions in cluster: {}", e);
+                }
+            };
+
+            ctx.spawn(actix::fut::wrap_future::<_, Self>(fut));
+        });
+    }
+
+    /// Resets `connected` flag of openers left after crash or restart of instance,
+    /// HELLO is not processed until it is done
+    fn release_openers(&self, ctx: &mut Context<Self>) {
+        let db: Database = self.db.clone();
+        let cluster = self.cluster.clone();
+
+        let fut = async move {
+            let openers = release_openers(&db, cluster.instance_id())
+                .await
+                .map_err(|e| e.to_string())?;
+
+            OpenerServer::publish_released(&db, &cluster, openers, "Service restarted").await;
+
+            Ok::<(), String>(())
+        };
+
+        ctx.wait(actix::fut::wrap_future::<_, Self>(fut).map(|result, _, _| {
+            if let Err(e) = result {
+                log::error!("Failed to reset connected openers: {}", e);
+            }
+        }));
+    }
+
+    /// Fails commands of released openers and notifies subscribers
+    async fn publish_released(
+        db: &Database,
+        cluster: &Cluster,
+        openers: Vec<OpenerEntity>,
+        reason: &str,
+    ) {
+        for opener in openers {
+            log::info!("Opener {} is marked disconnected", opener.serial_number);
+
+            if let Err(e) = OpenerServer::fail_command(
+                db,
+                cluster,
+                &opener.serial_number,
+                None,
+                ErrorCode::Disconnected.into(),
+                reason,
+            )
+            .await
+            {
+                log::error!(
+                    "Failed to fail command of opener {}: {}",
+                    opener.serial_number,
+                    e
+                );
+            }
+
+            cluster
+                .publish(OpenerConnectionChanged {
+                    serial_number: opener.serial_number,
+                    connected: false,
+                    user_id: opener.user_id.map(|user_id| user_id.to_string()),
+                })
+                .await;
+        }
+    }
+
+    /// Periodically fails commands which controllers did not reply in time
+    fn watchdog(&self, ctx: &mut Context<Self>) {
+        ctx.run_interval(WATCHDOG_INTERVAL, |act, ctx| {
+            let db: Database = act.db.clone();
+            let cluster = act.cluster.clone();
+            let started = Instant::now();
+
+            let fut = async move { OpenerServer::handle_expired_commands(&db, &cluster).await };
+
+            let wrapped_future = actix::fut::wrap_future::<_, Self>(fut);
+
+            ctx.spawn(wrapped_future.map(move |result, actor, ctx| match result {
+                Ok(expired) => {
+                    // Newer command could be sent while expired one was processed
+                    actor.commands.retain(|_, c| {
+                        c.sent_at > started
+                            || !expired.iter().any(|sn| sn == c.command.serial_number())
+                    });
+
+                    for serial_number in expired {
+                        actor.dispatch(serial_number, ctx);
+                    }
+                }
+                Err(e) => log::error!("Failed to check expired commands: {}", e),
+            }));
+        });
+    }
+
+    /// Marks queued commands which were not delivered before their expiry as expired
+    /// and notifies subscribers about them
+    async fn handle_expired_queued_commands(db: &Database, cluster: &Cluster) {
+        let expired = match expire_queued_commands(db).await {
+            Err(e) => {
+                log::error!("Failed to expire queued commands: {}", e);
+                return;
+            }
+            Ok(expired) => expired,
+        };
+
+        for record in expired {
+            log::warn!(
+                "Command {} of opener {} is expired before delivery",
+                record.id,
+                record.serial_number
+            );
+
+            OpenerServer::publish_command_status(
+                db,
+                cluster,
+                &record.serial_number,
+                &record.command_type,
+                CommandStatus::Expired,
+            )
+            .await;
+        }
+    }
+
+    /// Notifies subscribers about status of command which has no result yet
+    async fn publish_command_status(
+        db: &Database,
+        cluster: &Cluster,
+        serial_number: &str,
+        command_type: &str,
+        command_status: CommandStatus,
+    ) {
+        let command_type = match command_type.try_into() {
+            Err(e) => {
+                log::error!(
+                    "Failed to publish status of {} command: {}",
+                    command_type,
+                    e
+                );
+                return;
+            }
+            Ok(command_type) => command_type,
+        };
+
+        let opener = match get_opener_by_sn(db, &serial_number.to_string()).await {
+            Err(e) => {
+                log::error!("Failed to found opener {}: {}", serial_number, e);
+                return;
+            }
+            Ok(Some(opener)) => opener,
+            Ok(None) => return,
+        };
+
+        cluster
+            .publish(OpenerCommandResult {
+                serial_number: serial_number.to_string(),
+                command_type,
+                command_status,
+                error: None,
+                user_id: opener.user_id.map(|user_id| user_id.to_string()),
+            })
+            .await;
+    }
+
+    /// Fails all pending commands which deadline is passed,
+    /// returns serial numbers of openers with failed commands
+    async fn handle_expired_commands(
+        db: &Database,
+        cluster: &Cluster,
+    ) -> Result<Vec<String>, String> {
+        OpenerServer::handle_expired_queued_commands(db, cluster).await;
+
+        let now = bson::DateTime::now().timestamp_millis();
+
+        let min_timeout = (*COMMAND_TIMEOUT).min(*UPDATE_COMMAND_TIMEOUT);
+
+        let openers = match get_openers_with_pending_command(
+            db,
+            bson::DateTime::from_millis(now - min_timeout * 1000),
+        )
+        .await
+        {
+            Err(e) => {
+                log::error!("Failed to find openers with pending command: {}", e);
+                return Err(e.to_string());
+            }
+            Ok(openers) => openers,
+        };
+
+        let mut expired = Vec::new();
+
+        for opener in openers {
+            let changed_at = match opener.command_status_changed_at {
+                Some(changed_at) => changed_at,
+                None => continue,
+            };
+
+            let timeout = match opener.last_command_type.as_deref() {
+                Some(command::UPDATE_COMMAND) => *UPDATE_COMMAND_TIMEOUT,
+                _ => *COMMAND_TIMEOUT,
+            };
+
+            if changed_at.timestamp_millis() + timeout * 1000 > now {
+                continue;
+            }
+
+            if OpenerServer::held_by_other_instance(cluster, &opener).await {
+                continue;
+            }
+
+            log::warn!(
+                "Command of opener {} is timed out after {} seconds",
+                opener.serial_number,
+                timeout
+            );
+
+            match OpenerServer::fail_command(
+                db,
+                cluster,
+                &opener.serial_number,
+                Some(changed_at),
+                ErrorCode::Timeout.into(),
+                "Timeout",
+            )
+            .await
+            {
+                Err(e) => log::error!(
+                    "Failed to fail command of opener {}: {}",
+                    opener.serial_number,
+                    e
+                ),
+                Ok(true) => expired.push(opener.serial_number),
+                Ok(false) => {}
+            }
         }
+
+        Ok(expired)
     }
 
-    async fn handle_connect(db: &Database, msg: &message::Connect) -> Result<(), String> {
+    /// Command sent by another live instance is failed only by it, otherwise
+    /// that instance would keep it in-flight and never send the next queued command
+    async fn held_by_other_instance(cluster: &Cluster, opener: &OpenerEntity) -> bool {
+        match opener.session_owner.as_deref() {
+            Some(owner) if owner != cluster.instance_id() => {}
+            _ => return false,
+        }
+
+        match cluster.session_owner(&opener.serial_number).await {
+            // Owner is stopped, its command is failed by any instance
+            Ok(None) => false,
+            Ok(Some(owner)) => owner != cluster.instance_id(),
+            Err(e) => {
+                log::error!(
+                    "Failed to find session owner of opener {}: {}",
+                    opener.serial_number,
+                    e
+                );
+                true
+            }
+        }
+    }
+
+    /// Sets FAILED status to pending command of opener and publishes result,
+    /// returns `false` if opener has no pending command
+    async fn fail_command(
+        db: &Database,
+        cluster: &Cluster,
+        serial_number: &str,
+        changed_at: Option<bson::DateTime>,
+        code: u32,
+        description: &str,
+    ) -> Result<bool, String> {
+        let error = OpenerErrorEntity {
+            serial_number: serial_number.to_string(),
+            code,
+            description: description.to_string(),
+            details: None,
+            tags: None,
+        };
+
+        let opener = match fail_pending_command(db, serial_number, changed_at, error.clone()).await
+        {
+            Err(e) => {
+                log::error!("Failed to update opener {}: {}", serial_number, e);
+                return Err(e.to_string());
+            }
+            Ok(None) => return Ok(false),
+            Ok(Some(opener)) => {
+                log::info!("Opener {} updated", serial_number);
+                opener
+            }
+        };
+
+        if let Err(e) = fail_pending_commands(db, serial_number, &error).await {
+            log::error!(
+                "Failed to update commands of opener {}: {}",
+                serial_number,
+                e
+            );
+        }
+
+        log::info!("Publish failed command result");
+
+        let command_type: CommandType = opener
+            .last_command_type
+            .as_deref()
+            .unwrap_or_default()
+            .try_into()?;
+
+        cluster
+            .publish(OpenerCommandResult {
+                serial_number: serial_number.to_string(),
+                command_type,
+                command_status: CommandStatus::Failed,
+                error: Some(OpenerError {
+                    serial_number: serial_number.to_string(),
+                    code,
+                    description: description.to_string(),
+                    details: None,
+                    tags: None,
+                }),
+                user_id: opener.user_id.map(|user_id| user_id.to_string()),
+            })
+            .await;
+
+        Ok(true)
+    }
+
+    /// Records failed authentication attempt of controller and refuses connection
+    async fn reject_connect<T>(
+        db: &Database,
+        msg: &message::Connect,
+        reason: &str,
+    ) -> Result<T, String> {
+        log::error!(
+            "Opener {} failed to authenticate: {}",
+            msg.serial_number,
+            reason
+        );
+
+        if let Err(e) = create_auth_failure(db, &msg.serial_number, reason).await {
+            log::error!(
+                "Failed to record auth failure of {}: {}",
+                msg.serial_number,
+                e
+            );
+        }
+
+        Err(reason.to_string())
+    }
+
+    /// Checks HELLO of controller without changing state of opener,
+    /// returns opener with negotiated protocol version
+    async fn authenticate_connect(
+        db: &Database,
+        msg: &message::Connect,
+    ) -> Result<AcceptedConnect, String> {
         log::info!("Process hello from opener {}", msg.serial_number);
 
         let model = if !msg.barrier_model.is_empty() {
@@ -52,6 +1021,24 @@ impl OpenerServer {
             None
         };
 
+        let since = Local::now() - chrono::Duration::seconds(AUTH_FAILURES_WINDOW);
+
+        match count_auth_failures(db, &msg.serial_number, since).await {
+            Err(e) => {
+                log::error!(
+                    "Failed to count auth failures of {}: {}",
+                    msg.serial_number,
+                    e
+                );
+                return Err(e.to_string());
+            }
+            Ok(count) if count >= AUTH_FAILURES_LIMIT => {
+                log::error!("Opener {} is temporarily blocked", msg.serial_number);
+                return Err("Too many failed authentication attempts".to_string());
+            }
+            Ok(_) => {}
+        }
+
         let opener = match get_opener_by_sn(db, &msg.serial_number).await {
             Err(e) => {
                 log::error!(
@@ -68,11 +1055,82 @@ impl OpenerServer {
                 }
                 None => {
                     log::error!("Opener {} not found", msg.serial_number,);
-                    return Err("Opener not found".to_string());
+                    return OpenerServer::reject_connect(db, msg, "Opener not found").await;
+                }
+            },
+        };
+
+        if msg.nonce.len() < MIN_NONCE_LENGTH {
+            return OpenerServer::reject_connect(db, msg, "Nonce is too short").await;
+        }
+
+        match opener.secret.as_ref() {
+            Some(secret) => {
+                let proof = msg.proof.as_deref().unwrap_or_default();
+
+                if !verify_hello_proof(secret, &msg.serial_number, &msg.nonce, proof) {
+                    return OpenerServer::reject_connect(db, msg, "Invalid proof").await;
+                }
+            }
+            None => match *LEGACY_AUTH_UNTIL {
+                Some(until) if Local::now() < until => {
+                    log::warn!(
+                        "Opener {} has no secret, it is accepted without proof until {}",
+                        msg.serial_number,
+                        until
+                    );
                 }
+                _ => return OpenerServer::reject_connect(db, msg, "Secret is not issued").await,
             },
+        }
+
+        // Version is checked before nonce is used, so controller can retry with it
+        let device_version = msg.protocol_version.unwrap_or(LEGACY_PROTOCOL_VERSION);
+
+        let protocol_version = match negotiate_version(device_version) {
+            Some(v) => v,
+            None => {
+                log::error!(
+                    "Opener {} speaks unsupported protocol version {}",
+                    msg.serial_number,
+                    device_version
+                );
+                return OpenerServer::reject_connect(db, msg, "Protocol version is not supported")
+                    .await;
+            }
         };
 
+        match register_nonce(db, &msg.serial_number, &msg.nonce).await {
+            Err(e) => {
+                log::error!("Failed to register nonce of {}: {}", msg.serial_number, e);
+                return Err(e.to_string());
+            }
+            Ok(false) => {
+                return OpenerServer::reject_connect(db, msg, "Nonce is already used").await;
+            }
+            Ok(true) => {}
+        }
+
+        Ok(AcceptedConnect {
+            opener,
+            model_id: model.and_then(|m| m.id.map(|i| i.to_string())),
+            protocol_version,
+        })
+    }
+
+    /// Marks opener connected to this instance and notifies subscribers
+    async fn register_connect(
+        db: &Database,
+        cluster: &Cluster,
+        msg: &message::Connect,
+        accepted: AcceptedConnect,
+    ) -> Result<u32, String> {
+        let AcceptedConnect {
+            opener,
+            model_id,
+            protocol_version,
+        } = accepted;
+
         let new_opener_entity = UpdateOpenerEntity {
             user_id: None,
             alias: None,
@@ -82,9 +1140,12 @@ impl OpenerServer {
             login: None,
             password: None,
             connected: Some(true),
+            session_owner: Some(cluster.instance_id().to_string()),
             nonce: Some(msg.nonce.clone()),
             version: Some(msg.version.clone()),
-            barrier_model_id: model.and_then(|m| m.id.map(|i| i.to_string())),
+            protocol_version: Some(protocol_version),
+            capabilities: Some(capabilities(msg.capabilities.clone())),
+            barrier_model_id: model_id,
         };
 
         match update_opener(db, &msg.serial_number, &new_opener_entity).await {
@@ -101,134 +1162,445 @@ impl OpenerServer {
             }
         };
 
+        // Commands queued on other instances are routed here from now on
+        if let Err(e) = cluster.register_session(&msg.serial_number).await {
+            log::error!(
+                "Failed to register session of opener {}: {}",
+                msg.serial_number,
+                e
+            );
+        }
+
         log::info!("Publish connected");
 
-        SimpleBroker::publish(OpenerConnectionChanged {
-            serial_number: msg.serial_number.clone(),
-            connected: true,
-            user_id: opener.user_id.map(|user_id| user_id.to_string()),
-        });
+        cluster
+            .publish(OpenerConnectionChanged {
+                serial_number: msg.serial_number.clone(),
+                connected: true,
+                user_id: opener.user_id.map(|user_id| user_id.to_string()),
+            })
+            .await;
+
+        Ok(protocol_version)
+    }
+
+    async fn handle_disconnect(
+        db: &Database,
+        cluster: &Cluster,
+        msg: &message::Disconnect,
+    ) -> Result<(), String> {
+        if let Err(e) = cluster.unregister_session(&msg.id).await {
+            log::error!("Failed to unregister session of opener {}: {}", msg.id, e);
+        }
+
+        let opener = match disconnect_opener(db, &msg.id, cluster.instance_id()).await {
+            Err(e) => {
+                log::error!("Failed to update opener {}: {}", msg.id, e.to_string());
+                return Err(e.to_string());
+            }
+            Ok(Some(opener)) => {
+                log::info!("Opener {} updated", msg.id);
+                opener
+            }
+            Ok(None) => {
+                // Controller is already connected to another instance or removed
+                log::info!("Opener {} is not connected to this instance", msg.id);
+                return Ok(());
+            }
+        };
+
+        if let Err(e) = OpenerServer::fail_command(
+            db,
+            cluster,
+            &msg.id,
+            None,
+            ErrorCode::Disconnected.into(),
+            "Controller disconnected",
+        )
+        .await
+        {
+            log::error!("Failed to fail command of opener {}: {}", msg.id, e);
+        }
+
+        cluster
+            .publish(OpenerConnectionChanged {
+                serial_number: msg.id.clone(),
+                connected: false,
+                user_id: opener.user_id.map(|user_id| user_id.to_string()),
+            })
+            .await;
 
         Ok(())
     }
 
-    async fn handle_disconnect(db: &Database, msg: &message::Disconnect) -> Result<(), String> {
-        log::info!("Search opener {}", msg.id);
+    async fn handle_set_message(
+        db: &Database,
+        cluster: &Cluster,
+        serial_number: &str,
+        id: &str,
+        arguments: &command::SetCommandArgs,
+    ) -> Result<(), String> {
+        log::info!("Process set from opener {}", serial_number);
 
-        let opener = match get_opener_by_sn(db, &msg.id).await {
+        let opener = match get_opener_by_sn(db, &serial_number.to_string()).await {
             Err(e) => {
-                log::error!("Failed to found opener {}: {}", msg.id, e.to_string());
+                log::error!(
+                    "Failed to found opener {}: {}",
+                    serial_number,
+                    e.to_string()
+                );
                 return Err(e.to_string());
             }
             Ok(opener) => match opener {
                 Some(opener) => {
-                    log::info!("Opener {} founded", msg.id);
+                    log::info!("Opener {} founded", serial_number);
                     opener
                 }
                 None => {
-                    log::error!("Opener {} not found", msg.id,);
+                    log::error!("Opener {} not found", serial_number,);
                     return Err("Opener not found".to_string());
                 }
             },
         };
 
-        let new_opener_entity = UpdateOpenerEntity {
-            user_id: None,
-            alias: None,
-            description: None,
-            lat: None,
-            lng: None,
-            login: None,
-            password: None,
-            connected: Some(false),
-            nonce: None,
-            version: None,
-            barrier_model_id: None,
+        match set_command_to_opener_with_model(
+            db,
+            serial_number,
+            "SUCCESS",
+            &arguments.barrier_model,
+        )
+        .await
+        {
+            Err(e) => {
+                log::error!(
+                    "Failed to update opener {}: {}",
+                    serial_number,
+                    e.to_string()
+                );
+                return Err(e.to_string());
+            }
+            Ok(_) => {
+                log::info!("Opener {} updated", serial_number);
+            }
         };
 
-        match update_opener(db, &msg.id, &new_opener_entity).await {
+        if let Err(e) = complete_command(db, id, "SUCCESS", None).await {
+            log::error!("Failed to update command {}: {}", id, e);
+        }
+
+        log::info!("Publish set command result");
+
+        cluster
+            .publish(OpenerCommandResult {
+                serial_number: serial_number.to_string(),
+                command_type: CommandType::Set,
+                command_status: CommandStatus::Success,
+                error: None,
+                user_id: opener.user_id.map(|user_id| user_id.to_string()),
+            })
+            .await;
+
+        Ok(())
+    }
+
+    async fn handle_info_message(
+        db: &Database,
+        cluster: &Cluster,
+        msg: &message::Info,
+        id: &str,
+    ) -> Result<(), String> {
+        log::info!("Process info from opener {}", msg.serial_number);
+
+        let opener = match get_opener_by_sn(db, &msg.serial_number).await {
             Err(e) => {
-                log::error!("Failed to update opener {}: {}", msg.id, e.to_string());
+                log::error!(
+                    "Failed to found opener {}: {}",
+                    msg.serial_number,
+                    e.to_string()
+                );
+                return Err(e.to_string());
+            }
+            Ok(opener) => match opener {
+                Some(opener) => {
+                    log::info!("Opener {} founded", msg.serial_number);
+                    opener
+                }
+                None => {
+                    log::error!("Opener {} not found", msg.serial_number,);
+                    return Err("Opener not found".to_string());
+                }
+            },
+        };
+
+        let model = if !msg.barrier_model.is_empty() {
+            match get_barrier_model_by_id(db, &msg.barrier_model).await {
+                Err(e) => {
+                    log::error!("Failed to find barrier model: {}", e);
+                    None
+                }
+                Ok(m) => m,
+            }
+        } else {
+            None
+        };
+
+        match set_info_to_opener(
+            db,
+            &msg.serial_number,
+            "SUCCESS",
+            model.and_then(|m| m.id),
+            OpenerInfoEntity {
+                version: msg.version.clone(),
+                uptime: msg.uptime,
+                barrier_model: msg.barrier_model.clone(),
+                barrier_algorithm: msg.barrier_algorithm.clone(),
+                received_at: bson::DateTime::now(),
+            },
+        )
+        .await
+        {
+            Err(e) => {
+                log::error!(
+                    "Failed to update opener {}: {}",
+                    msg.serial_number,
+                    e.to_string()
+                );
+                return Err(e.to_string());
+            }
+            Ok(_) => {
+                log::info!("Opener {} updated", msg.serial_number);
+            }
+        };
+
+        if let Err(e) = complete_command(db, id, "SUCCESS", None).await {
+            log::error!("Failed to update command {}: {}", id, e);
+        }
+
+        log::info!("Publish info command result");
+
+        cluster
+            .publish(OpenerCommandResult {
+                serial_number: msg.serial_number.clone(),
+                command_type: CommandType::Info,
+                command_status: CommandStatus::Success,
+                error: None,
+                user_id: opener.user_id.map(|user_id| user_id.to_string()),
+            })
+            .await;
+
+        Ok(())
+    }
+
+    async fn handle_ack_message(
+        db: &Database,
+        cluster: &Cluster,
+        msg: &message::Ack,
+        id: &str,
+    ) -> Result<(), String> {
+        log::info!("Process {} from opener {}", msg.command, msg.serial_number);
+
+        let opener = match get_opener_by_sn(db, &msg.serial_number).await {
+            Err(e) => {
+                log::error!(
+                    "Failed to found opener {}: {}",
+                    msg.serial_number,
+                    e.to_string()
+                );
+                return Err(e.to_string());
+            }
+            Ok(opener) => match opener {
+                Some(opener) => {
+                    log::info!("Opener {} founded", msg.serial_number);
+                    opener
+                }
+                None => {
+                    log::error!("Opener {} not found", msg.serial_number,);
+                    return Err("Opener not found".to_string());
+                }
+            },
+        };
+
+        match set_command_to_opener(db, &msg.serial_number, "SUCCESS", &msg.command).await {
+            Err(e) => {
+                log::error!(
+                    "Failed to update opener {}: {}",
+                    msg.serial_number,
+                    e.to_string()
+                );
+                return Err(e.to_string());
+            }
+            Ok(_) => {
+                log::info!("Opener {} updated", msg.serial_number);
+            }
+        };
+
+        if let Err(e) = complete_command(db, id, "SUCCESS", None).await {
+            log::error!("Failed to update command {}: {}", id, e);
+        }
+
+        log::info!("Publish {} command result", msg.command);
+
+        let command_type: CommandType = msg.command.as_str().try_into()?;
+
+        cluster
+            .publish(OpenerCommandResult {
+                serial_number: msg.serial_number.clone(),
+                command_type,
+                command_status: CommandStatus::Success,
+                error: None,
+                user_id: opener.user_id.map(|user_id| user_id.to_string()),
+            })
+            .await;
+
+        Ok(())
+    }
+
+    async fn handle_progress_message(
+        db: &Database,
+        cluster: &Cluster,
+        msg: &message::Progress,
+    ) -> Result<(), String> {
+        log::info!("Process progress from opener {}", msg.serial_number);
+
+        let stage: UpdateStage = msg.stage.as_str().try_into()?;
+
+        let opener = match set_update_progress_to_opener(
+            db,
+            &msg.serial_number,
+            UpdateProgressEntity {
+                stage: msg.stage.clone(),
+                percent: msg.percent,
+                changed_at: bson::DateTime::now(),
+            },
+        )
+        .await
+        {
+            Err(e) => {
+                log::error!(
+                    "Failed to update opener {}: {}",
+                    msg.serial_number,
+                    e.to_string()
+                );
                 return Err(e.to_string());
             }
-            Ok(_) => {
-                log::info!("Opener {} updated", msg.id);
+            Ok(opener) => {
+                log::info!("Opener {} updated", msg.serial_number);
+                opener
             }
         };
 
-        SimpleBroker::publish(OpenerConnectionChanged {
-            serial_number: msg.id.clone(),
-            connected: false,
-            user_id: opener.user_id.map(|user_id| user_id.to_string()),
-        });
+        log::info!("Publish update progress");
+
+        cluster
+            .publish(OpenerUpdateProgress {
+                serial_number: msg.serial_number.clone(),
+                stage,
+                percent: msg.percent,
+                user_id: opener.user_id.map(|user_id| user_id.to_string()),
+            })
+            .await;
 
         Ok(())
     }
 
-    async fn handle_set_message(
+    /// Saves event of opener and publishes it to subscribers
+    async fn record_event(
         db: &Database,
-        command: &command::SetCommand,
+        cluster: &Cluster,
+        serial_number: &str,
+        event_type: &str,
+        description: &str,
+        data: bson::Document,
     ) -> Result<(), String> {
-        log::info!("Process set from opener {}", command.serial_number);
+        let event = create_event(db, serial_number, event_type, description, data)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let opener = get_opener_by_sn(db, &serial_number.to_string())
+            .await
+            .map_err(|e| e.to_string())?;
+
+        cluster
+            .publish(OpenerEvent {
+                serial_number: serial_number.to_string(),
+                event: Event::try_from(&event)?,
+                user_id: opener.and_then(|o| o.user_id).map(|id| id.to_string()),
+            })
+            .await;
 
-        let opener = match get_opener_by_sn(db, &command.serial_number).await {
-            Err(e) => {
-                log::error!(
-                    "Failed to found opener {}: {}",
-                    command.serial_number,
-                    e.to_string()
-                );
-                return Err(e.to_string());
+        Ok(())
+    }
+
+    async fn handle_event_message(
+        db: &Database,
+        cluster: &Cluster,
+        msg: message::Event,
+    ) -> Result<(), String> {
+        log::info!(
+            "Process event {} from opener {}",
+            msg.event_type,
+            msg.serial_number
+        );
+
+        if !EventData::TYPES.contains(&msg.event_type.as_str()) {
+            return Err(format!("Unknown event type {}", msg.event_type));
+        }
+
+        let mut data = match msg.data {
+            None => bson::Document::new(),
+            Some(serde_json::Value::Object(data)) => {
+                bson::to_document(&data).map_err(|e| e.to_string())?
             }
-            Ok(opener) => match opener {
-                Some(opener) => {
-                    log::info!("Opener {} founded", command.serial_number);
-                    opener
-                }
-                None => {
-                    log::error!("Opener {} not found", command.serial_number,);
-                    return Err("Opener not found".to_string());
-                }
-            },
+            Some(value) => doc! { "value": bson::to_bson(&value).map_err(|e| e.to_string())? },
         };
 
-        match set_command_to_opener_with_model(
+        if let Some(time) = msg.time {
+            data.insert("deviceTime", time);
+        }
+
+        let description = match msg.description {
+            Some(description) => description,
+            None => event_description(&msg.event_type).to_string(),
+        };
+
+        OpenerServer::record_event(
             db,
-            &command.serial_number,
-            "SUCCESS",
-            &command.arguments.barrier_model,
+            cluster,
+            &msg.serial_number,
+            &msg.event_type,
+            &description,
+            data,
         )
         .await
-        {
-            Err(e) => {
-                log::error!(
-                    "Failed to update opener {}: {}",
-                    command.serial_number,
-                    e.to_string()
-                );
-                return Err(e.to_string());
-            }
-            Ok(_) => {
-                log::info!("Opener {} updated", command.serial_number);
-            }
-        };
+    }
 
-        log::info!("Publish set command result");
+    async fn handle_telemetry_message(
+        db: &Database,
+        msg: message::Telemetry,
+    ) -> Result<(), String> {
+        let telemetry = TelemetryEntity {
+            serial_number: msg.serial_number,
+            signal_strength: msg.signal_strength,
+            temperature: msg.temperature,
+            voltage: msg.voltage,
+            uptime: msg.uptime,
+            created_at: bson::DateTime::from(Local::now()),
+        };
 
-        SimpleBroker::publish(OpenerCommandResult {
-            serial_number: command.serial_number.clone(),
-            command_type: CommandType::Set,
-            command_status: CommandStatus::Success,
-            error: None,
-            user_id: opener.user_id.map(|user_id| user_id.to_string()),
-        });
+        create_telemetry(db, &telemetry)
+            .await
+            .map_err(|e| e.to_string())?;
 
-        Ok(())
+        set_telemetry_to_opener(db, &telemetry.serial_number, &telemetry)
+            .await
+            .map_err(|e| e.to_string())
     }
 
     async fn handle_error_message(
         db: &Database,
+        cluster: &Cluster,
         msg: &message::Error,
+        id: &str,
         command_type: String,
     ) -> Result<(), String> {
         log::info!("Process error from opener {}", msg.serial_number);
@@ -254,19 +1626,22 @@ impl OpenerServer {
             },
         };
 
-        match set_error_to_opener(
-            db,
-            &msg.serial_number,
-            "FAILED",
-            OpenerErrorEntity {
-                serial_number: msg.serial_number.clone(),
-                code: msg.code,
-                description: msg.description.clone(),
-                details: msg.details.clone(),
-            },
-        )
-        .await
-        {
+        let error = OpenerErrorEntity {
+            serial_number: msg.serial_number.clone(),
+            code: msg.code,
+            description: msg.description.clone(),
+            details: msg.details.clone(),
+            tags: msg.tags.as_ref().map(|tags| {
+                tags.iter()
+                    .map(|t| TagResultEntity {
+                        tag: t.tag.clone(),
+                        code: t.code,
+                    })
+                    .collect()
+            }),
+        };
+
+        match set_error_to_opener(db, &msg.serial_number, "FAILED", error.clone()).await {
             Err(e) => {
                 log::error!(
                     "Failed to update opener {}: {}",
@@ -280,22 +1655,36 @@ impl OpenerServer {
             }
         };
 
+        if let Err(e) = complete_command(db, id, "FAILED", Some(error)).await {
+            log::error!("Failed to update command {}: {}", id, e);
+        }
+
         log::info!("Publish error result");
 
         let command_type: CommandType = command_type.as_str().try_into()?;
 
-        SimpleBroker::publish(OpenerCommandResult {
-            serial_number: msg.serial_number.clone(),
-            command_type,
-            command_status: CommandStatus::Failed,
-            error: Some(OpenerError {
+        cluster
+            .publish(OpenerCommandResult {
                 serial_number: msg.serial_number.clone(),
-                code: msg.code,
-                description: msg.description.clone(),
-                details: msg.details.clone(),
-            }),
-            user_id: opener.user_id.map(|user_id| user_id.to_string()),
-        });
+                command_type,
+                command_status: CommandStatus::Failed,
+                error: Some(OpenerError {
+                    serial_number: msg.serial_number.clone(),
+                    code: msg.code,
+                    description: msg.description.clone(),
+                    details: msg.details.clone(),
+                    tags: msg.tags.as_ref().map(|tags| {
+                        tags.iter()
+                            .map(|t| TagResult {
+                                tag: t.tag.clone(),
+                                code: t.code,
+                            })
+                            .collect()
+                    }),
+                }),
+                user_id: opener.user_id.map(|user_id| user_id.to_string()),
+            })
+            .await;
 
         Ok(())
     }
@@ -303,10 +1692,35 @@ impl OpenerServer {
 
 impl Actor for OpenerServer {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.release_openers(ctx);
+        self.watchdog(ctx);
+        self.session_heartbeat(ctx);
+
+        ctx.add_stream(self.cluster.subscribe());
+    }
+}
+
+impl StreamHandler<ClusterMessage> for OpenerServer {
+    fn handle(&mut self, msg: ClusterMessage, ctx: &mut Context<Self>) {
+        match msg {
+            ClusterMessage::Dispatch { serial_number } => {
+                log::info!("Command of opener {} is routed by cluster", serial_number);
+                self.dispatch(serial_number, ctx);
+            }
+            ClusterMessage::Event(event) => self.cluster.publish_local(event),
+        }
+    }
+
+    /// Server keeps running if bus is closed, it only loses other instances
+    fn finished(&mut self, _: &mut Context<Self>) {
+        log::error!("Cluster bus subscription is closed");
+    }
 }
 
 impl Handler<message::Connect> for OpenerServer {
-    type Result = ResponseActFuture<Self, Result<String, String>>;
+    type Result = ResponseActFuture<Self, Result<u32, String>>;
 
     fn handle(&mut self, msg: message::Connect, _: &mut Context<Self>) -> Self::Result {
         log::info!("Opener {} connected", msg.serial_number);
@@ -314,149 +1728,569 @@ impl Handler<message::Connect> for OpenerServer {
         let db: Database = self.db.clone();
         let m = msg.clone();
 
-        let fut = async move { OpenerServer::handle_connect(&db, &m).await };
+        let fut = async move { OpenerServer::authenticate_connect(&db, &m).await };
 
         let wrapped_future = actix::fut::wrap_future::<_, Self>(fut);
 
-        let res = wrapped_future.map(|result, actor, _ctx| match result {
-            Ok(_) => {
-                actor.sessions.insert(msg.serial_number.clone(), msg.addr);
-                actor.count.fetch_add(1, Ordering::SeqCst);
-                Ok(msg.serial_number)
+        let res = wrapped_future.then(move |result, actor, ctx| {
+            let accepted = match result {
+                Ok(accepted) => accepted,
+                Err(e) => return actix::fut::ready(Err(e)).boxed_local(),
+            };
+
+            let serial_number = msg.serial_number.clone();
+            let policy = *DUPLICATE_CONNECTION_POLICY;
+
+            // Refused connection must not touch opener, the first session keeps working
+            let duplicate = actor.sessions.contains_key(&serial_number);
+
+            if duplicate {
+                actor.report_clone(&serial_number, policy, ctx);
+
+                if policy == DuplicateConnectionPolicy::Refuse {
+                    return actix::fut::ready(Err("Opener is already connected".to_string()))
+                        .boxed_local();
+                }
             }
-            Err(e) => Err(e),
+
+            let db: Database = actor.db.clone();
+            let cluster = actor.cluster.clone();
+            let m = msg.clone();
+
+            let fut =
+                async move { OpenerServer::register_connect(&db, &cluster, &m, accepted).await };
+
+            actix::fut::wrap_future::<_, Self>(fut)
+                .map(move |result, actor, ctx| {
+                    let protocol_version = result?;
+
+                    if !actor.sessions.contains_key(&serial_number) {
+                        actor
+                            .sessions
+                            .insert(serial_number.clone(), Session::new(msg));
+                        actor.count.fetch_add(1, Ordering::SeqCst);
+
+                        // Send commands queued while opener was disconnected
+                        actor.dispatch(serial_number, ctx);
+
+                        return Ok(protocol_version);
+                    }
+
+                    // Another connection could complete HELLO while opener was registered
+                    if !duplicate {
+                        actor.report_clone(&serial_number, policy, ctx);
+
+                        if policy == DuplicateConnectionPolicy::Refuse {
+                            return Err("Opener is already connected".to_string());
+                        }
+                    }
+
+                    // Count is not changed as one session replaces another
+                    let old = actor
+                        .sessions
+                        .insert(serial_number.clone(), Session::new(msg))
+                        .unwrap();
+
+                    old.close.do_send(message::Close {
+                        reason: "Replaced by new connection".to_string(),
+                    });
+
+                    actor.replace_session(serial_number, ctx);
+
+                    Ok(protocol_version)
+                })
+                .boxed_local()
         });
 
         Box::pin(res)
     }
 }
 
+impl Handler<Shutdown> for OpenerServer {
+    type Result = ResponseFuture<()>;
+
+    fn handle(&mut self, _: Shutdown, _: &mut Context<Self>) -> Self::Result {
+        log::info!("Release {} sessions on shutdown", self.sessions.len());
+
+        let mut serial_numbers = Vec::new();
+
+        for (serial_number, session) in self.sessions.drain() {
+            session.close.do_send(message::Close {
+                reason: "Service is shutting down".to_string(),
+            });
+
+            serial_numbers.push(serial_number);
+        }
+
+        self.commands.clear();
+        self.count.store(0, Ordering::SeqCst);
+
+        let db: Database = self.db.clone();
+        let cluster = self.cluster.clone();
+
+        Box::pin(async move {
+            for serial_number in serial_numbers {
+                if let Err(e) = cluster.unregister_session(&serial_number).await {
+                    log::error!(
+                        "Failed to unregister session of opener {}: {}",
+                        serial_number,
+                        e
+                    );
+                }
+            }
+
+            match release_openers(&db, cluster.instance_id()).await {
+                Err(e) => log::error!("Failed to release openers on shutdown: {}", e),
+                Ok(openers) => {
+                    OpenerServer::publish_released(
+                        &db,
+                        &cluster,
+                        openers,
+                        "Service is shutting down",
+                    )
+                    .await
+                }
+            }
+        })
+    }
+}
+
+impl Handler<message::Violation> for OpenerServer {
+    type Result = ResponseFuture<Result<(), String>>;
+
+    fn handle(&mut self, msg: message::Violation, _: &mut Context<Self>) -> Self::Result {
+        log::error!(
+            "Opener {} violated protocol: {}",
+            msg.serial_number,
+            msg.description
+        );
+
+        let db: Database = self.db.clone();
+        let cluster = self.cluster.clone();
+
+        let fut = async move {
+            let data = doc! {
+                "messageType": msg.message_type,
+                "claimedSerialNumber": msg.claimed_serial_number,
+            };
+
+            OpenerServer::record_event(
+                &db,
+                &cluster,
+                &msg.serial_number,
+                PROTOCOL_VIOLATION_EVENT,
+                &msg.description,
+                data,
+            )
+            .await
+        };
+
+        Box::pin(fut)
+    }
+}
+
 impl Handler<message::Disconnect> for OpenerServer {
     type Result = ResponseFuture<Result<(), String>>;
 
     fn handle(&mut self, msg: message::Disconnect, _: &mut Context<Self>) -> Self::Result {
-        if self.sessions.get(&msg.id).is_none() {
-            return Box::pin(async { Ok(()) });
-        } else {
-            self.sessions.remove(&msg.id);
-            self.count.fetch_sub(1, Ordering::SeqCst);
+        // Session could be already replaced by new connection of the same opener
+        match self.sessions.get(&msg.id) {
+            Some(session) if session.connection_id == msg.connection_id => {
+                self.sessions.remove(&msg.id);
+            }
+            _ => return Box::pin(async { Ok(()) }),
         }
 
+        self.count.fetch_sub(1, Ordering::SeqCst);
+
+        self.commands
+            .retain(|_, c| c.command.serial_number() != msg.id);
+
         log::info!("Opener {} disconnected", msg.id);
 
         let db: Database = self.db.clone();
+        let cluster = self.cluster.clone();
         let m = msg.clone();
 
-        let fut = async move { OpenerServer::handle_disconnect(&db, &m).await };
+        let fut = async move { OpenerServer::handle_disconnect(&db, &cluster, &m).await };
 
         Box::pin(fut)
     }
 }
 
 impl Handler<message::SetCommand> for OpenerServer {
-    type Result = ResponseFuture<()>;
+    type Result = ();
+
+    fn handle(&mut self, msg: message::SetCommand, ctx: &mut Context<Self>) {
+        log::info!("Queue set params command to opener {}", msg.serial_number);
+
+        let arguments = command::SetCommandArgs {
+            barrier_model: msg.barrier_model,
+            barrier_algorithm: msg.barrier_algorithm,
+        };
+
+        let expires_at = msg.deliver_within.map(|d| {
+            bson::DateTime::from_millis(bson::DateTime::now().timestamp_millis() + d as i64 * 1000)
+        });
+
+        self.enqueue_command(
+            msg.serial_number,
+            command::SET_COMMAND,
+            &arguments,
+            msg.user_id,
+            expires_at,
+            ctx,
+        );
+    }
+}
+
+impl Handler<message::InfoCommand> for OpenerServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: message::InfoCommand, ctx: &mut Context<Self>) {
+        log::info!("Queue info command to opener {}", msg.serial_number);
+
+        let arguments = command::InfoCommandArgs {};
 
-    fn handle(&mut self, msg: message::SetCommand, _: &mut Context<Self>) -> Self::Result {
-        let addr = self.sessions.get(&msg.serial_number);
-        if addr.is_none() {
-            return Box::pin(async { });
+        self.enqueue_command(
+            msg.serial_number,
+            command::INFO_COMMAND,
+            &arguments,
+            msg.user_id,
+            None,
+            ctx,
+        );
+    }
+}
+
+impl Handler<message::ScheduledCommand> for OpenerServer {
+    type Result = ResponseActFuture<Self, Result<String, String>>;
+
+    fn handle(&mut self, msg: message::ScheduledCommand, _: &mut Context<Self>) -> Self::Result {
+        log::info!(
+            "Queue scheduled {} command to opener {}",
+            msg.command,
+            msg.serial_number
+        );
+
+        let arguments = command::BarrierCommandArgs { door: msg.door };
+
+        self.queue_command(
+            msg.serial_number,
+            &msg.command,
+            &arguments,
+            msg.user_id,
+            msg.expires_at,
+        )
+    }
+}
+
+impl Handler<message::BarrierCommand> for OpenerServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: message::BarrierCommand, ctx: &mut Context<Self>) {
+        log::info!(
+            "Queue {} command to opener {}",
+            msg.command,
+            msg.serial_number
+        );
+
+        if !matches!(
+            msg.command.as_str(),
+            command::OPEN_COMMAND | command::CLOSE_COMMAND
+        ) {
+            log::error!("Unsupported barrier command: {}", msg.command);
+            return;
         }
 
-        let addr = addr.unwrap().clone();
+        let arguments = command::BarrierCommandArgs { door: msg.door };
 
-        log::info!("Send set params command to opener {}", msg.serial_number);
+        self.enqueue_command(
+            msg.serial_number,
+            &msg.command,
+            &arguments,
+            msg.user_id,
+            None,
+            ctx,
+        );
+    }
+}
 
-        let credentials = format!("{}{}{}", msg.login, msg.password, msg.nonce);
+impl Handler<message::TagsCommand> for OpenerServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: message::TagsCommand, ctx: &mut Context<Self>) {
+        log::info!(
+            "Queue {} command to opener {}",
+            msg.command,
+            msg.serial_number
+        );
+
+        if !matches!(
+            msg.command.as_str(),
+            command::ADD_TAGS_COMMAND | command::REMOVE_TAGS_COMMAND
+        ) {
+            log::error!("Unsupported tags command: {}", msg.command);
+            return;
+        }
 
-        let hash = sha256::digest(credentials);
+        let arguments = command::TagsCommandArgs { tags: msg.tags };
 
-        let command = command::SetCommand {
-            serial_number: msg.serial_number.clone(),
-            command: "SET".to_string(),
-            authorization: hash,
-            arguments: command::SetCommandArgs {
-                barrier_model: msg.barrier_model,
-                barrier_algorithm: msg.barrier_algorithm,
-            },
-        };
+        self.enqueue_command(
+            msg.serial_number,
+            &msg.command,
+            &arguments,
+            msg.user_id,
+            None,
+            ctx,
+        );
+    }
+}
 
-        self.commands
-            .insert(msg.serial_number, command::Command::Set(command.clone()));
+impl Handler<message::UpdateCommand> for OpenerServer {
+    type Result = ();
 
-        let fut = async move {
-            if let Err(e) = addr.send(command).await {
-                log::error!("Failed to send set command to controller: {}", e);
-            }
+    fn handle(&mut self, msg: message::UpdateCommand, ctx: &mut Context<Self>) {
+        log::info!("Queue update command to opener {}", msg.serial_number);
+
+        let arguments = command::UpdateCommandArgs {
+            url: msg.url,
+            size: msg.size,
+            checksum: msg.checksum,
+            version: msg.version,
         };
 
-        Box::pin(fut)
+        self.enqueue_command(
+            msg.serial_number,
+            command::UPDATE_COMMAND,
+            &arguments,
+            msg.user_id,
+            None,
+            ctx,
+        );
     }
 }
 
 impl Handler<message::Set> for OpenerServer {
-    type Result = ResponseFuture<Result<(), String>>;
+    type Result = ResponseActFuture<Self, Result<(), String>>;
 
     fn handle(&mut self, msg: message::Set, _: &mut Context<Self>) -> Self::Result {
         log::info!("Opener {} sent set message", msg.serial_number);
 
         let db: Database = self.db.clone();
+        let cluster = self.cluster.clone();
 
-        let command = self.commands.get(&msg.serial_number);
-
-        if command.is_none() {
-            log::error!("Set command for {} not found", msg.serial_number);
-            return Box::pin(async { Ok(()) });
-        }
-
-        let command = command.unwrap();
-
-        let command = match command {
-            command::Command::Set(c) => c,
-            _ => {
+        let (id, arguments) = match self
+            .find_command(&msg.serial_number, msg.id.as_deref())
+            .map(|c| &c.command)
+        {
+            Some(
+                c @ command::Command(acs_protocol::Command {
+                    body: CommandBody::Set(arguments),
+                    ..
+                }),
+            ) => (c.id().to_string(), arguments.clone()),
+            Some(_) => {
                 log::error!(
                     "Wrong command instead of set command for {} found",
                     msg.serial_number
                 );
-                return Box::pin(async { Ok(()) });
+                return Box::pin(actix::fut::ready(Ok(())));
+            }
+            None => {
+                log::error!("Set command for {} not found", msg.serial_number);
+                return Box::pin(actix::fut::ready(Ok(())));
             }
         };
 
-        let command = command.clone();
+        let sn = msg.serial_number.clone();
+        let command_id = id.clone();
 
-        let fut = async move { OpenerServer::handle_set_message(&db, &command).await };
+        let fut = async move {
+            OpenerServer::handle_set_message(&db, &cluster, &sn, &command_id, &arguments).await
+        };
 
-        Box::pin(fut)
+        OpenerServer::finish_command(id, msg.serial_number, fut)
     }
 }
 
 impl Handler<message::Error> for OpenerServer {
-    type Result = ResponseFuture<Result<(), String>>;
+    type Result = ResponseActFuture<Self, Result<(), String>>;
 
     fn handle(&mut self, msg: message::Error, _: &mut Context<Self>) -> Self::Result {
         log::info!("Opener {} sent error message", msg.serial_number);
 
         let db: Database = self.db.clone();
+        let cluster = self.cluster.clone();
         let m = msg.clone();
 
-        let command = self.commands.get(&msg.serial_number);
+        let (id, command) = match self.find_command(&msg.serial_number, msg.id.as_deref()) {
+            Some(c) => (c.command.id().to_string(), c.command.name().to_string()),
+            None => {
+                log::error!("Command for {} not found", msg.serial_number);
+                return Box::pin(actix::fut::ready(Ok(())));
+            }
+        };
 
-        if command.is_none() {
-            log::error!("Set command for {} not found", msg.serial_number);
-            return Box::pin(async { Ok(()) });
-        }
+        let fut = {
+            let id = id.clone();
+            async move { OpenerServer::handle_error_message(&db, &cluster, &m, &id, command).await }
+        };
+
+        OpenerServer::finish_command(id, msg.serial_number, fut)
+    }
+}
+
+impl Handler<message::Info> for OpenerServer {
+    type Result = ResponseActFuture<Self, Result<(), String>>;
+
+    fn handle(&mut self, msg: message::Info, _: &mut Context<Self>) -> Self::Result {
+        log::info!("Opener {} sent info message", msg.serial_number);
+
+        let db: Database = self.db.clone();
+        let cluster = self.cluster.clone();
+
+        let id = match self
+            .find_command(&msg.serial_number, msg.id.as_deref())
+            .map(|c| &c.command)
+        {
+            Some(c) if matches!(c.body(), CommandBody::Info(_)) => c.id().to_string(),
+            Some(_) => {
+                log::error!(
+                    "Wrong command instead of info command for {} found",
+                    msg.serial_number
+                );
+                return Box::pin(actix::fut::ready(Ok(())));
+            }
+            None => {
+                log::error!("Info command for {} not found", msg.serial_number);
+                return Box::pin(actix::fut::ready(Ok(())));
+            }
+        };
+
+        let serial_number = msg.serial_number.clone();
+
+        let fut = {
+            let id = id.clone();
+            async move { OpenerServer::handle_info_message(&db, &cluster, &msg, &id).await }
+        };
+
+        OpenerServer::finish_command(id, serial_number, fut)
+    }
+}
+
+impl Handler<message::Ack> for OpenerServer {
+    type Result = ResponseActFuture<Self, Result<(), String>>;
+
+    fn handle(&mut self, msg: message::Ack, _: &mut Context<Self>) -> Self::Result {
+        log::info!("Opener {} sent {} message", msg.serial_number, msg.command);
+
+        let db: Database = self.db.clone();
+        let cluster = self.cluster.clone();
+
+        let id = match self.find_command(&msg.serial_number, msg.id.as_deref()) {
+            Some(c) if c.command.name() == msg.command => c.command.id().to_string(),
+            Some(_) => {
+                log::error!(
+                    "Wrong command instead of {} command for {} found",
+                    msg.command,
+                    msg.serial_number
+                );
+                return Box::pin(actix::fut::ready(Ok(())));
+            }
+            None => {
+                log::error!(
+                    "{} command for {} not found",
+                    msg.command,
+                    msg.serial_number
+                );
+                return Box::pin(actix::fut::ready(Ok(())));
+            }
+        };
+
+        let serial_number = msg.serial_number.clone();
+
+        let fut = {
+            let id = id.clone();
+            async move { OpenerServer::handle_ack_message(&db, &cluster, &msg, &id).await }
+        };
+
+        OpenerServer::finish_command(id, serial_number, fut)
+    }
+}
+
+impl Handler<message::Progress> for OpenerServer {
+    type Result = ResponseFuture<Result<(), String>>;
+
+    fn handle(&mut self, msg: message::Progress, _: &mut Context<Self>) -> Self::Result {
+        log::info!("Opener {} sent progress message", msg.serial_number);
 
-        let command = command.unwrap();
+        let db: Database = self.db.clone();
+        let cluster = self.cluster.clone();
 
-        let command = match command {
-            command::Command::Set(c) => c.clone().command,
+        match self
+            .find_command(&msg.serial_number, msg.id.as_deref())
+            .map(|c| &c.command)
+        {
+            Some(c) if matches!(c.body(), CommandBody::Update(_)) => {}
             _ => {
-                log::error!("Wrong command for {} found", msg.serial_number);
+                log::error!("Update command for {} not found", msg.serial_number);
                 return Box::pin(async { Ok(()) });
             }
-        };
+        }
 
-        let fut = async move { OpenerServer::handle_error_message(&db, &m, command).await };
+        let fut = async move { OpenerServer::handle_progress_message(&db, &cluster, &msg).await };
 
         Box::pin(fut)
     }
 }
+
+impl Handler<message::Event> for OpenerServer {
+    type Result = ResponseFuture<Result<(), String>>;
+
+    fn handle(&mut self, msg: message::Event, _: &mut Context<Self>) -> Self::Result {
+        log::info!("Opener {} sent event message", msg.serial_number);
+
+        let db: Database = self.db.clone();
+        let cluster = self.cluster.clone();
+
+        Box::pin(async move { OpenerServer::handle_event_message(&db, &cluster, msg).await })
+    }
+}
+
+impl Handler<message::Telemetry> for OpenerServer {
+    type Result = ResponseFuture<Result<(), String>>;
+
+    fn handle(&mut self, msg: message::Telemetry, _: &mut Context<Self>) -> Self::Result {
+        log::debug!("Opener {} sent telemetry message", msg.serial_number);
+
+        let db: Database = self.db.clone();
+
+        Box::pin(async move { OpenerServer::handle_telemetry_message(&db, msg).await })
+    }
+}
+
+/// Description of event if controller does not send it
+fn event_description(event_type: &str) -> &'static str {
+    match event_type {
+        "OPENED_BY_REMOTE" => "Barrier is opened by remote",
+        "TAG_SCANNED" => "Tag is scanned",
+        "OBSTACLE_DETECTED" => "Obstacle is detected",
+        "POWER_RESTORED" => "Power is restored",
+        _ => "",
+    }
+}
+
+/// Commands supported by controller, old firmware does not declare them in HELLO
+fn capabilities(declared: Option<Vec<String>>) -> Vec<String> {
+    declared.unwrap_or_else(|| LEGACY_CAPABILITIES.iter().map(|c| c.to_string()).collect())
+}
+
+/// Checks command against capabilities stored on opener,
+/// opener which has not sent HELLO since they are stored supports only legacy commands
+pub(crate) fn supports_command(capabilities: Option<&Vec<String>>, command: &str) -> bool {
+    match capabilities {
+        Some(capabilities) => capabilities.iter().any(|c| c == command),
+        None => LEGACY_CAPABILITIES.contains(&command),
+    }
+}
@@ -1,23 +1,80 @@
 use actix::prelude::*;
-use serde::Serialize;
+use anyhow::Result;
+use bson::{doc, Bson, Document};
 
-#[derive(Serialize, Clone)]
-pub struct SetCommandArgs {
-    pub barrier_model: String,
-    pub barrier_algorithm: String,
+pub use acs_protocol::{
+    BarrierCommandArgs, CommandBody, InfoCommandArgs, SetCommandArgs, TagsCommandArgs,
+    UpdateCommandArgs,
+};
+
+pub const SET_COMMAND: &str = "SET";
+pub const INFO_COMMAND: &str = "INFO";
+pub const OPEN_COMMAND: &str = "OPEN";
+pub const CLOSE_COMMAND: &str = "CLOSE";
+pub const ADD_TAGS_COMMAND: &str = "ADD_TAGS";
+pub const REMOVE_TAGS_COMMAND: &str = "REMOVE_TAGS";
+pub const UPDATE_COMMAND: &str = "UPDATE";
+
+/// Nonces and counter of controller session used to sign commands
+pub struct Challenge {
+    /// Nonce sent by controller in HELLO
+    pub device_nonce: String,
+    /// Nonce issued by service for session
+    pub nonce: String,
+    /// Number of command in session
+    pub counter: u64,
 }
 
-#[derive(Message, Serialize, Clone)]
+/// Command sent to controller session
+#[derive(Message, Clone)]
 #[rtype(result = "()")]
-pub struct SetCommand {
-    pub serial_number: String,
-    pub command: String,
-    pub authorization: String,
-    pub arguments: SetCommandArgs,
-}
+pub struct Command(pub acs_protocol::Command);
+
+impl Command {
+    /// Restores queued command from its name and stored arguments and signs it
+    /// with login and password of opener for session
+    pub fn new(
+        id: String,
+        serial_number: String,
+        name: &str,
+        arguments: Document,
+        challenge: &Challenge,
+        login: &str,
+        password: &str,
+    ) -> Result<Self> {
+        let body: CommandBody = bson::from_bson(Bson::Document(doc! {
+            "command": name,
+            "arguments": arguments,
+        }))?;
+
+        let mut command = acs_protocol::Command {
+            id,
+            serial_number,
+            nonce: challenge.nonce.clone(),
+            counter: challenge.counter,
+            authorization: String::new(),
+            body,
+        };
+
+        command.sign(login, password, &challenge.device_nonce);
+
+        Ok(Command(command))
+    }
+
+    pub fn id(&self) -> &str {
+        &self.0.id
+    }
+
+    pub fn serial_number(&self) -> &str {
+        &self.0.serial_number
+    }
+
+    pub fn body(&self) -> &CommandBody {
+        &self.0.body
+    }
 
-pub enum Command {
-    Set(SetCommand),
-    #[allow(unused)]
-    Other,
+    /// Returns name of command as it is sent to controller
+    pub fn name(&self) -> &str {
+        self.0.body.name()
+    }
 }
@@ -1,44 +1,195 @@
 use crate::server::command;
 use actix::prelude::*;
 
+/// HELLO of controller, returns version of protocol used in session
 #[derive(Message, Clone)]
-#[rtype(result = "Result<String, String>")]
+#[rtype(result = "Result<u32, String>")]
 pub struct Connect {
-    pub addr: Recipient<command::SetCommand>,
+    /// Unique id of websocket connection
+    pub connection_id: u64,
+    pub addr: Recipient<command::Command>,
+    pub close: Recipient<Close>,
     pub serial_number: String,
     pub version: String,
     pub nonce: String,
     pub barrier_model: String,
+    pub proof: Option<String>,
+    pub protocol_version: Option<u32>,
+    pub capabilities: Option<Vec<String>>,
 }
 
 #[derive(Message, Clone)]
 #[rtype(result = "Result<(), String>")]
 pub struct Disconnect {
     pub id: String,
+    pub connection_id: u64,
+}
+
+/// Asks session to close websocket connection
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct Close {
+    pub reason: String,
+}
+
+/// Message from controller breaking protocol, session is closed after it
+#[derive(Message, Clone)]
+#[rtype(result = "Result<(), String>")]
+pub struct Violation {
+    /// Serial number session is authenticated with
+    pub serial_number: String,
+    pub description: String,
+    /// Type of message
+    pub message_type: String,
+    /// Serial number given in message
+    pub claimed_serial_number: Option<String>,
 }
 
 #[derive(Message, Clone)]
 #[rtype(result = "Result<(), String>")]
 pub struct Set {
     pub serial_number: String,
+    /// Id of command, absent if controller firmware does not echo it
+    pub id: Option<String>,
 }
 
 #[derive(Message, Clone)]
 #[rtype(result = "Result<(), String>")]
 pub struct Error {
     pub serial_number: String,
+    /// Id of command, absent if controller firmware does not echo it
+    pub id: Option<String>,
     pub code: u32,
     pub description: String,
     pub details: Option<String>,
+    pub tags: Option<Vec<TagResult>>,
+}
+
+#[derive(Clone)]
+pub struct TagResult {
+    pub tag: String,
+    pub code: Option<u32>,
 }
 
 #[derive(Message, Clone)]
 #[rtype(result = "()")]
 pub struct SetCommand {
-    pub login: String,
-    pub password: String,
-    pub nonce: String,
+    /// Id of user who sent command
+    pub user_id: String,
+    pub serial_number: String,
+    pub barrier_model: String,
+    pub barrier_algorithm: String,
+    /// Seconds to keep command until opener connects
+    pub deliver_within: Option<u32>,
+}
+
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct InfoCommand {
+    /// Id of user who sent command
+    pub user_id: String,
+    pub serial_number: String,
+}
+
+#[derive(Message, Clone)]
+#[rtype(result = "Result<(), String>")]
+pub struct Info {
     pub serial_number: String,
+    /// Id of command, absent if controller firmware does not echo it
+    pub id: Option<String>,
+    pub version: String,
+    pub uptime: i64,
     pub barrier_model: String,
     pub barrier_algorithm: String,
 }
+
+/// Queues OPEN or CLOSE command to controller
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct BarrierCommand {
+    /// Id of user who sent command
+    pub user_id: String,
+    pub serial_number: String,
+    pub command: String,
+    pub door: Option<u32>,
+}
+
+/// Queues OPEN or CLOSE command sent by schedule, returns id of command
+#[derive(Message, Clone)]
+#[rtype(result = "Result<String, String>")]
+pub struct ScheduledCommand {
+    /// Id of user who created schedule
+    pub user_id: String,
+    pub serial_number: String,
+    pub command: String,
+    pub door: Option<u32>,
+    /// Command for disconnected opener is kept until this time
+    pub expires_at: Option<bson::DateTime>,
+}
+
+/// Controller's acknowledgement of command without result data (OPEN, CLOSE, ADD_TAGS, REMOVE_TAGS, UPDATE)
+#[derive(Message, Clone)]
+#[rtype(result = "Result<(), String>")]
+pub struct Ack {
+    pub serial_number: String,
+    /// Id of command, absent if controller firmware does not echo it
+    pub id: Option<String>,
+    pub command: String,
+}
+
+/// Queues ADD_TAGS or REMOVE_TAGS command to controller
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct TagsCommand {
+    /// Id of user who sent command
+    pub user_id: String,
+    pub serial_number: String,
+    pub command: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct UpdateCommand {
+    /// Id of user who sent command
+    pub user_id: String,
+    pub serial_number: String,
+    pub url: String,
+    pub size: u64,
+    pub checksum: String,
+    pub version: String,
+}
+
+/// Progress of firmware update reported by controller
+#[derive(Message, Clone)]
+#[rtype(result = "Result<(), String>")]
+pub struct Progress {
+    pub serial_number: String,
+    /// Id of command, absent if controller firmware does not echo it
+    pub id: Option<String>,
+    pub stage: String,
+    pub percent: u32,
+}
+
+/// Event reported by controller without command
+#[derive(Message, Clone)]
+#[rtype(result = "Result<(), String>")]
+pub struct Event {
+    pub serial_number: String,
+    pub event_type: String,
+    pub description: Option<String>,
+    pub data: Option<serde_json::Value>,
+    /// Unix time of event on controller in seconds
+    pub time: Option<i64>,
+}
+
+/// State of controller sent periodically
+#[derive(Message, Clone)]
+#[rtype(result = "Result<(), String>")]
+pub struct Telemetry {
+    pub serial_number: String,
+    pub signal_strength: Option<i32>,
+    pub temperature: Option<f64>,
+    pub voltage: Option<f64>,
+    pub uptime: i64,
+}
@@ -1,19 +1,64 @@
+use std::env;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use actix::prelude::*;
 use actix_web_actors::ws;
+use lazy_static::lazy_static;
+
+use acs_protocol::{
+    CodecError, DeviceMessage, Encoding, ErrorCode, MessageErrorData, ServiceMessage, WelcomeData,
+};
+use serde::{Deserialize, Serialize};
+
+use super::server::{message, OpenerServer};
+use super::traffic::Traffic;
+
+lazy_static! {
+    /// Interval of ping sent to controller
+    static ref HEARTBEAT_INTERVAL: Duration = Duration::from_secs(
+        env::var("DEVICE_HEARTBEAT_INTERVAL")
+            .ok()
+            .and_then(|t| t.parse().ok())
+            .unwrap_or(5)
+    );
+    /// Session is closed if controller does not respond during this time
+    static ref CLIENT_TIMEOUT: Duration = Duration::from_secs(
+        env::var("DEVICE_CLIENT_TIMEOUT")
+            .ok()
+            .and_then(|t| t.parse().ok())
+            .unwrap_or(10)
+    );
+    /// Interval in seconds of TELEMETRY sent by controllers
+    static ref TELEMETRY_INTERVAL: u64 = env::var("TELEMETRY_INTERVAL")
+        .ok()
+        .and_then(|t| t.parse().ok())
+        .unwrap_or(60);
+}
 
-mod dsl;
-
-use dsl::{ERROR_TYPE, HELLO_TYPE, SET_TYPE};
+/// Session is closed when controller sends more malformed messages
+const MALFORMED_MESSAGES_LIMIT: u32 = 10;
 
-const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
-const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Only type of message is parsed to tell unknown messages from invalid ones
+#[derive(Deserialize)]
+struct Envelope {
+    #[serde(rename = "type")]
+    message_type: String,
+}
 
 pub(crate) struct WsOpenerSession {
     pub id: Option<String>,
+    /// Unique id of connection to distinguish sessions with the same serial number
+    pub connection_id: u64,
     pub hb: Instant,
-    pub addr: Addr<super::server::OpenerServer>,
+    pub addr: Addr<OpenerServer>,
+    /// Encoding of messages chosen by controller when it connects
+    pub encoding: Encoding,
+    pub traffic: Arc<Traffic>,
+    /// Number of malformed messages received in session
+    pub violations: u32,
+    /// Common name of client certificate, HELLO is accepted only for this serial number
+    pub certificate: Option<String>,
 }
 
 impl Actor for WsOpenerSession {
@@ -26,8 +71,10 @@ impl Actor for WsOpenerSession {
 
     fn stopping(&mut self, _: &mut Self::Context) -> Running {
         if let Some(x) = &self.id {
-            self.addr
-                .do_send(super::server::message::Disconnect { id: x.clone() });
+            self.addr.do_send(message::Disconnect {
+                id: x.clone(),
+                connection_id: self.connection_id,
+            });
         }
 
         Running::Stop
@@ -59,123 +106,25 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsOpenerSession {
             ws::Message::Text(text) => {
                 log::info!("Raw msg: {}", text);
 
-                let msg: dsl::Response = match serde_json::from_str(&text) {
-                    Err(e) => {
-                        log::error!("Parse message failed: {}", e);
-                        return;
-                    }
-                    Ok(msg) => msg,
-                };
-
-                match msg.response_type.as_str() {
-                    HELLO_TYPE => {
-                        let hello: dsl::Hello = match serde_json::from_value(msg.data) {
-                            Err(e) => {
-                                log::error!("Wrong hello message format: {}", e);
-                                return;
-                            }
-                            Ok(hello) => hello,
-                        };
-
-                        log::info!("Hello message: {}", serde_json::to_string(&hello).unwrap());
-
-                        let addr = ctx.address();
-
-                        self.addr
-                            .send(super::server::message::Connect {
-                                addr: addr.recipient(),
-                                serial_number: hello.serial_number,
-                                version: hello.version,
-                                nonce: hello.nonce,
-                                barrier_model: hello.barrier_model,
-                            })
-                            .into_actor(self)
-                            .then(|res, act, ctx| {
-                                match res {
-                                    Ok(res) => match res {
-                                        Ok(id) => act.id = Some(id),
-                                        Err(_) => ctx.stop(),
-                                    },
-                                    Err(e) => {
-                                        log::error!("Error on server connect: {}", e);
-                                        ctx.stop()
-                                    }
-                                }
-                                fut::ready(())
-                            })
-                            .wait(ctx);
-                    }
-
-                    SET_TYPE => {
-                        let set: dsl::Set = match serde_json::from_value(msg.data) {
-                            Err(e) => {
-                                log::error!("Wrong set message format: {}", e);
-                                return;
-                            }
-                            Ok(set) => set,
-                        };
-
-                        log::info!("Set message: {}", serde_json::to_string(&set).unwrap());
-
-                        self.addr
-                            .send(super::server::message::Set {
-                                serial_number: set.serial_number,
-                            })
-                            .into_actor(self)
-                            .then(|res, _act, _ctx| {
-                                match res {
-                                    Ok(res) => if let Err(e) = res {
-                                        log::error!("Error on server set handler: {}", e);
-                                    },
-                                    Err(e) => {
-                                        log::info!("Failed to send set message to server: {}", e)
-                                    }
-                                }
-                                fut::ready(())
-                            })
-                            .wait(ctx);
-                    }
-
-                    ERROR_TYPE => {
-                        let error: dsl::Error = match serde_json::from_value(msg.data) {
-                            Err(e) => {
-                                log::error!("Wrong error message format: {}", e);
-                                return;
-                            }
-                            Ok(error) => error,
-                        };
-
-                        log::info!("Error message: {}", serde_json::to_string(&error).unwrap());
-
-                        self.addr
-                            .send(super::server::message::Error {
-                                serial_number: error.serial_number,
-                                code: error.code,
-                                description: error.description,
-                                details: error.details,
-                            })
-                            .into_actor(self)
-                            .then(|res, _act, _ctx| {
-                                match res {
-                                    Ok(res) => if let Err(e) = res {
-                                        log::error!("Error on server error handler: {}", e);
-                                    },
-                                    Err(e) => {
-                                        log::info!("Failed to send error message to server: {}", e)
-                                    }
-                                }
-                                fut::ready(())
-                            })
-                            .wait(ctx);
-                    }
+                self.handle_frame(Encoding::Json, text.as_bytes(), ctx);
+            }
 
-                    t => {
-                        log::error!("Unsupported message type: {}", t);
-                    }
+            ws::Message::Binary(data) => {
+                log::info!("Binary msg: {} bytes", data.len());
+
+                if !self.encoding.is_binary() {
+                    log::error!("Binary message is received in JSON session");
+                    self.reject_message(
+                        None,
+                        ErrorCode::BadRequest,
+                        Some("Binary message is received in JSON session".to_string()),
+                        ctx,
+                    );
+                    return;
                 }
-            }
 
-            ws::Message::Binary(_) => {}
+                self.handle_frame(self.encoding, &data, ctx);
+            }
 
             ws::Message::Close(reason) => {
                 ctx.close(reason);
@@ -192,14 +141,385 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsOpenerSession {
 }
 
 impl WsOpenerSession {
+    fn handle_frame(
+        &mut self,
+        encoding: Encoding,
+        data: &[u8],
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        self.traffic.add_received(encoding, data.len());
+
+        let msg: DeviceMessage = match encoding.decode(data) {
+            Err(e) => {
+                log::error!("Parse message failed: {}", e);
+                self.reject_malformed(encoding, data, e, ctx);
+                return;
+            }
+            Ok(msg) => msg,
+        };
+
+        if !self.check_binding(&msg, ctx) {
+            return;
+        }
+
+        self.process_message(msg, ctx);
+    }
+
+    /// Tells controller why its message can not be parsed
+    fn reject_malformed(
+        &mut self,
+        encoding: Encoding,
+        data: &[u8],
+        error: CodecError,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        let envelope: Envelope = match encoding.decode(data) {
+            Err(_) => {
+                return self.reject_message(
+                    None,
+                    ErrorCode::BadRequest,
+                    Some(error.to_string()),
+                    ctx,
+                )
+            }
+            Ok(envelope) => envelope,
+        };
+
+        let message_type = envelope.message_type;
+
+        if DeviceMessage::TYPES.contains(&message_type.as_str()) {
+            let details = format!("Invalid {} message: {}", message_type, error);
+            self.reject_message(
+                Some(message_type),
+                ErrorCode::BadRequest,
+                Some(details),
+                ctx,
+            );
+        } else {
+            let details = format!("Unsupported message type {}", message_type);
+            self.reject_message(
+                Some(message_type),
+                ErrorCode::NotImplemented,
+                Some(details),
+                ctx,
+            );
+        }
+    }
+
+    /// Replies with ERROR message and closes session
+    /// when controller keeps sending malformed messages
+    fn reject_message(
+        &mut self,
+        message_type: Option<String>,
+        code: ErrorCode,
+        details: Option<String>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        let error = ServiceMessage::Error(MessageErrorData {
+            message_type: message_type.clone(),
+            code: code.code(),
+            description: code.description().to_string(),
+            details: details.clone(),
+        });
+
+        if let Err(e) = self.send(&error, ctx) {
+            log::error!("Failed to serialize error message: {}", e);
+        }
+
+        self.violations += 1;
+
+        if self.violations <= MALFORMED_MESSAGES_LIMIT {
+            return;
+        }
+
+        log::error!("Too many malformed messages, closing session");
+
+        if let Some(id) = &self.id {
+            self.addr.do_send(message::Violation {
+                serial_number: id.clone(),
+                description: "Too many malformed messages".to_string(),
+                message_type: message_type.unwrap_or_default(),
+                claimed_serial_number: None,
+            });
+        }
+
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Policy,
+            description: Some("Too many malformed messages".to_string()),
+        }));
+
+        ctx.stop();
+    }
+
+    /// Sends message in encoding of session
+    fn send<T: Serialize>(
+        &self,
+        msg: &T,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) -> Result<(), CodecError> {
+        let data = self.encoding.encode(msg)?;
+
+        self.traffic.add_sent(self.encoding, data.len());
+
+        if self.encoding.is_binary() {
+            ctx.binary(data);
+        } else {
+            // JSON encoder always produces valid UTF-8
+            ctx.text(String::from_utf8(data).unwrap_or_default());
+        }
+
+        Ok(())
+    }
+
+    /// Checks that HELLO is the first message and all other messages are sent
+    /// for serial number session is authenticated with, otherwise replies with ERROR
+    /// and closes session
+    fn check_binding(&mut self, msg: &DeviceMessage, ctx: &mut ws::WebsocketContext<Self>) -> bool {
+        let is_hello = matches!(msg, DeviceMessage::Hello(_));
+
+        let (serial_number, code, description) = match (is_hello, &self.id) {
+            (true, None) => match &self.certificate {
+                Some(cn) if msg.serial_number() != cn => (
+                    cn.clone(),
+                    ErrorCode::Forbidden,
+                    "Serial number does not match client certificate",
+                ),
+                _ => return true,
+            },
+            // Controller is not authenticated yet, violation is recorded
+            // for serial number of client certificate or the claimed one
+            (false, None) => (
+                self.certificate
+                    .clone()
+                    .unwrap_or_else(|| msg.serial_number().to_string()),
+                ErrorCode::Unauthorized,
+                "Message is received before HELLO",
+            ),
+            (true, Some(id)) => (id.clone(), ErrorCode::Forbidden, "HELLO is received twice"),
+            (false, Some(id)) if msg.serial_number() == id => return true,
+            (false, Some(id)) => (
+                id.clone(),
+                ErrorCode::Forbidden,
+                "Serial number does not match session",
+            ),
+        };
+
+        log::error!("{} message is refused: {}", msg.message_type(), description);
+
+        self.reject_message(
+            Some(msg.message_type().to_string()),
+            code,
+            Some(description.to_string()),
+            ctx,
+        );
+
+        self.addr.do_send(message::Violation {
+            serial_number,
+            description: description.to_string(),
+            message_type: msg.message_type().to_string(),
+            claimed_serial_number: Some(msg.serial_number().to_string()),
+        });
+
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Policy,
+            description: Some(description.to_string()),
+        }));
+
+        ctx.stop();
+
+        false
+    }
+
+    /// Passes message of authenticated controller to server
+    fn process_message(&mut self, msg: DeviceMessage, ctx: &mut ws::WebsocketContext<Self>) {
+        let message_type = msg.message_type();
+
+        match msg {
+            DeviceMessage::Hello(hello) => {
+                let addr = ctx.address();
+                let serial_number = hello.serial_number.clone();
+
+                self.addr
+                    .send(message::Connect {
+                        connection_id: self.connection_id,
+                        addr: addr.clone().recipient(),
+                        close: addr.recipient(),
+                        serial_number: hello.serial_number,
+                        version: hello.version,
+                        nonce: hello.nonce,
+                        proof: hello.proof,
+                        barrier_model: hello.barrier_model,
+                        protocol_version: hello.protocol_version,
+                        capabilities: hello.capabilities,
+                    })
+                    .into_actor(self)
+                    .then(|res, act, ctx| {
+                        match res {
+                            Ok(res) => match res {
+                                Ok(protocol_version) => {
+                                    act.id = Some(serial_number);
+                                    act.welcome(protocol_version, ctx);
+                                }
+                                Err(_) => ctx.stop(),
+                            },
+                            Err(e) => {
+                                log::error!("Error on server connect: {}", e);
+                                ctx.stop()
+                            }
+                        }
+                        fut::ready(())
+                    })
+                    .wait(ctx);
+            }
+
+            DeviceMessage::Set(set) => self.forward(
+                message_type,
+                message::Set {
+                    serial_number: set.serial_number,
+                    id: set.id,
+                },
+                ctx,
+            ),
+
+            DeviceMessage::Error(error) => self.forward(
+                message_type,
+                message::Error {
+                    serial_number: error.serial_number,
+                    id: error.id,
+                    code: error.code,
+                    description: error.description,
+                    details: error.details,
+                    tags: error.tags.map(|tags| {
+                        tags.into_iter()
+                            .map(|t| message::TagResult {
+                                tag: t.tag,
+                                code: t.code,
+                            })
+                            .collect()
+                    }),
+                },
+                ctx,
+            ),
+
+            DeviceMessage::Info(info) => self.forward(
+                message_type,
+                message::Info {
+                    serial_number: info.serial_number,
+                    id: info.id,
+                    version: info.version,
+                    uptime: info.uptime as i64,
+                    barrier_model: info.settings.barrier_model,
+                    barrier_algorithm: info.settings.barrier_algorithm,
+                },
+                ctx,
+            ),
+
+            DeviceMessage::Open(ack) | DeviceMessage::Close(ack) => self.forward(
+                message_type,
+                message::Ack {
+                    serial_number: ack.serial_number,
+                    id: ack.id,
+                    command: message_type.to_string(),
+                },
+                ctx,
+            ),
+
+            DeviceMessage::AddTags(ack)
+            | DeviceMessage::RemoveTags(ack)
+            | DeviceMessage::Update(ack) => self.forward(
+                message_type,
+                message::Ack {
+                    serial_number: ack.serial_number,
+                    id: ack.id,
+                    command: message_type.to_string(),
+                },
+                ctx,
+            ),
+
+            DeviceMessage::Progress(progress) => self.forward(
+                message_type,
+                message::Progress {
+                    serial_number: progress.serial_number,
+                    id: progress.id,
+                    stage: progress.stage,
+                    percent: progress.percent,
+                },
+                ctx,
+            ),
+
+            DeviceMessage::Event(event) => self.forward(
+                message_type,
+                message::Event {
+                    serial_number: event.serial_number,
+                    event_type: event.event_type,
+                    description: event.description,
+                    data: event.data,
+                    time: event.time,
+                },
+                ctx,
+            ),
+
+            DeviceMessage::Telemetry(telemetry) => self.forward(
+                message_type,
+                message::Telemetry {
+                    serial_number: telemetry.serial_number,
+                    signal_strength: telemetry.signal_strength,
+                    temperature: telemetry.temperature,
+                    voltage: telemetry.voltage,
+                    uptime: telemetry.uptime as i64,
+                },
+                ctx,
+            ),
+        }
+    }
+
+    /// Replies to accepted HELLO with session settings
+    fn welcome(&self, protocol_version: u32, ctx: &mut ws::WebsocketContext<Self>) {
+        let welcome = ServiceMessage::Welcome(WelcomeData {
+            protocol_version,
+            server_time: chrono::Utc::now().timestamp(),
+            heartbeat_interval: HEARTBEAT_INTERVAL.as_secs(),
+            client_timeout: CLIENT_TIMEOUT.as_secs(),
+            telemetry_interval: *TELEMETRY_INTERVAL,
+        });
+
+        if let Err(e) = self.send(&welcome, ctx) {
+            log::error!("Failed to serialize welcome message: {}", e);
+        }
+    }
+
+    /// Sends message to server and logs error returned by its handler
+    fn forward<M>(&self, message_type: &'static str, msg: M, ctx: &mut ws::WebsocketContext<Self>)
+    where
+        M: Message<Result = Result<(), String>> + Send + 'static,
+        OpenerServer: Handler<M>,
+    {
+        self.addr
+            .send(msg)
+            .into_actor(self)
+            .then(move |res, _act, _ctx| {
+                match res {
+                    Ok(Err(e)) => log::error!("Error on server {} handler: {}", message_type, e),
+                    Ok(Ok(())) => {}
+                    Err(e) => {
+                        log::info!("Failed to send {} message to server: {}", message_type, e)
+                    }
+                }
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
     fn hb(&self, ctx: &mut ws::WebsocketContext<Self>) {
-        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
-            if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
+        ctx.run_interval(*HEARTBEAT_INTERVAL, |act, ctx| {
+            if Instant::now().duration_since(act.hb) > *CLIENT_TIMEOUT {
                 println!("WebSocket Client heartbeat failed, disconnecting!");
 
                 if let Some(id) = &act.id {
-                    act.addr
-                        .do_send(super::server::message::Disconnect { id: id.clone() });
+                    act.addr.do_send(message::Disconnect {
+                        id: id.clone(),
+                        connection_id: act.connection_id,
+                    });
                 }
 
                 ctx.stop();
@@ -212,18 +532,27 @@ impl WsOpenerSession {
     }
 }
 
-impl Handler<super::server::command::SetCommand> for WsOpenerSession {
+impl Handler<super::server::command::Command> for WsOpenerSession {
     type Result = ();
 
-    fn handle(&mut self, cmd: super::server::command::SetCommand, ctx: &mut Self::Context) {
-        let msg = match serde_json::to_string(&cmd) {
-            Err(e) => {
-                log::error!("Failed to serialize set command: {}", e);
-                return;
-            }
-            Ok(msg) => msg,
-        };
+    fn handle(&mut self, cmd: super::server::command::Command, ctx: &mut Self::Context) {
+        if let Err(e) = self.send(&cmd.0, ctx) {
+            log::error!("Failed to serialize {} command: {}", cmd.name(), e);
+        }
+    }
+}
+
+impl Handler<message::Close> for WsOpenerSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: message::Close, ctx: &mut Self::Context) {
+        log::info!("Close session: {}", msg.reason);
+
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Policy,
+            description: Some(msg.reason),
+        }));
 
-        ctx.text(msg);
+        ctx.stop();
     }
 }
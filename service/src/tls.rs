@@ -0,0 +1,135 @@
+//! TLS termination of service, controllers may be required to present client certificates
+
+use actix_tls::accept::rustls::TlsStream;
+use actix_web::dev::Extensions;
+use actix_web::rt::net::TcpStream;
+use anyhow::{anyhow, Context, Result};
+use rustls::server::AllowAnyAnonymousOrAuthenticatedClient;
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use std::any::Any;
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+
+/// Paths to PEM files of TLS settings
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    /// Certificate chain of service
+    pub cert: String,
+    /// Private key of service in PKCS#8 or RSA format
+    pub key: String,
+    /// CA which issues certificates of controllers, `/ws` requires client certificate if it is set
+    pub client_ca: Option<String>,
+}
+
+impl TlsConfig {
+    /// Reads `TLS_CERT`, `TLS_KEY` and `TLS_CLIENT_CA` with prefix of listener,
+    /// TLS is disabled if certificate is not set
+    pub fn from_env(prefix: &str) -> Option<Self> {
+        let var = |name: &str| env::var(format!("{}{}", prefix, name));
+
+        let cert = var("TLS_CERT").ok()?;
+
+        Some(TlsConfig {
+            cert,
+            key: var("TLS_KEY")
+                .unwrap_or_else(|_| panic!("You must set the {}TLS_KEY environment var!", prefix)),
+            client_ca: var("TLS_CLIENT_CA").ok(),
+        })
+    }
+
+    /// Builds rustls settings, client certificate is verified by CA if it is presented.
+    /// Connections without it are allowed at TLS level and rejected by `/ws`
+    pub fn server_config(&self) -> Result<ServerConfig> {
+        let certs = load_certs(&self.cert)?;
+        let key = load_key(&self.key)?;
+
+        let builder = ServerConfig::builder().with_safe_defaults();
+
+        let config = match &self.client_ca {
+            Some(ca) => {
+                let mut roots = RootCertStore::empty();
+                for cert in load_certs(ca)? {
+                    roots
+                        .add(&cert)
+                        .with_context(|| format!("Invalid CA certificate in {}", ca))?;
+                }
+
+                builder
+                    .with_client_cert_verifier(AllowAnyAnonymousOrAuthenticatedClient::new(roots))
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        Ok(config.with_single_cert(certs, key)?)
+    }
+}
+
+/// Identity of controller proven by client certificate, it is stored in data of connection
+/// if client certificates are required
+#[derive(Clone, Debug)]
+pub struct ClientIdentity {
+    /// Common name of verified certificate, it is absent if controller has not presented it
+    pub common_name: Option<String>,
+}
+
+/// Returns callback for `HttpServer::on_connect` which stores identity of TLS connection
+pub fn on_connect(client_auth: bool) -> impl Fn(&dyn Any, &mut Extensions) + Send + Sync {
+    move |conn, data| {
+        if !client_auth {
+            return;
+        }
+
+        let common_name = conn
+            .downcast_ref::<TlsStream<TcpStream>>()
+            .and_then(|stream| stream.get_ref().1.peer_certificates())
+            .and_then(|certs| certs.first())
+            .and_then(|cert| common_name(&cert.0));
+
+        data.insert(ClientIdentity { common_name });
+    }
+}
+
+/// Extracts common name from subject of DER certificate
+pub fn common_name(der: &[u8]) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+
+    // Name borrows parsed certificate, so it is copied before certificate is dropped
+    let name = cert
+        .subject()
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+        .map(|cn| cn.to_string());
+
+    name
+}
+
+fn load_certs(path: &str) -> Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(
+        File::open(path).with_context(|| format!("Can't open certificate file {}", path))?,
+    );
+
+    let certs = rustls_pemfile::certs(&mut reader)?;
+
+    if certs.is_empty() {
+        return Err(anyhow!("No certificates in {}", path));
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn load_key(path: &str) -> Result<PrivateKey> {
+    let mut reader =
+        BufReader::new(File::open(path).with_context(|| format!("Can't open key file {}", path))?);
+
+    loop {
+        match rustls_pemfile::read_one(&mut reader)? {
+            Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => return Ok(PrivateKey(key)),
+            Some(_) => continue,
+            None => return Err(anyhow!("No private key in {}", path)),
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use acs_protocol::Encoding;
+
+/// Bytes of websocket messages exchanged with controllers by encoding
+#[derive(Default)]
+pub struct Traffic {
+    received: [AtomicU64; 3],
+    sent: [AtomicU64; 3],
+}
+
+impl Traffic {
+    pub fn add_received(&self, encoding: Encoding, bytes: usize) {
+        self.received[Traffic::index(encoding)].fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_sent(&self, encoding: Encoding, bytes: usize) {
+        self.sent[Traffic::index(encoding)].fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Returns one line per encoding
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+
+        for encoding in Encoding::ALL {
+            let i = Traffic::index(encoding);
+
+            let _ = writeln!(
+                report,
+                "{}: received {} bytes, sent {} bytes",
+                encoding.name(),
+                self.received[i].load(Ordering::Relaxed),
+                self.sent[i].load(Ordering::Relaxed)
+            );
+        }
+
+        report
+    }
+
+    fn index(encoding: Encoding) -> usize {
+        match encoding {
+            Encoding::Json => 0,
+            Encoding::Cbor => 1,
+            Encoding::MessagePack => 2,
+        }
+    }
+}
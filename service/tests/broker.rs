@@ -0,0 +1,67 @@
+//! Every test has its own broker, so subscribers of one test do not see events of another
+
+use futures::{FutureExt, StreamExt};
+
+use acs_service::broker::OverflowPolicy;
+use acs_service::{Broker, BrokerConfig};
+
+fn broker(overflow: OverflowPolicy) -> Broker {
+    Broker::new(BrokerConfig {
+        capacity: 2,
+        overflow,
+    })
+}
+
+#[tokio::test]
+async fn events_are_delivered_by_type() {
+    let broker = broker(OverflowPolicy::DropOldest);
+
+    let mut numbers = Box::pin(broker.subscribe::<u32>());
+    let mut names = Box::pin(broker.subscribe::<String>());
+
+    broker.publish(1_u32);
+    broker.publish("a".to_string());
+
+    assert_eq!(numbers.next().await, Some(1));
+    assert_eq!(names.next().await.as_deref(), Some("a"));
+    assert_eq!(numbers.next().now_or_never(), None);
+}
+
+#[tokio::test]
+async fn oldest_event_is_dropped_on_overflow() {
+    let broker = broker(OverflowPolicy::DropOldest);
+
+    let mut events = Box::pin(broker.subscribe::<u32>());
+
+    for i in 1..=3_u32 {
+        broker.publish(i);
+    }
+
+    assert_eq!(events.next().await, Some(2));
+    assert_eq!(events.next().await, Some(3));
+    assert!(broker.report().contains("dropped: 1"));
+}
+
+#[tokio::test]
+async fn slow_subscriber_is_disconnected_on_overflow() {
+    let broker = broker(OverflowPolicy::Disconnect);
+
+    let mut slow = Box::pin(broker.subscribe::<u32>());
+    let mut fast = Box::pin(broker.subscribe::<u32>());
+
+    for i in 1..=3_u32 {
+        broker.publish(i);
+
+        assert_eq!(fast.next().await, Some(i));
+    }
+
+    // Queued events are read before subscription is finished
+    assert_eq!(slow.next().await, Some(1));
+    assert_eq!(slow.next().await, Some(2));
+    assert_eq!(slow.next().await, None);
+
+    assert!(broker.report().contains("disconnected: 1"));
+
+    drop(slow);
+    assert!(broker.report().contains("subscribers: 1"));
+}
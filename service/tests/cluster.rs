@@ -0,0 +1,252 @@
+//! Two service instances sharing bus: sessions registered by one of them are
+//! visible to another, commands are routed to owner and events reach all instances
+
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::options::ClientOptions;
+
+use acs_service::cluster::{ClusterBus, ClusterMessage, MemoryBus, MongoBus};
+use acs_service::server::message::InfoCommand;
+
+mod common;
+
+use common::{create_opener, start_instance, wait_statuses, Controller};
+
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn receive(messages: &mut BoxStream<'static, ClusterMessage>) -> Option<ClusterMessage> {
+    tokio::time::timeout(RECEIVE_TIMEOUT, messages.next())
+        .await
+        .ok()
+        .flatten()
+}
+
+async fn nothing_received(messages: &mut BoxStream<'static, ClusterMessage>) -> bool {
+    tokio::time::timeout(Duration::from_secs(1), messages.next())
+        .await
+        .is_err()
+}
+
+fn dispatch(serial_number: &str) -> ClusterMessage {
+    ClusterMessage::Dispatch {
+        serial_number: serial_number.to_string(),
+    }
+}
+
+fn dispatched(message: Option<ClusterMessage>) -> Option<String> {
+    match message {
+        Some(ClusterMessage::Dispatch { serial_number }) => Some(serial_number),
+        _ => None,
+    }
+}
+
+async fn check_session_registry(a: &dyn ClusterBus, b: &dyn ClusterBus) {
+    a.register_session("SN-1").await.unwrap();
+
+    assert_eq!(b.session_owner("SN-1").await.unwrap().as_deref(), Some("a"));
+
+    // Only owner removes session
+    b.unregister_session("SN-1").await.unwrap();
+    assert_eq!(b.session_owner("SN-1").await.unwrap().as_deref(), Some("a"));
+
+    // Opener reconnected to another instance
+    b.register_session("SN-1").await.unwrap();
+    assert_eq!(a.session_owner("SN-1").await.unwrap().as_deref(), Some("b"));
+
+    a.unregister_session("SN-1").await.unwrap();
+    assert_eq!(a.session_owner("SN-1").await.unwrap().as_deref(), Some("b"));
+
+    b.unregister_session("SN-1").await.unwrap();
+    assert_eq!(a.session_owner("SN-1").await.unwrap(), None);
+}
+
+async fn check_routing(a: &dyn ClusterBus, b: &dyn ClusterBus) {
+    let mut a_messages = a.subscribe();
+    let mut b_messages = b.subscribe();
+
+    b.register_session("SN-2").await.unwrap();
+
+    let owner = a.session_owner("SN-2").await.unwrap().unwrap();
+    a.send(&owner, dispatch("SN-2")).await.unwrap();
+
+    assert_eq!(
+        dispatched(receive(&mut b_messages).await).as_deref(),
+        Some("SN-2")
+    );
+    assert!(nothing_received(&mut a_messages).await);
+
+    b.broadcast(dispatch("SN-3")).await.unwrap();
+
+    assert_eq!(
+        dispatched(receive(&mut a_messages).await).as_deref(),
+        Some("SN-3")
+    );
+    assert!(nothing_received(&mut b_messages).await);
+
+    b.unregister_session("SN-2").await.unwrap();
+}
+
+#[tokio::test]
+async fn memory_bus_session_registry() {
+    let a = MemoryBus::new("a".to_string());
+    let b = a.join("b".to_string());
+
+    check_session_registry(&a, &b).await;
+}
+
+#[tokio::test]
+async fn memory_bus_routing() {
+    let a = MemoryBus::new("a".to_string());
+    let b = a.join("b".to_string());
+
+    check_routing(&a, &b).await;
+}
+
+/// Instances share database given by `DATABASE_URL`, tests using it are ignored
+/// unless run with `--ignored`
+async fn mongo_buses(test: &str) -> (MongoBus, MongoBus, mongodb::Database) {
+    let uri = env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+
+    let options = ClientOptions::parse(&uri).await.unwrap();
+    let client = mongodb::Client::with_options(options).unwrap();
+    let db = client.database(&format!("cluster_test_{}_{}", test, std::process::id()));
+
+    (
+        MongoBus::new(db.clone(), "a".to_string()),
+        MongoBus::new(db.clone(), "b".to_string()),
+        db,
+    )
+}
+
+#[tokio::test]
+#[ignore = "needs DATABASE_URL"]
+async fn mongo_bus_session_registry() {
+    let (a, b, db) = mongo_buses("registry").await;
+
+    check_session_registry(&a, &b).await;
+
+    db.drop(None).await.unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs DATABASE_URL"]
+async fn mongo_bus_routing() {
+    let (a, b, db) = mongo_buses("routing").await;
+
+    check_routing(&a, &b).await;
+
+    db.drop(None).await.unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs DATABASE_URL"]
+async fn mongo_bus_stale_session() {
+    let (a, b, db) = mongo_buses("stale").await;
+
+    a.register_session("SN-4").await.unwrap();
+
+    // Instance a stopped without unregistering session
+    let stale = DateTime::from_millis(DateTime::now().timestamp_millis() - 10 * 60 * 1000);
+    db.collection::<Document>("clusterSessions")
+        .update_one(
+            doc! { "_id": "SN-4" },
+            doc! { "$set": { "updatedAt": stale } },
+            None,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(b.session_owner("SN-4").await.unwrap(), None);
+
+    // Heartbeat of live owner makes session visible again
+    a.refresh_sessions(&["SN-4".to_string()]).await.unwrap();
+    assert_eq!(b.session_owner("SN-4").await.unwrap().as_deref(), Some("a"));
+
+    // Session removed by TTL index is registered again, session of other instance is kept
+    a.unregister_session("SN-4").await.unwrap();
+    b.register_session("SN-5").await.unwrap();
+
+    a.refresh_sessions(&["SN-4".to_string(), "SN-5".to_string()])
+        .await
+        .unwrap();
+
+    assert_eq!(b.session_owner("SN-4").await.unwrap().as_deref(), Some("a"));
+    assert_eq!(a.session_owner("SN-5").await.unwrap().as_deref(), Some("b"));
+
+    db.drop(None).await.unwrap();
+}
+
+fn info_command(serial_number: &str) -> InfoCommand {
+    InfoCommand {
+        user_id: String::new(),
+        serial_number: serial_number.to_string(),
+    }
+}
+
+/// Controller is connected to instance a, commands are queued on instance b
+async fn connected_to_owner(
+    test: &str,
+    serial_number: &str,
+) -> (
+    actix::Addr<acs_service::server::OpenerServer>,
+    Controller,
+    mongodb::Database,
+) {
+    let db = common::test_db(test).await;
+
+    // Controller of tests does not reply to some commands, they fail after timeout
+    env::set_var("COMMAND_TIMEOUT", "1");
+
+    create_opener(&db, serial_number).await;
+
+    let a = MemoryBus::new("a".to_string());
+    let b = a.join("b".to_string());
+
+    let (_, port) = start_instance(&db, Arc::new(a));
+    let (server_b, _) = start_instance(&db, Arc::new(b));
+
+    let controller = Controller::connect(port, serial_number).await.unwrap();
+
+    (server_b, controller, db)
+}
+
+#[actix_web::test]
+#[ignore = "needs DATABASE_URL"]
+async fn command_queued_on_other_instance_is_delivered_by_owner() {
+    let (server_b, mut controller, db) = connected_to_owner("delivery", "SN-10").await;
+
+    server_b.send(info_command("SN-10")).await.unwrap();
+
+    let command = controller.next_command().await.unwrap();
+    controller.reply_info(&command).await;
+
+    assert!(wait_statuses(&db, "SN-10", &["SUCCESS"]).await);
+
+    db.drop(None).await.unwrap();
+}
+
+#[actix_web::test]
+#[ignore = "needs DATABASE_URL"]
+async fn timed_out_command_is_failed_by_owner() {
+    let (server_b, mut controller, db) = connected_to_owner("timeout", "SN-11").await;
+
+    server_b.send(info_command("SN-11")).await.unwrap();
+    server_b.send(info_command("SN-11")).await.unwrap();
+
+    // Both watchdogs see the first command timed out, only owner fails it
+    // and sends the next one instead of waiting for reply forever
+    let first = controller.next_command().await.unwrap();
+    let second = controller.next_command().await.unwrap();
+    assert_ne!(first.id, second.id);
+
+    controller.reply_info(&second).await;
+
+    assert!(wait_statuses(&db, "SN-11", &["FAILED", "SUCCESS"]).await);
+
+    db.drop(None).await.unwrap();
+}
@@ -0,0 +1,220 @@
+//! Service instances with device listener and simulated controllers for tests,
+//! instances use database given by `DATABASE_URL`
+#![allow(dead_code)]
+
+use std::env;
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix::{Actor, Addr};
+use actix_web::{web, App, HttpServer};
+use futures::{SinkExt, StreamExt};
+use mongodb::bson::{doc, Document};
+use mongodb::options::{ClientOptions, FindOptions};
+use mongodb::Database;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use acs_protocol::{
+    hello_proof, Command, CommandBody, DeviceMessage, HelloData, InfoData, ServiceMessage,
+    Settings, PROTOCOL_VERSION,
+};
+use acs_service::cluster::{Cluster, ClusterBus};
+use acs_service::server::OpenerServer;
+use acs_service::{ws_route, Broker, BrokerConfig, Traffic};
+
+pub const LOGIN: &str = "admin";
+pub const PASSWORD: &str = "password";
+pub const SECRET: &str = "secret";
+
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Returns empty database for test, tests using it are ignored unless run
+/// with `--ignored` and `DATABASE_URL`
+pub async fn test_db(test: &str) -> Database {
+    let uri = env::var("DATABASE_URL").expect("DATABASE_URL is not set");
+
+    let options = ClientOptions::parse(&uri).await.unwrap();
+    let client = mongodb::Client::with_options(options).unwrap();
+
+    client.database(&format!("service_test_{}_{}", test, std::process::id()))
+}
+
+/// Creates opener with issued secret as `createOpener` does
+pub async fn create_opener(db: &Database, serial_number: &str) {
+    let opener = doc! {
+        "serialNumber": serial_number,
+        "login": LOGIN,
+        "password": PASSWORD,
+        "secret": SECRET,
+        "connected": false,
+        "commandStatus": "",
+        "createdAt": mongodb::bson::DateTime::now(),
+    };
+
+    db.collection::<Document>("openers")
+        .insert_one(opener, None)
+        .await
+        .unwrap();
+}
+
+pub async fn get_opener(db: &Database, serial_number: &str) -> Document {
+    db.collection::<Document>("openers")
+        .find_one(doc! { "serialNumber": serial_number }, None)
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+/// Returns statuses of commands of opener in order they are queued
+pub async fn command_statuses(db: &Database, serial_number: &str) -> Vec<String> {
+    let mut cursor = db
+        .collection::<Document>("commands")
+        .find(
+            doc! { "serialNumber": serial_number },
+            FindOptions::builder().sort(doc! { "_id": 1 }).build(),
+        )
+        .await
+        .unwrap();
+
+    let mut statuses = Vec::new();
+    while let Some(command) = cursor.next().await {
+        statuses.push(command.unwrap().get_str("status").unwrap().to_string());
+    }
+
+    statuses
+}
+
+/// Waits until commands of opener have expected statuses
+pub async fn wait_statuses(db: &Database, serial_number: &str, expected: &[&str]) -> bool {
+    for _ in 0..50 {
+        if command_statuses(db, serial_number).await == expected {
+            return true;
+        }
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+
+    false
+}
+
+/// Starts server actor of instance with device listener, returns its address and port
+pub fn start_instance(db: &Database, bus: Arc<dyn ClusterBus>) -> (Addr<OpenerServer>, u16) {
+    let cluster = Cluster::new(bus, Broker::new(BrokerConfig::default()));
+    let server = OpenerServer::new(Arc::new(AtomicUsize::new(0)), db.clone(), cluster).start();
+    let traffic = Arc::new(Traffic::default());
+
+    let addr = server.clone();
+
+    let listener = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(traffic.clone()))
+            .app_data(web::Data::new(addr.clone()))
+            .service(web::resource("/ws").to(ws_route))
+    })
+    .workers(1)
+    .bind("127.0.0.1:0")
+    .unwrap();
+
+    let port = listener.addrs()[0].port();
+
+    actix_web::rt::spawn(listener.run());
+
+    (server, port)
+}
+
+/// Controller speaking JSON, it replies only to commands test asks for
+pub struct Controller {
+    pub serial_number: String,
+    /// Nonce sent in HELLO
+    pub nonce: String,
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl Controller {
+    /// Connects and sends HELLO, returns `None` if service does not send WELCOME
+    pub async fn connect(port: u16, serial_number: &str) -> Option<Controller> {
+        let url = format!("ws://127.0.0.1:{}/ws", port);
+        let (ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+        let nonce = hex::encode(rand::random::<[u8; 16]>());
+
+        let mut controller = Controller {
+            serial_number: serial_number.to_string(),
+            nonce: nonce.clone(),
+            ws,
+        };
+
+        controller
+            .send(DeviceMessage::Hello(HelloData {
+                serial_number: serial_number.to_string(),
+                version: "1.0.0".to_string(),
+                proof: Some(hello_proof(SECRET, serial_number, &nonce)),
+                nonce,
+                barrier_model: String::new(),
+                protocol_version: Some(PROTOCOL_VERSION),
+                capabilities: Some(CommandBody::NAMES.iter().map(|c| c.to_string()).collect()),
+            }))
+            .await;
+
+        match controller.receive().await? {
+            Message::Text(text) => match serde_json::from_str(&text) {
+                Ok(ServiceMessage::Welcome(_)) => Some(controller),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    async fn send(&mut self, message: DeviceMessage) {
+        let text = serde_json::to_string(&message).unwrap();
+        self.ws.send(Message::Text(text)).await.unwrap();
+    }
+
+    /// Returns the next data frame, `None` if connection is closed or nothing is received
+    async fn receive(&mut self) -> Option<Message> {
+        loop {
+            let message = tokio::time::timeout(RECEIVE_TIMEOUT, self.ws.next())
+                .await
+                .ok()??
+                .ok()?;
+
+            match message {
+                Message::Text(_) | Message::Binary(_) => return Some(message),
+                Message::Close(_) => return None,
+                _ => continue,
+            }
+        }
+    }
+
+    /// Waits for command signed for this session
+    pub async fn next_command(&mut self) -> Option<Command> {
+        let command: Command = match self.receive().await? {
+            Message::Text(text) => serde_json::from_str(&text).ok()?,
+            _ => return None,
+        };
+
+        assert!(command.verify(LOGIN, PASSWORD, &self.nonce));
+
+        Some(command)
+    }
+
+    /// Replies to INFO command
+    pub async fn reply_info(&mut self, command: &Command) {
+        assert!(matches!(command.body, CommandBody::Info(_)));
+
+        self.send(DeviceMessage::Info(InfoData {
+            id: Some(command.id.clone()),
+            serial_number: self.serial_number.clone(),
+            version: "1.0.0".to_string(),
+            uptime: 1,
+            settings: Settings {
+                barrier_model: String::new(),
+                barrier_algorithm: String::new(),
+            },
+        }))
+        .await;
+    }
+}
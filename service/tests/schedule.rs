@@ -0,0 +1,78 @@
+//! Runs of schedules are calculated in time zone of schedule
+
+use chrono::{TimeZone, Utc};
+
+use acs_service::scheduler::{parse_time_zone, ScheduleRule};
+
+#[test]
+fn weekly_rule_runs_on_given_days_in_time_zone() {
+    let tz = parse_time_zone("Europe/Moscow").unwrap();
+
+    // Monday and Friday at 08:00 Moscow time (UTC+3)
+    let rule = ScheduleRule::weekly(&[5, 1], "08:00").unwrap();
+
+    // Wednesday
+    let after = Utc.with_ymd_and_hms(2024, 5, 15, 12, 0, 0).unwrap();
+
+    let next = rule.next_after(after, tz).unwrap();
+    assert_eq!(next, Utc.with_ymd_and_hms(2024, 5, 17, 5, 0, 0).unwrap());
+
+    let next = rule.next_after(next, tz).unwrap();
+    assert_eq!(next, Utc.with_ymd_and_hms(2024, 5, 20, 5, 0, 0).unwrap());
+}
+
+#[test]
+fn weekly_rule_follows_daylight_saving_time() {
+    let tz = parse_time_zone("Europe/Berlin").unwrap();
+
+    let rule = ScheduleRule::weekly(&[1, 2, 3, 4, 5, 6, 7], "02:30").unwrap();
+
+    // 02:30 does not exist on 31 March 2024, run is moved to 03:30 CEST
+    let after = Utc.with_ymd_and_hms(2024, 3, 30, 12, 0, 0).unwrap();
+    let next = rule.next_after(after, tz).unwrap();
+    assert_eq!(next, Utc.with_ymd_and_hms(2024, 3, 31, 1, 30, 0).unwrap());
+
+    // The next day is in summer time
+    let next = rule.next_after(next, tz).unwrap();
+    assert_eq!(next, Utc.with_ymd_and_hms(2024, 4, 1, 0, 30, 0).unwrap());
+}
+
+#[test]
+fn cron_rule_runs_in_time_zone() {
+    let tz = parse_time_zone("Asia/Yekaterinburg").unwrap();
+
+    // Working days at 20:00 local time (UTC+5)
+    let rule = ScheduleRule::cron("0 0 20 * * Mon-Fri").unwrap();
+
+    // Friday evening after the run
+    let after = Utc.with_ymd_and_hms(2024, 5, 17, 16, 0, 0).unwrap();
+
+    let next = rule.next_after(after, tz).unwrap();
+    assert_eq!(next, Utc.with_ymd_and_hms(2024, 5, 20, 15, 0, 0).unwrap());
+}
+
+#[test]
+fn missed_runs_are_counted() {
+    let tz = parse_time_zone("UTC").unwrap();
+
+    let rule = ScheduleRule::cron("0 0 * * * *").unwrap();
+
+    let from = Utc.with_ymd_and_hms(2024, 5, 17, 10, 0, 0).unwrap();
+    let to = Utc.with_ymd_and_hms(2024, 5, 17, 13, 30, 0).unwrap();
+
+    assert_eq!(rule.count_between(from, to, tz), 3);
+    assert_eq!(
+        rule.last_between(from, to, tz),
+        Some(Utc.with_ymd_and_hms(2024, 5, 17, 13, 0, 0).unwrap())
+    );
+    assert_eq!(rule.last_between(to, to, tz), None);
+}
+
+#[test]
+fn invalid_rules_are_refused() {
+    assert!(ScheduleRule::weekly(&[], "08:00").is_err());
+    assert!(ScheduleRule::weekly(&[0], "08:00").is_err());
+    assert!(ScheduleRule::weekly(&[1], "25:00").is_err());
+    assert!(ScheduleRule::cron("every day").is_err());
+    assert!(parse_time_zone("Mars/Olympus").is_err());
+}
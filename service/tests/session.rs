@@ -0,0 +1,79 @@
+//! Sessions of controllers: duplicate connection is refused without breaking the first one,
+//! openers without secret are accepted during transition period
+
+use std::env;
+use std::sync::Arc;
+
+use acs_service::cluster::MemoryBus;
+use acs_service::server::message::InfoCommand;
+use mongodb::bson::{doc, Document};
+
+mod common;
+
+use common::{create_opener, get_opener, start_instance, wait_statuses, Controller};
+
+#[actix_web::test]
+#[ignore = "needs DATABASE_URL"]
+async fn refused_duplicate_keeps_first_session() {
+    let db = common::test_db("duplicate").await;
+
+    env::set_var("DUPLICATE_CONNECTION_POLICY", "refuse");
+
+    create_opener(&db, "SN-DUP").await;
+
+    let (server, port) = start_instance(&db, Arc::new(MemoryBus::new("a".to_string())));
+
+    let mut first = Controller::connect(port, "SN-DUP").await.unwrap();
+
+    assert!(Controller::connect(port, "SN-DUP").await.is_none());
+
+    // Refused HELLO has not replaced state recorded for the first session
+    let opener = get_opener(&db, "SN-DUP").await;
+    assert_eq!(opener.get_str("nonce").unwrap(), first.nonce);
+    assert!(opener.get_bool("connected").unwrap());
+
+    server
+        .send(InfoCommand {
+            user_id: String::new(),
+            serial_number: "SN-DUP".to_string(),
+        })
+        .await
+        .unwrap();
+
+    let command = first.next_command().await.unwrap();
+    first.reply_info(&command).await;
+
+    assert!(wait_statuses(&db, "SN-DUP", &["SUCCESS"]).await);
+
+    db.drop(None).await.unwrap();
+}
+
+#[actix_web::test]
+#[ignore = "needs DATABASE_URL"]
+async fn legacy_opener_is_accepted_during_transition() {
+    let db = common::test_db("legacy").await;
+
+    env::set_var("LEGACY_AUTH_UNTIL", "2100-01-01T00:00:00Z");
+
+    create_opener(&db, "SN-LEGACY").await;
+
+    // Opener created before secrets were issued
+    db.collection::<Document>("openers")
+        .update_one(
+            doc! { "serialNumber": "SN-LEGACY" },
+            doc! { "$unset": { "secret": "" } },
+            None,
+        )
+        .await
+        .unwrap();
+
+    let (_, port) = start_instance(&db, Arc::new(MemoryBus::new("a".to_string())));
+
+    assert!(Controller::connect(port, "SN-LEGACY").await.is_some());
+    assert!(get_opener(&db, "SN-LEGACY")
+        .await
+        .get_bool("connected")
+        .unwrap());
+
+    db.drop(None).await.unwrap();
+}
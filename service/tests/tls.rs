@@ -0,0 +1,158 @@
+//! Controllers are authenticated by client certificates issued by CA of service
+
+use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa};
+use rustls::{ClientConfig, RootCertStore, ServerName};
+use std::convert::TryFrom;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+use acs_service::tls::common_name;
+use acs_service::TlsConfig;
+
+fn ca(name: &str) -> Certificate {
+    let mut params = CertificateParams::new(vec![]);
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.distinguished_name.push(DnType::CommonName, name);
+
+    Certificate::from_params(params).unwrap()
+}
+
+fn issue(ca: &Certificate, common_name: &str, names: Vec<String>) -> (String, String) {
+    let mut params = CertificateParams::new(names);
+    params
+        .distinguished_name
+        .push(DnType::CommonName, common_name);
+
+    let cert = Certificate::from_params(params).unwrap();
+
+    (
+        cert.serialize_pem_with_signer(ca).unwrap(),
+        cert.serialize_private_key_pem(),
+    )
+}
+
+/// Writes PEM files of service to unique temporary directory
+fn service_files(name: &str, ca: &Certificate) -> TlsConfig {
+    let dir: PathBuf = std::env::temp_dir().join(format!("acs-tls-{}-{}", name, rand_suffix()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let (cert, key) = issue(ca, "service", vec!["localhost".to_string()]);
+
+    let path = |file: &str, content: &str| {
+        let path = dir.join(file);
+        fs::write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
+    };
+
+    TlsConfig {
+        cert: path("cert.pem", &cert),
+        key: path("key.pem", &key),
+        client_ca: Some(path("ca.pem", &ca.serialize_pem().unwrap())),
+    }
+}
+
+fn rand_suffix() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64
+}
+
+fn client_config(ca: &Certificate, client: Option<(String, String)>) -> ClientConfig {
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(&rustls::Certificate(ca.serialize_der().unwrap()))
+        .unwrap();
+
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots);
+
+    match client {
+        Some((cert, key)) => {
+            let cert = rustls_pemfile::certs(&mut cert.as_bytes()).unwrap();
+            let key = rustls_pemfile::pkcs8_private_keys(&mut key.as_bytes()).unwrap();
+
+            builder
+                .with_single_cert(
+                    cert.into_iter().map(rustls::Certificate).collect(),
+                    rustls::PrivateKey(key[0].clone()),
+                )
+                .unwrap()
+        }
+        None => builder.with_no_client_auth(),
+    }
+}
+
+/// Makes TLS handshake and returns common name of client certificate seen by service
+async fn handshake(
+    config: &TlsConfig,
+    client: ClientConfig,
+) -> Result<Option<String>, std::io::Error> {
+    let acceptor = TlsAcceptor::from(Arc::new(config.server_config().unwrap()));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let connector = TlsConnector::from(Arc::new(client));
+
+    let client = tokio::spawn(async move {
+        let stream = TcpStream::connect(addr).await?;
+        let domain = ServerName::try_from("localhost").unwrap();
+        connector.connect(domain, stream).await.map(|_| ())
+    });
+
+    let (stream, _) = listener.accept().await?;
+    let stream = acceptor.accept(stream).await?;
+
+    let name = stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .and_then(|certs| certs.first())
+        .and_then(|cert| common_name(&cert.0));
+
+    _ = client.await;
+
+    Ok(name)
+}
+
+#[tokio::test]
+async fn client_certificate_names_controller() {
+    let ca = ca("acs ca");
+    let config = service_files("named", &ca);
+
+    let client = issue(&ca, "SN0001", vec!["SN0001".to_string()]);
+
+    let name = handshake(&config, client_config(&ca, Some(client)))
+        .await
+        .unwrap();
+
+    assert_eq!(name.as_deref(), Some("SN0001"));
+}
+
+#[tokio::test]
+async fn client_without_certificate_is_anonymous() {
+    let ca = ca("acs ca");
+    let config = service_files("anonymous", &ca);
+
+    let name = handshake(&config, client_config(&ca, None)).await.unwrap();
+
+    assert_eq!(name, None);
+}
+
+#[tokio::test]
+async fn certificate_of_unknown_ca_is_refused() {
+    let ca = ca("acs ca");
+    let config = service_files("unknown", &ca);
+
+    let other = self::ca("other ca");
+    let client = issue(&other, "SN0001", vec!["SN0001".to_string()]);
+
+    assert!(handshake(&config, client_config(&ca, Some(client)))
+        .await
+        .is_err());
+}